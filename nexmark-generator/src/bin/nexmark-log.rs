@@ -0,0 +1,29 @@
+//! Writes generated NEXMark events to event logs, one partition per worker, for runs to replay.
+//!
+//! Usage: `nexmark-log --dir <dir> --events <count> --peers <workers> [--format binary|framed|json|both]
+//! [--<generator option> <value> ...]`. Replaying runs need the same number of workers and the
+//! same generator options, such as `--events-per-second`, for their input times to match the
+//! logged events. Partitions record the `--base-time`, which the timely suite's replays move to
+//! the base time of their run.
+
+use std::path::PathBuf;
+
+use nexmark_generator::config::{Config, NEXMarkConfig};
+use nexmark_generator::event_log::{write_log, Format};
+
+fn main() {
+    let config = Config::from(std::env::args().skip(1)).expect("pass options as --<option> <value> pairs");
+    let dir = PathBuf::from(config.get("dir").expect("missing --dir"));
+    let events: usize = config.get_as("events").expect("missing or invalid --events");
+    let peers: usize = config.get_as("peers").expect("missing or invalid --peers");
+    let formats = match config.get_or("format", "both").as_str() {
+        "both" => vec![Format::Binary, Format::Json],
+        format => vec![format.parse().unwrap()],
+    };
+
+    let nexmark_config = NEXMarkConfig::new(&config);
+    for format in formats {
+        write_log(&nexmark_config, events, peers, &dir, format).expect("failed to write event log");
+        println!("wrote {} events in {} {:?} partitions to {}", events, peers, format, dir.display());
+    }
+}
@@ -0,0 +1,44 @@
+//! Streams generated NEXMark events over TCP at their emission times, standing in for a broker.
+//!
+//! Usage: `nexmark-producer --listen <addr> --events <count> [--<generator option> <value> ...]`.
+//! Every worker of a run connects once and receives its share of the events, paced by generator
+//! options such as `--events-per-second`. The producer exits after serving all workers.
+
+use std::collections::HashSet;
+use std::net::TcpListener;
+
+use nexmark_generator::config::{Config, NEXMarkConfig};
+use nexmark_generator::tcp::{produce, read_handshake};
+
+fn main() {
+    let config = Config::from(std::env::args().skip(1)).expect("pass options as --<option> <value> pairs");
+    let listen = config.get_or("listen", "127.0.0.1:9092");
+    let events: usize = config.get_as("events").expect("missing or invalid --events");
+    let nexmark_config = NEXMarkConfig::new(&config);
+
+    let listener = TcpListener::bind(&listen).expect("failed to listen");
+    println!("listening on {}", listener.local_addr().unwrap());
+
+    let mut served = HashSet::new();
+    let mut producers = Vec::new();
+    while served.is_empty() || producers.len() < served_peers(&served) {
+        let (mut stream, peer) = listener.accept().expect("failed to accept worker");
+        let (index, peers) = read_handshake(&mut stream).expect("invalid handshake");
+        assert!(served.insert((index, peers)), "worker {} of {} connected twice", index, peers);
+        println!("serving worker {} of {} at {}", index, peers, peer);
+        let nexmark_config = nexmark_config.clone();
+        producers.push(std::thread::spawn(move || {
+            produce(stream, &nexmark_config, index, peers, events).expect("failed to send events");
+        }));
+    }
+    for producer in producers {
+        producer.join().unwrap();
+    }
+}
+
+/// The number of workers of the run, which all workers report the same.
+fn served_peers(served: &HashSet<(usize, usize)>) -> usize {
+    let peers: HashSet<_> = served.iter().map(|&(_, peers)| peers).collect();
+    assert_eq!(peers.len(), 1, "workers report different numbers of peers");
+    *peers.iter().next().unwrap()
+}
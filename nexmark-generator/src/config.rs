@@ -0,0 +1,519 @@
+use std::io::{Result, Error};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// This is a simple command line options parser.
+#[derive(Clone, Default)]
+pub struct Config {
+    args: HashMap<String, String>
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config{ args: HashMap::new() }
+    }
+
+    /// Parses the command line arguments into a new Config object.
+    ///
+    /// Its parsing strategy is as follows:
+    ///   If an argument starts with --, the remaining string is used as the key
+    ///   and the next argument as the associated value.
+    ///   Otherwise the argument is used as the next positional value, counting
+    ///   from zero.
+    ///
+    pub fn from<I: Iterator<Item=String>>(mut cmd_args: I) -> Result<Self> {
+        let mut args = HashMap::new();
+        let mut i = 0;
+        while let Some(arg) = cmd_args.next() {
+            if let Some(key) = arg.strip_prefix("--") {
+                match cmd_args.next() {
+                    Some(value) => args.insert(key.to_string(), value),
+                    None => return Err(Error::other("No corresponding value."))
+                };
+            } else {
+                args.insert(format!("{}", i), arg);
+                i += 1;
+            }
+        }
+        Ok(Config{ args })
+    }
+
+    /// Inserts the given value for the given key.
+    ///
+    /// If the key already exists, its value is overwritten.
+    #[allow(dead_code)]
+    pub fn insert(&mut self, key: &str, value: String) {
+        self.args.insert(String::from(key), value);
+    }
+
+    /// Returns the value for the given key, if available.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.args.get(key).cloned()
+    }
+
+    /// Returns the value for the given key automatically parsed if possible.
+    pub fn get_as<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.args.get(key).and_then(|x| x.parse::<T>().ok())
+    }
+
+    /// Returns the value for the given key or a default value if the key does not exist.
+    pub fn get_or(&self, key: &str, default: &str) -> String {
+        self.args.get(key).map_or(String::from(default), |x| x.clone())
+    }
+
+    /// Returns the value for the given key automatically parsed, or a default value if the key does not exist.
+    pub fn get_as_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        self.get_as(key).unwrap_or(default)
+    }
+}
+
+
+
+use std::cmp::max;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::utils::get_base_url;
+
+// type Id = usize;
+// type Date = usize;
+
+// const MIN_STRING_LENGTH: usize = 3;
+const BASE_TIME: usize = 0; //1436918400_000;
+
+fn split_string_arg(string: String) -> Vec<String> {
+    string.split(",").map(String::from).collect::<Vec<String>>()
+}
+
+// trait NEXMarkRng {
+//     fn gen_string(&mut self, usize) -> String;
+//     fn gen_price(&mut self) -> usize;
+// }
+
+// impl NEXMarkRng for StdRng {
+//     fn gen_string(&mut self, max: usize) -> String {
+//         let len = self.gen_range(MIN_STRING_LENGTH, max);
+//         String::from((0..len).map(|_|{
+//             if self.gen_range(0, 13) == 0 { String::from(" ") }
+//             else { from_u32('a' as u32+self.gen_range(0, 26)).unwrap().to_string() }
+//         }).collect::<Vec<String>>().join("").trim())
+//     }
+
+//     fn gen_price(&mut self) -> usize {
+//         (10.0_f32.powf(self.gen::<f32>() * 6.0) * 100.0).round() as usize
+//     }
+// }
+
+/// How the event rate changes over time.
+///
+/// Shapes divide a period of `rate-period` seconds (600 by default) into steps of constant rate,
+/// between the first rate, `first-event-rate` or `events-per-second`, and `next-event-rate`. All
+/// but ramp and step repeat their period. Selected with `rate-shape`, `sine` by default.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum RateShape {
+    /// Alternate between the first and the next rate.
+    Square,
+    /// Oscillate between the first and the next rate.
+    Sine,
+    /// Change linearly from the first to the next rate over one period, then keep the next rate.
+    Ramp,
+    /// Change from the first to the next rate after one period.
+    Step,
+    /// Keep the first rate, with bursts of the next rate in steps chosen at random.
+    Bursty,
+    /// Draw the number of events of each step from a Poisson distribution around the first rate.
+    Poisson,
+    /// Follow the rates listed in `rate-trace`, one per line and step.
+    Trace,
+}
+
+impl FromStr for RateShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(RateShape::Square),
+            "sine" => Ok(RateShape::Sine),
+            "ramp" => Ok(RateShape::Ramp),
+            "step" => Ok(RateShape::Step),
+            "bursty" => Ok(RateShape::Bursty),
+            "poisson" => Ok(RateShape::Poisson),
+            "trace" => Ok(RateShape::Trace),
+            _ => Err(format!("unknown rate shape: {}", s)),
+        }
+    }
+}
+
+/// Draw from a Poisson distribution with mean `lambda`, approximating large means with a normal
+/// distribution.
+fn poisson(lambda: f64, rng: &mut SmallRng) -> usize {
+    if lambda < 30.0 {
+        // Knuth's algorithm
+        let limit = (-lambda).exp();
+        let mut count = 0;
+        let mut product = rng.gen::<f64>();
+        while product > limit {
+            count += 1;
+            product *= rng.gen::<f64>();
+        }
+        count
+    } else {
+        // Box-Muller transform
+        let z = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt() * (2.0 * PI * rng.gen::<f64>()).cos();
+        (lambda + lambda.sqrt() * z).round().max(0.0) as usize
+    }
+}
+
+/// Read a rate trace: one rate in events per second per line, ignoring blank lines and lines
+/// starting with `#`.
+fn read_trace(path: &str) -> Vec<f64> {
+    let file = File::open(path).unwrap_or_else(|e| panic!("couldn't open rate trace {}: {}", path, e));
+    BufReader::new(file)
+        .lines()
+        .map(|line| line.expect("couldn't read rate trace"))
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|line| line.trim().parse().unwrap_or_else(|_| panic!("invalid rate in trace: {}", line)))
+        .collect()
+}
+
+#[derive(Clone)]
+pub struct NEXMarkConfig {
+    pub active_people: usize,
+    pub in_flight_auctions: usize,
+    /// Events are generated in a permuted order within groups of this size, `out-of-order-group-size`
+    pub out_of_order_group_size: usize,
+    /// Share of events that are late, `late-fraction`
+    pub late_fraction: f64,
+    /// Maximal lateness of late events, `max-lateness-ms`
+    pub max_lateness_ns: usize,
+    /// Maximal jitter of the event times of people, auctions and bids, `person-jitter-ms`,
+    /// `auction-jitter-ms` and `bid-jitter-ms`
+    pub person_jitter_ns: usize,
+    pub auction_jitter_ns: usize,
+    pub bid_jitter_ns: usize,
+    pub hot_seller_ratio: usize,
+    pub hot_auction_ratio: usize,
+    pub hot_bidder_ratio: usize,
+    pub hot_channel_ratio: usize,
+    pub first_event_id: usize,
+    pub first_event_number: usize,
+    pub base_time_ns: usize,
+    pub rate_shape: RateShape,
+    /// Length of each step of constant rate
+    pub step_length_ns: usize,
+    /// Number of events in each step of an epoch
+    pub events_per_step: Vec<usize>,
+    /// Number of events of an epoch before each step, to find the step of an event
+    pub events_before_step: Vec<usize>,
+    pub events_per_epoch: usize,
+    pub epoch_period_ns: usize,
+    /// Delay between events in each step of an epoch
+    pub inter_event_delays_ns: Vec<f64>,
+    /// Delay between events after the first epoch, for shapes that do not repeat
+    pub final_inter_event_delay_ns: Option<f64>,
+    pub avg_person_byte_size: usize,
+    pub avg_auction_byte_size: usize,
+    pub avg_bid_byte_size: usize,
+    // Originally constants
+    pub num_categories: usize,
+    pub auction_id_lead: usize,
+    pub hot_seller_ratio_2: usize,
+    pub hot_auction_ratio_2: usize,
+    pub hot_bidder_ratio_2: usize,
+    pub person_proportion: usize,
+    pub auction_proportion: usize,
+    pub bid_proportion: usize,
+    pub proportion_denominator: usize,
+    pub first_auction_id: usize,
+    pub first_person_id: usize,
+    pub first_category_id: usize,
+    pub person_id_lead: usize,
+    pub sine_approx_steps: usize,
+    pub us_states: Vec<String>,
+    pub us_cities: Vec<String>,
+    pub first_names: Vec<String>,
+    pub last_names: Vec<String>,
+    pub hot_channels: Vec<String>,
+    pub hot_urls: Vec<String>,
+}
+
+impl NEXMarkConfig {
+    pub fn new(config: &Config) -> Self{
+        let active_people = config.get_as_or("active-people", 1000);
+        let in_flight_auctions = config.get_as_or("in-flight-auctions", 100);
+        let out_of_order_group_size = config.get_as_or("out-of-order-group-size", 1);
+        let late_fraction = config.get_as_or("late-fraction", 0.0);
+        assert!((0.0..=1.0).contains(&late_fraction), "late-fraction must be between 0 and 1");
+        let max_lateness_ns = config.get_as_or("max-lateness-ms", 0) * 1_000_000;
+        let person_jitter_ns = config.get_as_or("person-jitter-ms", 0) * 1_000_000;
+        let auction_jitter_ns = config.get_as_or("auction-jitter-ms", 0) * 1_000_000;
+        let bid_jitter_ns = config.get_as_or("bid-jitter-ms", 0) * 1_000_000;
+        let hot_seller_ratio = config.get_as_or("hot-seller-ratio", 4);
+        let hot_auction_ratio = config.get_as_or("hot-auction-ratio", 2);
+        let hot_bidder_ratio = config.get_as_or("hot-bidder-ratio", 4);
+        let first_event_id = config.get_as_or("first-event-id", 0);
+        let first_event_number = config.get_as_or("first-event-number", 0);
+        let num_categories = config.get_as_or("num-categories", 5);
+        let auction_id_lead = config.get_as_or("auction-id-lead", 10);
+        let hot_seller_ratio_2 = config.get_as_or("hot-seller-ratio-2", 100);
+        let hot_auction_ratio_2 = config.get_as_or("hot-auction-ratio-2", 100);
+        let hot_bidder_ratio_2 = config.get_as_or("hot-bidder-ratio-2", 100);
+        let person_proportion = config.get_as_or("person-proportion", 1);
+        let auction_proportion = config.get_as_or("auction-proportion", 3);
+        let bid_proportion = config.get_as_or("bid-proportion", 46);
+        let proportion_denominator = person_proportion+auction_proportion+bid_proportion;
+        let first_auction_id = config.get_as_or("first-auction-id", 1000);
+        let first_person_id = config.get_as_or("first-person-id", 1000);
+        let first_category_id = config.get_as_or("first-category-id", 10);
+        let person_id_lead = config.get_as_or("person-id-lead", 10);
+        let sine_approx_steps = config.get_as_or("sine-approx-steps", 10);
+        let base_time_ns = config.get_as_or("base-time", BASE_TIME);
+        let us_states = split_string_arg(config.get_or("us-states", "AZ,CA,ID,OR,WA,WY"));
+        let us_cities = split_string_arg(config.get_or("us-cities", "phoenix,los angeles,san francisco,boise,portland,bend,redmond,seattle,kent,cheyenne"));
+        let first_names = split_string_arg(config.get_or("first-names", "peter,paul,luke,john,saul,vicky,kate,julie,sarah,deiter,walter"));
+        let last_names = split_string_arg(config.get_or("last-names", "shultz,abrams,spencer,white,bartels,walton,smith,jones,noris"));
+        let rate_shape: RateShape = config.get_or("rate-shape", "sine").parse().unwrap_or_else(|e| panic!("{}", e));
+        let rate_period: usize = config.get_as_or("rate-period", 600);
+        let rate_steps = config.get_as_or("rate-steps", sine_approx_steps);
+        let first_rate: f64 = config.get_as_or("first-event-rate", config.get_as_or("events-per-second", 1_000.0));
+        let next_rate: f64 = config.get_as_or("next-event-rate", first_rate);
+        let ns_per_unit = config.get_as_or("us-per-unit", 1_000_000_000); // Rate is in μs
+        let generators = config.get_as_or("threads", 1) as f64;
+        let mut rng = SmallRng::seed_from_u64(config.get_as_or("rate-seed", 0));
+        // Calculate the rates of the steps of an epoch, and the rate after it if it doesn't repeat.
+        let (rates, final_rate) = match rate_shape {
+            RateShape::Trace => (read_trace(&config.get("rate-trace").expect("trace shape requires rate-trace")), None),
+            RateShape::Poisson => {
+                let step_s = rate_period as f64 / rate_steps as f64;
+                ((0..rate_steps).map(|_| poisson(first_rate * step_s, &mut rng) as f64 / step_s).collect(), None)
+            },
+            _ if first_rate == next_rate => (vec![first_rate], None),
+            RateShape::Square => (vec![first_rate, next_rate], None),
+            RateShape::Sine => {
+                let mid = (first_rate + next_rate) / 2.0;
+                let amp = (first_rate - next_rate) / 2.0;
+                ((0..rate_steps).map(|i| {
+                    let r = (2.0 * PI * i as f64) / rate_steps as f64;
+                    (mid + amp * r.cos()).round()
+                }).collect(), None)
+            },
+            RateShape::Ramp => {
+                ((0..rate_steps).map(|i| {
+                    (first_rate + (next_rate - first_rate) * i as f64 / rate_steps as f64).round()
+                }).collect(), Some(next_rate))
+            },
+            RateShape::Step => (vec![first_rate], Some(next_rate)),
+            RateShape::Bursty => {
+                let burst_probability = config.get_as_or("burst-probability", 0.1);
+                ((0..rate_steps).map(|_| {
+                    if rng.gen_bool(burst_probability) { next_rate } else { first_rate }
+                }).collect(), None)
+            },
+        };
+        assert!(!rates.is_empty(), "rate shape without steps");
+        assert!(final_rate.is_none_or(|r| r > 0.0), "rate shape ending without events");
+        let rate_to_period = |r: f64| ns_per_unit as f64 / r * generators;
+        let inter_event_delays_ns: Vec<f64> = rates.iter().map(|&r| rate_to_period(r)).collect();
+        let final_inter_event_delay_ns = final_rate.map(rate_to_period);
+        // Calculate events per epoch and epoch period.
+        let step_length_ns = rate_period * 1_000_000_000 / rates.len();
+        let events_per_step: Vec<usize> = inter_event_delays_ns.iter()
+            .map(|delay| (step_length_ns as f64 / delay).round() as usize)
+            .collect();
+        let events_before_step: Vec<usize> = events_per_step.iter()
+            .scan(0, |before, &events| { let start = *before; *before += events; Some(start) })
+            .collect();
+        let events_per_epoch = events_per_step.iter().sum();
+        let epoch_period_ns = step_length_ns * rates.len();
+        if inter_event_delays_ns.len() > 1 || final_inter_event_delay_ns.is_some() {
+            assert!(events_per_epoch > 0, "rate shape without events");
+        }
+        NEXMarkConfig {
+            active_people,
+            in_flight_auctions,
+            out_of_order_group_size,
+            late_fraction,
+            max_lateness_ns,
+            person_jitter_ns,
+            auction_jitter_ns,
+            bid_jitter_ns,
+            hot_seller_ratio,
+            hot_auction_ratio,
+            hot_bidder_ratio,
+            hot_channel_ratio: 2,
+            first_event_id,
+            first_event_number,
+            base_time_ns,
+            rate_shape,
+            step_length_ns,
+            events_per_step,
+            events_before_step,
+            events_per_epoch,
+            epoch_period_ns,
+            inter_event_delays_ns,
+            final_inter_event_delay_ns,
+            avg_person_byte_size: 200,
+            avg_auction_byte_size: 500,
+            avg_bid_byte_size: 100,
+            // Originally constants
+            num_categories,
+            auction_id_lead,
+            hot_seller_ratio_2,
+            hot_auction_ratio_2,
+            hot_bidder_ratio_2,
+            person_proportion,
+            auction_proportion,
+            bid_proportion,
+            proportion_denominator,
+            first_auction_id,
+            first_person_id,
+            first_category_id,
+            person_id_lead,
+            sine_approx_steps,
+            us_states,
+            us_cities,
+            first_names,
+            last_names,
+            hot_channels: split_str("Google,Facebook,Baidu,Apple"),
+            hot_urls: (0..4).map(get_base_url).collect(),
+        }
+    }
+
+    /// The time of an event, spacing the events of each step evenly.
+    pub fn event_timestamp_ns(&self, event_number: usize) -> usize {
+        if self.inter_event_delays_ns.len() == 1 && self.final_inter_event_delay_ns.is_none() {
+            return self.base_time_ns + ((event_number as f64 * self.inter_event_delays_ns[0]) as usize);
+        }
+
+        let (epoch, event_i) = match self.final_inter_event_delay_ns {
+            Some(delay) if event_number >= self.events_per_epoch => {
+                let offset = ((event_number - self.events_per_epoch) as f64 * delay) as usize;
+                return self.base_time_ns + self.epoch_period_ns + offset;
+            },
+            Some(_) => (0, event_number),
+            None => (event_number / self.events_per_epoch, event_number % self.events_per_epoch),
+        };
+        // The last step starting at or before the event, which is never empty as empty steps start
+        // where the next step starts
+        let step = self.events_before_step.partition_point(|&before| before <= event_i) - 1;
+        let offset_in_step = (event_i - self.events_before_step[step]) * self.step_length_ns / self.events_per_step[step];
+        self.base_time_ns + epoch * self.epoch_period_ns + step * self.step_length_ns + offset_in_step
+    }
+
+    pub fn next_adjusted_event(&self, events_so_far: usize) -> usize {
+        let n = self.out_of_order_group_size;
+        let event_number = self.first_event_number + events_so_far;
+        (event_number / n) * n + (event_number * 953) % n
+    }
+
+    /// The time at which the event following `events_so_far` events is emitted. Emission times
+    /// increase with the number of events, while event times may be out of order.
+    pub fn emission_time_ns(&self, events_so_far: usize) -> usize {
+        self.event_timestamp_ns(self.first_event_number + events_so_far)
+    }
+
+    /// How much the event time of an event precedes its timestamp: up to `jitter_ns` for all
+    /// events, and up to `max_lateness_ns` more for late events.
+    pub fn event_delay_ns(&self, event_number: usize, jitter_ns: usize) -> usize {
+        if jitter_ns == 0 && (self.late_fraction == 0.0 || self.max_lateness_ns == 0) {
+            return 0;
+        }
+        // Independent of the generator of the event's contents, which is seeded with its id
+        let rng = &mut SmallRng::seed_from_u64(!(event_number as u64));
+        let mut delay = rng.gen_range(0..=jitter_ns);
+        if rng.gen_bool(self.late_fraction) {
+            delay += rng.gen_range(0..=self.max_lateness_ns);
+        }
+        delay
+    }
+
+    /// An upper bound of how much an event's time precedes the times of events emitted before it.
+    pub fn max_disorder_ns(&self) -> usize {
+        let jitter_ns = max(self.person_jitter_ns, max(self.auction_jitter_ns, self.bid_jitter_ns));
+        let lateness_ns = if self.late_fraction > 0.0 { self.max_lateness_ns } else { 0 };
+        // Out-of-order groups move events by less than the length of a group
+        let max_delay_ns = self.inter_event_delays_ns.iter().chain(self.final_inter_event_delay_ns.iter())
+            .cloned()
+            .filter(|delay| delay.is_finite())
+            .fold(0.0, f64::max);
+        let group_ns = if self.out_of_order_group_size > 1 {
+            (max_delay_ns * self.out_of_order_group_size as f64).ceil() as usize
+        } else {
+            0
+        };
+        jitter_ns + lateness_ns + group_ns
+    }
+}
+
+fn split_str(string: &str) -> Vec<String> {
+    string.split(',').map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[(&str, &str)]) -> NEXMarkConfig {
+        let mut config = Config::new();
+        for &(key, value) in args {
+            config.insert(key, value.to_string());
+        }
+        NEXMarkConfig::new(&config)
+    }
+
+    /// The number of events with timestamps in each second up to `seconds`.
+    fn events_per_second(config: &NEXMarkConfig, seconds: usize) -> Vec<usize> {
+        let mut counts = vec![0; seconds];
+        let mut previous = 0;
+        for event_number in 0.. {
+            let ns = config.event_timestamp_ns(event_number);
+            assert!(previous <= ns, "event {} precedes its predecessor", event_number);
+            previous = ns;
+            if ns >= seconds * 1_000_000_000 {
+                return counts;
+            }
+            counts[ns / 1_000_000_000] += 1;
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn square_rate_alternates() {
+        let config = config(&[("rate-shape", "square"), ("rate-period", "4"), ("first-event-rate", "100"), ("next-event-rate", "10")]);
+        assert_eq!(events_per_second(&config, 8), vec![100, 100, 10, 10, 100, 100, 10, 10]);
+    }
+
+    #[test]
+    fn step_rate_keeps_next_rate() {
+        let config = config(&[("rate-shape", "step"), ("rate-period", "2"), ("first-event-rate", "10"), ("next-event-rate", "50")]);
+        assert_eq!(events_per_second(&config, 5), vec![10, 10, 50, 50, 50]);
+    }
+
+    #[test]
+    fn ramp_rate_increases() {
+        let config = config(&[("rate-shape", "ramp"), ("rate-period", "4"), ("rate-steps", "4"), ("first-event-rate", "10"), ("next-event-rate", "50")]);
+        assert_eq!(events_per_second(&config, 6), vec![10, 20, 30, 40, 50, 50]);
+    }
+
+    #[test]
+    fn trace_rate_repeats() {
+        let path = ::std::env::temp_dir().join(format!("nexmark-rate-trace-{}", ::std::process::id()));
+        ::std::fs::write(&path, "# events per second\n20\n\n0\n5\n").unwrap();
+        let config = config(&[("rate-shape", "trace"), ("rate-period", "3"), ("rate-trace", path.to_str().unwrap())]);
+        ::std::fs::remove_file(&path).unwrap();
+        assert_eq!(events_per_second(&config, 6), vec![20, 0, 5, 20, 0, 5]);
+    }
+
+    #[test]
+    fn poisson_rate_is_deterministic() {
+        let args = [("rate-shape", "poisson"), ("rate-period", "10"), ("first-event-rate", "1000")];
+        let counts = events_per_second(&config(&args), 10);
+        assert_eq!(counts, events_per_second(&config(&args), 10));
+        let total: usize = counts.iter().sum();
+        assert!(9_000 < total && total < 11_000, "{} events instead of about 10,000", total);
+    }
+}
@@ -1,7 +1,8 @@
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::cmp::max;
 
-use config::NEXMarkConfig;
+use crate::config::NEXMarkConfig;
 
 use crate::utils::{NexmarkRng, CHANNEL_URL_MAP};
 
@@ -12,7 +13,8 @@ const HOT_BIDDER_RATIO: usize = 100;
 
 
 type Id = usize;
-#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Abomonation, Hash, Copy, Default)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Hash, Copy, Default)]
+#[cfg_attr(feature = "abomonation", derive(abomonation_derive::Abomonation))]
 pub struct Date(usize);
 
 impl Date {
@@ -49,13 +51,8 @@ impl ::std::ops::Sub for Date {
 //     string.split(",").map(String::from).collect::<Vec<String>>()
 // }
 
-#[derive(Serialize, Deserialize, Abomonation, Debug)]
-struct EventCarrier {
-    time: Date,
-    event: Event,
-}
-
-#[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Debug, Abomonation)]
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "abomonation", derive(abomonation_derive::Abomonation))]
 #[serde(tag = "type")]
 pub enum Event {
     Person(Person),
@@ -73,17 +70,23 @@ impl Event {
         }
     }
 
-    pub fn create(events_so_far: usize, nex: &mut NEXMarkConfig) -> Self {
-        let rem = nex.next_adjusted_event(events_so_far) % nex.proportion_denominator;
-        let timestamp = Date(nex.event_timestamp_ns(nex.next_adjusted_event(events_so_far)));
-        let id = nex.first_event_id + nex.next_adjusted_event(events_so_far);
+    /// Create the event following `events_so_far` events, as a function of the event number only.
+    ///
+    /// Event times follow the disorder model of the configuration: out-of-order groups permute
+    /// events, and jitter and lateness move event times before the events' timestamps.
+    pub fn create(events_so_far: usize, nex: &NEXMarkConfig) -> Self {
+        let event_number = nex.next_adjusted_event(events_so_far);
+        let rem = event_number % nex.proportion_denominator;
+        let timestamp = nex.event_timestamp_ns(event_number);
+        let time = |jitter_ns| Date(timestamp.saturating_sub(nex.event_delay_ns(event_number, jitter_ns)));
+        let id = nex.first_event_id + event_number;
 
         if rem < nex.person_proportion {
-            Event::Person(Person::new(id, timestamp, nex))
+            Event::Person(Person::new(id, time(nex.person_jitter_ns), nex))
         } else if rem < nex.person_proportion + nex.auction_proportion {
-            Event::Auction(Auction::new(events_so_far, id, timestamp, nex))
+            Event::Auction(Auction::new(events_so_far, id, time(nex.auction_jitter_ns), nex))
         } else {
-            Event::Bid(Bid::new(id, timestamp, nex))
+            Event::Bid(Bid::new(id, time(nex.bid_jitter_ns), nex))
         }
     }
 
@@ -117,21 +120,8 @@ impl Event {
     // }
 }
 
-// impl ToData<usize, Event> for String{
-//     fn to_data(self) -> Result<(usize, Event)> {
-//         serde_json::from_str(&self)
-//             .map(|c: EventCarrier| (c.time, c.event))
-//             .map_err(|e| e.into())
-//     }
-// }
-
-// impl FromData<usize> for Event {
-//     fn from_data(&self, t: &usize) -> String {
-//         serde_json::to_string(&EventCarrier{ time: t.clone(), event: self.clone()}).unwrap()
-//     }
-// }
-
-#[derive(Eq, PartialEq,  Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Abomonation, Hash)]
+#[derive(Eq, PartialEq,  Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Hash)]
+#[cfg_attr(feature = "abomonation", derive(abomonation_derive::Abomonation))]
 pub struct Person{
     pub id: Id,
     pub name: String,
@@ -202,7 +192,8 @@ impl Person {
     }
 }
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Abomonation, Hash)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Hash)]
+#[cfg_attr(feature = "abomonation", derive(abomonation_derive::Abomonation))]
 pub struct Auction{
     pub id: Id,
     pub item_name: String,
@@ -266,7 +257,7 @@ impl Auction {
 
     fn next_id(id: usize, rng: &mut SmallRng, nex: &NEXMarkConfig) -> Id {
         let max_auction = Self::last_id(id, nex);
-        let min_auction = if max_auction < nex.in_flight_auctions { 0 } else { max_auction - nex.in_flight_auctions };
+        let min_auction = max_auction.saturating_sub(nex.in_flight_auctions);
         min_auction + rng.gen_range(0..(max_auction - min_auction + 1 + nex.auction_id_lead))
     }
 
@@ -294,7 +285,8 @@ impl Auction {
     }
 }
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Abomonation, Hash)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Hash)]
+#[cfg_attr(feature = "abomonation", derive(abomonation_derive::Abomonation))]
 pub struct Bid {
     /// The ID of the auction this bid is for.
     pub auction: Id,
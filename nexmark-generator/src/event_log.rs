@@ -0,0 +1,358 @@
+//! Event logs, which store generated events such that runs replay them instead of generating them.
+//!
+//! A log holds one partition per worker, named after the worker's index and the number of workers,
+//! and each partition holds the events that an `EventGenerator` of that worker produces, in order.
+//! Partitions are encoded either compactly with bincode, optionally framed by each record's
+//! length, or as one JSON event per line. Each starts with a header that holds the base time of
+//! the generator, such that replays can move event times to their own base time.
+//!
+//! The `nexmark-log` binary writes logs. The timely implementation replays them with
+//! `--event_log <dir>` and `--event_log_format`, the noir example with `--event-log <dir>`, and
+//! `rayon-nexmark` takes the directory as its first argument. Replaying runs need the same number
+//! of workers and generator options, such as the rate, as the log.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::NEXMarkConfig;
+use crate::event::{Auction, Bid, Date, Event, Person};
+use crate::{worker_share, EventGenerator};
+
+/// The encoding of an event log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Events encoded with bincode, back to back
+    Binary,
+    /// Events encoded with bincode, each preceded by its length as a little-endian `u32`, such
+    /// that readers of a stream can skip records or resynchronize
+    Framed,
+    /// One JSON event per line
+    Json,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match *self {
+            Format::Binary => "bin",
+            Format::Framed => "frames",
+            Format::Json => "jsonl",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "binary" | "bin" => Ok(Format::Binary),
+            "framed" | "frames" => Ok(Format::Framed),
+            "json" | "jsonl" => Ok(Format::Json),
+            _ => Err(format!("unknown event log format {}", s)),
+        }
+    }
+}
+
+/// The header of a partition.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// The base time of the generator that produced the events
+    base_time_ns: usize,
+}
+
+/// An event as binary and framed partitions encode it. `Event` is internally tagged for its JSON encoding,
+/// which bincode does not support, so binary partitions tag events externally.
+#[derive(Serialize)]
+enum BinaryEventRef<'a> {
+    Person(&'a Person),
+    Auction(&'a Auction),
+    Bid(&'a Bid),
+}
+
+/// An event decoded from a binary partition, with the variants of `BinaryEventRef`.
+#[derive(Deserialize)]
+enum BinaryEvent {
+    Person(Person),
+    Auction(Auction),
+    Bid(Bid),
+}
+
+impl<'a> From<&'a Event> for BinaryEventRef<'a> {
+    fn from(event: &'a Event) -> Self {
+        match event {
+            Event::Person(p) => BinaryEventRef::Person(p),
+            Event::Auction(a) => BinaryEventRef::Auction(a),
+            Event::Bid(b) => BinaryEventRef::Bid(b),
+        }
+    }
+}
+
+impl From<BinaryEvent> for Event {
+    fn from(event: BinaryEvent) -> Self {
+        match event {
+            BinaryEvent::Person(p) => Event::Person(p),
+            BinaryEvent::Auction(a) => Event::Auction(a),
+            BinaryEvent::Bid(b) => Event::Bid(b),
+        }
+    }
+}
+
+fn other_error<E: std::error::Error + Send + Sync + 'static>(error: E) -> Error {
+    Error::other(error)
+}
+
+/// Moves the times of an event from base time `from` to base time `to`.
+fn rebase(event: &mut Event, from: usize, to: usize) {
+    let rebase = |date: &mut Date| *date = Date::new((**date + to).saturating_sub(from));
+    match event {
+        Event::Person(p) => rebase(&mut p.date_time),
+        Event::Auction(a) => {
+            rebase(&mut a.date_time);
+            rebase(&mut a.expires);
+        }
+        Event::Bid(b) => rebase(&mut b.date_time),
+    }
+}
+
+/// The path of the partition of worker `index` out of `peers` in the log in `dir`.
+pub fn partition_path(dir: &Path, index: usize, peers: usize, format: Format) -> PathBuf {
+    dir.join(format!("events-{}-of-{}.{}", index, peers, format.extension()))
+}
+
+/// The number of partitions of the log in `dir`.
+pub fn partitions(dir: &Path, format: Format) -> Result<usize> {
+    let suffix = format!(".{}", format.extension());
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let peers = name.to_str()
+            .and_then(|name| name.strip_prefix("events-0-of-"))
+            .and_then(|name| name.strip_suffix(&suffix))
+            .and_then(|peers| peers.parse().ok());
+        if let Some(peers) = peers {
+            return Ok(peers);
+        }
+    }
+    Err(Error::new(ErrorKind::NotFound, format!("no {:?} event log in {}", format, dir.display())))
+}
+
+/// Writes the first `events` events of `peers` workers to the log in `dir`, one partition per
+/// worker.
+pub fn write_log(config: &NEXMarkConfig, events: usize, peers: usize, dir: &Path, format: Format) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for index in 0..peers {
+        let mut writer = EventLogWriter::create(&partition_path(dir, index, peers, format), format, config.base_time_ns)?;
+        for event in EventGenerator::new(config.clone(), index, peers).take(worker_share(events, index, peers)) {
+            writer.write(&event)?;
+        }
+        writer.finish()?;
+    }
+    Ok(())
+}
+
+/// Writes events to a partition of an event log.
+pub struct EventLogWriter<W: Write> {
+    writer: W,
+    format: Format,
+}
+
+impl EventLogWriter<BufWriter<File>> {
+    pub fn create(path: &Path, format: Format, base_time_ns: usize) -> Result<Self> {
+        EventLogWriter::new(BufWriter::new(File::create(path)?), format, base_time_ns)
+    }
+}
+
+impl<W: Write> EventLogWriter<W> {
+    /// Writes the header of a partition of events generated from `base_time_ns`.
+    pub fn new(writer: W, format: Format, base_time_ns: usize) -> Result<Self> {
+        let mut writer = EventLogWriter { writer, format };
+        writer.write_record(&Header { base_time_ns })?;
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, event: &Event) -> Result<()> {
+        match self.format {
+            Format::Binary | Format::Framed => self.write_record(&BinaryEventRef::from(event)),
+            Format::Json => self.write_record(event),
+        }
+    }
+
+    fn write_record<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        match self.format {
+            Format::Binary => bincode::serialize_into(&mut self.writer, record).map_err(other_error),
+            Format::Framed => {
+                let frame = bincode::serialize(record).map_err(other_error)?;
+                let length = u32::try_from(frame.len())
+                    .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("record of {} bytes exceeds a frame", frame.len())))?;
+                self.writer.write_all(&length.to_le_bytes())?;
+                self.writer.write_all(&frame)
+            }
+            Format::Json => {
+                serde_json::to_writer(&mut self.writer, record)?;
+                self.writer.write_all(b"\n")
+            }
+        }
+    }
+
+    /// Flushes the written events, for example before a stream waits for further events.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes the written events and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Replays the events of a partition of an event log.
+///
+/// Panics if the partition is corrupt, as replayed runs would otherwise silently miss events.
+pub struct EventLogReader<R: BufRead> {
+    reader: R,
+    format: Format,
+    line: String,
+    frame: Vec<u8>,
+    /// The base time of the generator that produced the events
+    base_time_ns: usize,
+    /// The base time to move event times to, if any
+    rebase_to_ns: Option<usize>,
+}
+
+impl EventLogReader<BufReader<File>> {
+    pub fn open(path: &Path, format: Format) -> Result<Self> {
+        EventLogReader::new(BufReader::new(File::open(path)?), format)
+    }
+}
+
+impl<R: BufRead> EventLogReader<R> {
+    /// Reads the header of a partition.
+    pub fn new(reader: R, format: Format) -> Result<Self> {
+        let mut reader = EventLogReader { reader, format, line: String::new(), frame: Vec::new(), base_time_ns: 0, rebase_to_ns: None };
+        let header: Header = reader.read_record()?
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "event log without header"))?;
+        reader.base_time_ns = header.base_time_ns;
+        Ok(reader)
+    }
+
+    /// The base time of the generator that produced the events.
+    pub fn base_time_ns(&self) -> usize {
+        self.base_time_ns
+    }
+
+    /// Replays events as if generated from `base_time_ns`, as needed for runs whose input times
+    /// start at a different base time than the log's.
+    pub fn rebased(mut self, base_time_ns: usize) -> Self {
+        self.rebase_to_ns = Some(base_time_ns);
+        self
+    }
+
+    /// Reads the next event, or `None` at the end of the partition.
+    pub fn read(&mut self) -> Result<Option<Event>> {
+        let event = match self.format {
+            Format::Binary | Format::Framed => self.read_record::<BinaryEvent>()?.map(Event::from),
+            Format::Json => self.read_record()?,
+        };
+        Ok(event.map(|mut event| {
+            if let Some(to) = self.rebase_to_ns {
+                rebase(&mut event, self.base_time_ns, to);
+            }
+            event
+        }))
+    }
+
+    fn read_record<T: for<'de> Deserialize<'de>>(&mut self) -> Result<Option<T>> {
+        match self.format {
+            Format::Binary => {
+                if self.reader.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                bincode::deserialize_from(&mut self.reader).map(Some).map_err(other_error)
+            }
+            Format::Framed => {
+                if self.reader.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                let mut length = [0; 4];
+                self.reader.read_exact(&mut length)?;
+                self.frame.resize(u32::from_le_bytes(length) as usize, 0);
+                self.reader.read_exact(&mut self.frame)?;
+                bincode::deserialize(&self.frame).map(Some).map_err(other_error)
+            }
+            Format::Json => {
+                self.line.clear();
+                if self.reader.read_line(&mut self.line)? == 0 {
+                    return Ok(None);
+                }
+                serde_json::from_str(&self.line).map(Some).map_err(other_error)
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EventLogReader<R> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.read().expect("corrupt event log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn config() -> NEXMarkConfig {
+        NEXMarkConfig::new(&Config::new())
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let events: Vec<_> = EventGenerator::new(config(), 0, 1).take(1_000).collect();
+        for &format in [Format::Binary, Format::Framed, Format::Json].iter() {
+            let mut writer = EventLogWriter::new(Vec::new(), format, config().base_time_ns).unwrap();
+            for event in events.iter() {
+                writer.write(event).unwrap();
+            }
+            let bytes = writer.finish().unwrap();
+            let replayed: Vec<_> = EventLogReader::new(&bytes[..], format).unwrap().collect();
+            assert!(replayed == events, "{:?} log changed events", format);
+        }
+    }
+
+    #[test]
+    fn replays_apply_base_time() {
+        let mut rebased = Config::new();
+        rebased.insert("base-time", "0".to_string());
+        let rebased = NEXMarkConfig::new(&rebased);
+        let events: Vec<_> = EventGenerator::new(config(), 0, 1).take(1_000).collect();
+        let mut writer = EventLogWriter::new(Vec::new(), Format::Binary, config().base_time_ns).unwrap();
+        for event in events.iter() {
+            writer.write(event).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let reader = EventLogReader::new(&bytes[..], Format::Binary).unwrap();
+        assert_eq!(reader.base_time_ns(), config().base_time_ns);
+        let replayed: Vec<_> = reader.rebased(0).collect();
+        let generated: Vec<_> = EventGenerator::new(rebased, 0, 1).take(1_000).collect();
+        assert!(replayed == generated, "rebased events differ from events generated at the base time");
+    }
+
+    #[test]
+    fn partitions_replay_workers() {
+        let dir = std::env::temp_dir().join(format!("nexmark-event-log-{}", std::process::id()));
+        write_log(&config(), 1_000, 3, &dir, Format::Binary).unwrap();
+        assert_eq!(partitions(&dir, Format::Binary).unwrap(), 3);
+        for index in 0..3 {
+            let replayed: Vec<_> = EventLogReader::open(&partition_path(&dir, index, 3, Format::Binary), Format::Binary).unwrap().collect();
+            let generated: Vec<_> = EventGenerator::new(config(), index, 3).take(worker_share(1_000, index, 3)).collect();
+            assert!(replayed == generated, "partition {} differs from its worker's events", index);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
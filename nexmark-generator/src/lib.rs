@@ -0,0 +1,125 @@
+//! The NEXMark event generator shared by the timely and noir suites.
+//!
+//! Events are a pure function of the configuration and the event number, such that every system
+//! splitting the event numbers among its workers the same way sees the same events.
+
+pub mod config;
+pub mod event;
+pub mod event_log;
+pub mod query;
+pub mod tcp;
+pub mod utils;
+
+use config::NEXMarkConfig;
+use event::Event;
+
+/// Generates the events of one of `peers` workers.
+///
+/// Worker `index` generates event numbers `index`, `index + peers`, and so on, such that the
+/// workers together generate the events of a single generator.
+#[derive(Clone)]
+pub struct EventGenerator {
+    config: NEXMarkConfig,
+    events_so_far: usize,
+    peers: usize,
+}
+
+impl EventGenerator {
+    pub fn new(mut config: NEXMarkConfig, index: usize, peers: usize) -> Self {
+        assert!(index < peers, "generator {} out of {} peers", index, peers);
+        config.first_event_number += index;
+        EventGenerator {
+            config,
+            events_so_far: 0,
+            peers,
+        }
+    }
+
+    /// The configuration of this worker's share of events, with its first event number.
+    pub fn config(&self) -> &NEXMarkConfig {
+        &self.config
+    }
+
+    /// The number of events that `peers` workers generate in total before this one's next event.
+    pub fn events_so_far(&self) -> usize {
+        self.events_so_far
+    }
+}
+
+/// The number of the first `events` events that worker `index` of `peers` generates.
+pub fn worker_share(events: usize, index: usize, peers: usize) -> usize {
+    events / peers + (index < events % peers) as usize
+}
+
+impl Iterator for EventGenerator {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        let event = Event::create(self.events_so_far, &self.config);
+        self.events_so_far += self.peers;
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+
+    fn config() -> NEXMarkConfig {
+        NEXMarkConfig::new(&Config::new())
+    }
+
+    #[test]
+    fn workers_split_a_single_generator() {
+        let single: Vec<_> = EventGenerator::new(config(), 0, 1).take(3_000).collect();
+        let mut split: Vec<_> = (0..3)
+            .flat_map(|index| {
+                EventGenerator::new(config(), index, 3)
+                    .take(1_000)
+                    .enumerate()
+                    .map(move |(i, event)| (3 * i + index, event))
+            })
+            .collect();
+        split.sort_by_key(|&(number, _)| number);
+        assert!(single == split.into_iter().map(|(_, event)| event).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn worker_shares_add_up() {
+        for peers in 1..5 {
+            let shares: usize = (0..peers).map(|index| worker_share(10, index, peers)).sum();
+            assert_eq!(shares, 10);
+        }
+    }
+
+    #[test]
+    fn disorder_is_bounded() {
+        let mut config = Config::new();
+        config.insert("out-of-order-group-size", "4".to_string());
+        config.insert("late-fraction", "0.1".to_string());
+        config.insert("max-lateness-ms", "500".to_string());
+        config.insert("bid-jitter-ms", "20".to_string());
+        let config = NEXMarkConfig::new(&config);
+        let bound = config.max_disorder_ns();
+        let mut latest = 0;
+        let mut out_of_order = 0;
+        for event in EventGenerator::new(config, 0, 1).take(10_000) {
+            let time = *event.time();
+            assert!(latest <= time + bound, "{:?} precedes {} by more than {}", event, latest, bound);
+            if time < latest {
+                out_of_order += 1;
+            }
+            latest = latest.max(time);
+        }
+        assert!(out_of_order > 0);
+    }
+
+    #[test]
+    fn events_are_byte_identical_across_runs() {
+        let serialize = |events: Vec<Event>| serde_json::to_vec(&events).unwrap();
+        let first = serialize(EventGenerator::new(config(), 1, 4).take(1_000).collect());
+        let second = serialize(EventGenerator::new(config(), 1, 4).take(1_000).collect());
+        assert_eq!(first, second);
+    }
+}
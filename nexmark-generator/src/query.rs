@@ -0,0 +1,202 @@
+//! Definitions that the query implementations of all suites share, such that they compute the
+//! same results.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::event::Date;
+
+const SECOND_NS: usize = 1_000_000_000;
+const MINUTE_NS: usize = 60 * SECOND_NS;
+const HOUR_NS: usize = 60 * MINUTE_NS;
+
+/// Determines how queries treat late events, which arrive after the watermark or input frontier
+/// passed their event time, or for windows, after the end of their windows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lateness {
+    /// Discard late events.
+    Drop,
+    /// Route late events to a side output stream per query, whose events are counted.
+    SideOutput,
+    /// Include events late by up to the given nanoseconds of event time, updating results that were
+    /// already produced, and discard later events.
+    Allowed(usize),
+}
+
+impl Lateness {
+    /// The allowed lateness in nanoseconds of event time.
+    pub fn allowed_ns(&self) -> usize {
+        match *self {
+            Lateness::Drop | Lateness::SideOutput => 0,
+            Lateness::Allowed(lateness_ns) => lateness_ns,
+        }
+    }
+}
+
+impl FromStr for Lateness {
+    type Err = String;
+
+    /// Parses `drop`, `side-output` or `allowed:<milliseconds>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Lateness::Drop),
+            "side-output" => Ok(Lateness::SideOutput),
+            _ => match s.strip_prefix("allowed:") {
+                Some(millis) => millis
+                    .parse::<usize>()
+                    .map(|lateness_ms| Lateness::Allowed(lateness_ms * 1_000_000))
+                    .map_err(|e| format!("invalid allowed lateness {s}: {e}")),
+                None => Err(format!("unknown lateness policy: {s}")),
+            },
+        }
+    }
+}
+
+/// Parameters of the queries, in nanoseconds of event time.
+///
+/// The default configuration filters a larger share of bids in query 2 and uses windows of seconds
+/// to produce results during short runs. The spec configuration follows the NEXMark specification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueryConfig {
+    /// Filter bids in query 2 as specified, and in noir, close auctions at their expiry rather
+    /// than once the input ends
+    pub spec: bool,
+    /// Window size and slide of query 5
+    pub q5_window_ns: (usize, usize),
+    /// Window size of query 7
+    pub q7_window_ns: usize,
+    /// Window size of query 8
+    pub q8_window_ns: usize,
+    /// Session gap of query 11
+    pub q11_gap_ns: usize,
+    /// Processing time window size of query 12
+    pub q12_window_ns: usize,
+    /// Window size of queries 15 to 17
+    pub statistics_window_ns: usize,
+    /// Treatment of late events
+    pub lateness: Lateness,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            spec: false,
+            q5_window_ns: (10 * SECOND_NS, 2 * SECOND_NS),
+            q7_window_ns: 10 * SECOND_NS,
+            q8_window_ns: 10 * SECOND_NS,
+            q11_gap_ns: 10 * SECOND_NS,
+            q12_window_ns: 10 * SECOND_NS,
+            statistics_window_ns: 10 * SECOND_NS,
+            lateness: Lateness::Drop,
+        }
+    }
+}
+
+impl QueryConfig {
+    pub fn spec() -> Self {
+        Self {
+            spec: true,
+            q5_window_ns: (60 * MINUTE_NS, MINUTE_NS),
+            q7_window_ns: MINUTE_NS,
+            q8_window_ns: 12 * HOUR_NS,
+            q11_gap_ns: 10 * SECOND_NS,
+            q12_window_ns: 10 * SECOND_NS,
+            statistics_window_ns: 24 * HOUR_NS,
+            lateness: Lateness::Drop,
+        }
+    }
+
+    /// Whether query 2 selects the bids for `auction`.
+    pub fn q2_selects(&self, auction: usize) -> bool {
+        if self.spec {
+            [1007, 1020, 2001, 2019, 2087].contains(&auction)
+        } else {
+            auction.is_multiple_of(123)
+        }
+    }
+}
+
+/// The price rank of a bid in queries 15 to 17: 1 below 10,000, 2 below 1,000,000, 3 otherwise.
+pub fn price_rank(price: usize) -> usize {
+    if price < 10_000 {
+        1
+    } else if price < 1_000_000 {
+        2
+    } else {
+        3
+    }
+}
+
+/// Classifies the time of day of a bid in query 14.
+pub fn bid_time_type(date_time: Date) -> &'static str {
+    match (*date_time / HOUR_NS) % 24 {
+        8..=18 => "dayTime",
+        0..=6 | 20..=23 => "nightTime",
+        _ => "otherTime",
+    }
+}
+
+/// Number of top bids per auction of query 19.
+pub const TOP_BIDS: usize = 10;
+
+/// Determines the channel id of a bid in query 21, from its channel for well-known channels and
+/// from the `channel_id` parameter of its URL otherwise.
+pub fn channel_id(channel: &str, url: &str) -> Option<String> {
+    match &channel.to_lowercase()[..] {
+        "apple" => Some("0".to_string()),
+        "google" => Some("1".to_string()),
+        "facebook" => Some("2".to_string()),
+        "baidu" => Some("3".to_string()),
+        _ => url
+            .split('&')
+            .find_map(|parameter| parameter.strip_prefix("channel_id="))
+            .map(String::from),
+    }
+}
+
+/// Number of entries in the side input of query 13.
+pub const SIDE_INPUT_SIZE: usize = 10_000;
+
+/// The bounded side input of query 13, which each worker holds in full.
+pub fn side_input() -> HashMap<usize, String> {
+    (0..SIDE_INPUT_SIZE)
+        .map(|key| (key, format!("side-{key}")))
+        .collect()
+}
+
+/// The key of the side input entry that a bid for `auction` joins with in query 13.
+pub fn side_input_key(auction: usize) -> usize {
+    auction % SIDE_INPUT_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bid_time_types() {
+        let at = |hour: usize| bid_time_type(Date::new(hour * HOUR_NS));
+        assert_eq!(at(8), "dayTime");
+        assert_eq!(at(18), "dayTime");
+        assert_eq!(at(6), "nightTime");
+        assert_eq!(at(20), "nightTime");
+        assert_eq!(at(19), "otherTime");
+        assert_eq!(at(24 + 7), "otherTime");
+    }
+
+    #[test]
+    fn lateness_policies() {
+        assert_eq!("drop".parse(), Ok(Lateness::Drop));
+        assert_eq!("side-output".parse(), Ok(Lateness::SideOutput));
+        assert_eq!("allowed:5".parse(), Ok(Lateness::Allowed(5_000_000)));
+        assert!("allowed:x".parse::<Lateness>().is_err());
+        assert!("late".parse::<Lateness>().is_err());
+    }
+
+    #[test]
+    fn channel_ids() {
+        assert_eq!(channel_id("Google", ""), Some("1".to_string()));
+        assert_eq!(channel_id("other", "https://x.com?a=1&channel_id=42"), Some("42".to_string()));
+        assert_eq!(channel_id("other", "https://x.com?a=1"), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! A TCP source of NEXMark events, which stands in for a broker such as Kafka such that runs
+//! measure network ingestion.
+//!
+//! Each worker connects to a producer and sends its index and the number of workers, as two
+//! little-endian `u32`s. The producer then streams the events of the worker's `EventGenerator` at
+//! their emission times, encoded as a framed partition of an event log, and closes the connection
+//! after the last event. Each record is preceded by its length as a little-endian `u32`, such that
+//! consumers can skip records they do not decode or resynchronize on a record boundary.
+//!
+//! The `nexmark-producer` binary serves the workers of one run. The timely implementation connects
+//! to it with `--tcp_source <addr>`, and the noir example with `--tcp-source <addr>`.
+
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::config::NEXMarkConfig;
+use crate::event::Event;
+use crate::event_log::{EventLogReader, EventLogWriter, Format};
+use crate::{worker_share, EventGenerator};
+
+/// Producers wait for events' emission times only if they are ahead by more than this, to avoid
+/// sleeping for every event at high rates.
+const MIN_WAIT: Duration = Duration::from_millis(1);
+
+/// Reads the index and number of workers that a connecting worker sends.
+pub fn read_handshake<R: Read>(reader: &mut R) -> Result<(usize, usize)> {
+    let mut words = [0; 8];
+    reader.read_exact(&mut words)?;
+    let index = u32::from_le_bytes([words[0], words[1], words[2], words[3]]) as usize;
+    let peers = u32::from_le_bytes([words[4], words[5], words[6], words[7]]) as usize;
+    if index >= peers {
+        return Err(Error::new(ErrorKind::InvalidData, format!("worker {} out of {} peers", index, peers)));
+    }
+    Ok((index, peers))
+}
+
+/// Streams worker `index`'s share of the first `events` events to `stream`, each at its emission
+/// time relative to the call.
+pub fn produce(stream: TcpStream, config: &NEXMarkConfig, index: usize, peers: usize, events: usize) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let mut writer = EventLogWriter::new(BufWriter::new(stream), Format::Framed, config.base_time_ns)?;
+    let mut generator = EventGenerator::new(config.clone(), index, peers);
+    let start = Instant::now();
+    for _ in 0..worker_share(events, index, peers) {
+        let emission_ns = generator.config().emission_time_ns(generator.events_so_far()) - config.base_time_ns;
+        let target = start + Duration::from_nanos(emission_ns as u64);
+        if let Some(wait) = target.checked_duration_since(Instant::now()) {
+            if wait > MIN_WAIT {
+                writer.flush()?;
+                std::thread::sleep(wait);
+            }
+        }
+        writer.write(&generator.next().unwrap())?;
+    }
+    writer.finish().map(drop)
+}
+
+/// The events that a producer streams to one worker.
+///
+/// Panics if the connection fails, as runs would otherwise silently miss events.
+pub struct TcpSource {
+    reader: EventLogReader<BufReader<TcpStream>>,
+}
+
+impl TcpSource {
+    /// Connects worker `index` of `peers` to the producer at `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A, index: usize, peers: usize) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.write_all(&(index as u32).to_le_bytes())?;
+        stream.write_all(&(peers as u32).to_le_bytes())?;
+        Ok(TcpSource { reader: EventLogReader::new(BufReader::new(stream), Format::Framed)? })
+    }
+
+    /// The base time of the producer's generator.
+    pub fn base_time_ns(&self) -> usize {
+        self.reader.base_time_ns()
+    }
+
+    /// Receives events as if generated from `base_time_ns`, as `EventLogReader::rebased`.
+    pub fn rebased(self, base_time_ns: usize) -> Self {
+        TcpSource { reader: self.reader.rebased(base_time_ns) }
+    }
+}
+
+impl Iterator for TcpSource {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.reader.read().expect("failed to read event from producer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::net::TcpListener;
+
+    #[test]
+    fn sources_receive_their_workers_events() {
+        let mut config = Config::new();
+        config.insert("events-per-second", "1000000".to_string());
+        let config = NEXMarkConfig::new(&config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let producer = {
+            let config = config.clone();
+            std::thread::spawn(move || {
+                for _ in 0..2 {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let (index, peers) = read_handshake(&mut stream).unwrap();
+                    produce(stream, &config, index, peers, 1_000).unwrap();
+                }
+            })
+        };
+        for index in 0..2 {
+            let received: Vec<_> = TcpSource::connect(addr, index, 2).unwrap().collect();
+            let generated: Vec<_> = EventGenerator::new(config.clone(), index, 2).take(worker_share(1_000, index, 2)).collect();
+            assert!(received == generated, "worker {} received different events", index);
+        }
+        producer.join().unwrap();
+    }
+}
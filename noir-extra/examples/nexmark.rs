@@ -2,38 +2,173 @@ use noir::operator::Operator;
 use noir::operator::Timestamp;
 use noir::prelude::*;
 use noir::Stream;
-use std::time::Instant;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use nexmark::event::*;
+use nexmark_generator::config::{Config, NEXMarkConfig};
+use nexmark_generator::event::*;
+use nexmark_generator::query::{
+    bid_time_type, channel_id, price_rank, side_input, side_input_key, Lateness, QueryConfig,
+    TOP_BIDS,
+};
+use noir_extra::EventSource;
 
 const WATERMARK_INTERVAL: usize = 1024;
 const BATCH_SIZE: usize = 4096;
-const SECOND_MILLIS: i64 = 1_000;
+
+/// Late events in the side outputs of this process under `Lateness::SideOutput`.
+static LATE_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The watermark of a stream, which trails the latest event time by the allowed lateness.
+#[derive(Clone, Copy, Debug)]
+struct Watermark {
+    lateness: Lateness,
+    latest: Timestamp,
+}
+
+impl Watermark {
+    fn new(lateness: Lateness) -> Self {
+        Self {
+            lateness,
+            latest: Timestamp::MIN,
+        }
+    }
+
+    /// The allowed lateness in milliseconds.
+    fn allowed(&self) -> Timestamp {
+        duration_millis(self.lateness.allowed_ns())
+    }
+
+    /// Whether a record at `time` is at or after the watermark.
+    fn on_time(&self, time: Timestamp) -> bool {
+        time >= self.latest.saturating_sub(self.allowed())
+    }
+
+    /// Advance past an event at `time`, and return the new watermark.
+    fn advance(&mut self, time: Timestamp) -> Timestamp {
+        self.latest = self.latest.max(time);
+        self.latest.saturating_sub(self.allowed())
+    }
+}
+
+/// The event time of a date, which the generator produces in nanoseconds.
+fn millis(date: Date) -> Timestamp {
+    (*date / 1_000_000) as Timestamp
+}
+
+/// A duration of the query configuration, which is in nanoseconds, in milliseconds.
+fn duration_millis(ns: usize) -> Timestamp {
+    (ns / 1_000_000) as Timestamp
+}
 
 fn timestamp_gen(e: &Event) -> Timestamp {
-    e.timestamp() as i64
+    millis(e.time())
 }
 
-fn watermark_gen(e: &Event, ts: &Timestamp) -> Option<Timestamp> {
-    let w = match e {
+/// Whether to emit a watermark after an event, about once every `WATERMARK_INTERVAL` events.
+fn emits_watermark(e: &Event) -> bool {
+    match e {
         Event::Person(x) => x.id % WATERMARK_INTERVAL == 0,
         Event::Auction(x) => x.id % WATERMARK_INTERVAL == 0,
-        Event::Bid(x) => (x.auction ^ x.bidder ^ x.date_time as usize) % WATERMARK_INTERVAL == 0,
-    };
-    if w {
-        Some(*ts)
-    } else {
-        None
+        Event::Bid(x) => bid_emits_watermark(x),
     }
 }
 
-/// For each concluded auction, find its winning bid.
+fn bid_emits_watermark(b: &Bid) -> bool {
+    (b.auction ^ b.bidder ^ *b.date_time) % WATERMARK_INTERVAL == 0
+}
+
+/// Timestamp events with their event time, and route the events that arrive after the watermark
+/// passed their time to the side output.
+fn timestamped(
+    events: Stream<Event, impl Operator<Event> + 'static>,
+    config: &QueryConfig,
+) -> Stream<Event, impl Operator<Event>> {
+    timestamped_with(events, config, timestamp_gen)
+}
+
+/// Timestamp events with `timestamp`, which is at or after their event time, and route the
+/// events that arrive after the watermark passed their timestamp to the side output.
+///
+/// Events arrive out of order if the generator delays them, so watermarks trail the latest event
+/// time at the source by the allowed lateness.
+fn timestamped_with(
+    events: Stream<Event, impl Operator<Event> + 'static>,
+    config: &QueryConfig,
+    timestamp: fn(&Event) -> Timestamp,
+) -> Stream<Event, impl Operator<Event>> {
+    let mut late = Watermark::new(config.lateness);
+    let mut watermark = Watermark::new(config.lateness);
+    let mut routes = events
+        .rich_map(move |e| {
+            let on_time = late.on_time(timestamp(&e));
+            if on_time {
+                late.advance(timestamp_gen(&e));
+            }
+            (on_time, e)
+        })
+        .route()
+        .add_route(|(on_time, _)| *on_time)
+        .add_route(|(on_time, _)| !*on_time)
+        .build()
+        .into_iter();
+
+    let on_time = routes.next().unwrap().map(|(_, e)| e);
+    side_output(routes.next().unwrap().map(|(_, e)| e), config.lateness);
+    on_time.add_timestamps(timestamp, move |e, _| {
+        let w = watermark.advance(timestamp_gen(e));
+        emits_watermark(e).then_some(w)
+    })
+}
+
+/// The side output of late events, which this process counts under `Lateness::SideOutput` and
+/// discards otherwise.
+fn side_output(late: Stream<Event, impl Operator<Event> + 'static>, lateness: Lateness) {
+    late.for_each(move |_| {
+        if lateness == Lateness::SideOutput {
+            LATE_EVENTS.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+/// Timestamp events for windows, which produce their results once the watermark passes their end.
+///
+/// The timely suite's windows produce their results at their end, and update them as late events
+/// arrive within the allowed lateness. Under `Lateness::Allowed`, the events are windowed in two
+/// runs to do the same: the first run's watermarks follow the latest event time, and the second
+/// run's trail it by the allowed lateness, such that its results are the updated results.
+fn timestamped_runs(
+    events: Stream<Event, impl Operator<Event> + 'static>,
+    config: &QueryConfig,
+) -> Vec<Stream<Event, impl Operator<Event>>> {
+    let runs = match config.lateness {
+        Lateness::Allowed(_) => vec![Lateness::Drop, config.lateness],
+        lateness => vec![lateness],
+    };
+    events
+        .split(runs.len())
+        .into_iter()
+        .zip(runs)
+        .map(|(events, lateness)| {
+            let config = QueryConfig {
+                lateness,
+                ..*config
+            };
+            timestamped(events, &config)
+        })
+        .collect()
+}
+
+/// For each auction, find its winning bid once the input ends.
+///
+/// This is cheaper than closing auctions at their expiry, as the timely suite and
+/// `winning_bids_at_expiry` do, but produces no results before the input ends.
 fn winning_bids(
     auction: Stream<Auction, impl Operator<Auction> + 'static>,
     bid: Stream<Bid, impl Operator<Bid> + 'static>,
 ) -> Stream<(Auction, Bid), impl Operator<(Auction, Bid)>> {
     auction
-        // TODO: filter a.expires < CURRENT_TIME
         // WHERE A.id = B.auction
         .join(bid, |a| a.id, |b| b.auction)
         // WHERE B.datetime < A.expires
@@ -58,6 +193,78 @@ fn winning_bids(
         .map(|(auction, bid)| (auction.unwrap(), bid.unwrap()))
 }
 
+/// Auctions at their expiry and other events at their event time.
+fn expiry_timestamp(e: &Event) -> Timestamp {
+    match e {
+        Event::Auction(a) => millis(a.expires),
+        _ => timestamp_gen(e),
+    }
+}
+
+/// For each concluded auction, find its winning bid once the watermark passes its expiry, as the
+/// timely suite does once its frontier passes it.
+///
+/// Auctions are timestamped with their expiry at the source, whose watermarks trail the event
+/// time of all events, such that an auction's window closes only after the bids before its expiry.
+fn winning_bids_at_expiry(
+    events: Stream<Event, impl Operator<Event> + 'static>,
+    config: &QueryConfig,
+) -> Stream<(Auction, Bid), impl Operator<(Auction, Bid)>> {
+    timestamped_with(events, config, expiry_timestamp)
+        .filter(|e| !matches!(e, Event::Person(_)))
+        // WHERE A.id = B.auction
+        .group_by(|e| match e {
+            Event::Auction(a) => a.id,
+            Event::Bid(b) => b.auction,
+            Event::Person(_) => unreachable!(),
+        })
+        .window(EventTimeWindow::tumbling(1))
+        .map(|w| w.cloned().collect::<Vec<_>>())
+        .rich_flat_map({
+            // The bids of the auction until it closes
+            let mut bids = Some(Vec::new());
+            move |(_, events): (_, Vec<Event>)| {
+                let (auction, new_bids): (Vec<_>, Vec<_>) = events
+                    .into_iter()
+                    .partition(|e| matches!(e, Event::Auction(_)));
+                if let Some(bids) = bids.as_mut() {
+                    bids.extend(new_bids.into_iter().map(unwrap_bid));
+                }
+                // A.expires < CURRENT_TIME
+                let a = auction.into_iter().next().map(unwrap_auction)?;
+                bids.take()?
+                    .into_iter()
+                    // WHERE B.datetime < A.expires
+                    .filter(|b| {
+                        b.price >= a.reserve && (a.date_time..a.expires).contains(&b.date_time)
+                    })
+                    // find the bid with the maximum price, the earliest among equal prices
+                    .max_by_key(|b| (b.price, std::cmp::Reverse(b.date_time)))
+                    .map(|b| (a, b))
+            }
+        })
+        .drop_key()
+}
+
+/// Split events into auctions and bids.
+fn auctions_and_bids(
+    events: Stream<Event, impl Operator<Event> + 'static>,
+) -> (
+    Stream<Auction, impl Operator<Auction>>,
+    Stream<Bid, impl Operator<Bid>>,
+) {
+    let mut routes = events
+        .route()
+        .add_route(|e| matches!(e, Event::Auction(_)))
+        .add_route(|e| matches!(e, Event::Bid(_)))
+        .build()
+        .into_iter();
+
+    let auction = routes.next().unwrap().map(unwrap_auction);
+    let bid = routes.next().unwrap().map(unwrap_bid);
+    (auction, bid)
+}
+
 /// Query 0: Passthrough
 fn query0(events: Stream<Event, impl Operator<Event> + 'static>) {
     events.for_each(std::mem::drop)
@@ -86,10 +293,11 @@ fn query1(events: Stream<Event, impl Operator<Event> + 'static>) {
 /// FROM Bid [NOW]
 /// WHERE auction = 1007 OR auction = 1020 OR auction = 2001 OR auction = 2019 OR auction = 2087;
 /// ```
-fn query2(events: Stream<Event, impl Operator<Event> + 'static>) {
+fn query2(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    let config = *config;
     events
         .filter_map(filter_bid)
-        .filter(|b| b.auction % 123 == 0)
+        .filter(move |b| config.q2_selects(b.auction))
         .for_each(std::mem::drop)
 }
 
@@ -140,18 +348,19 @@ fn query3(events: Stream<Event, impl Operator<Event> + 'static>) {
 /// WHERE Q.category = C.id
 /// GROUP BY C.id;
 /// ```
-fn query4(events: Stream<Event, impl Operator<Event> + 'static>) {
-    let mut routes = events
-        .route()
-        .add_route(|e| matches!(e, Event::Auction(_)))
-        .add_route(|e| matches!(e, Event::Bid(_)))
-        .build()
-        .into_iter();
-
-    let auction = routes.next().unwrap().map(unwrap_auction);
-    let bid = routes.next().unwrap().map(unwrap_bid);
+fn query4(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    if config.spec {
+        average_price_by_category(winning_bids_at_expiry(events, config))
+    } else {
+        let (auction, bid) = auctions_and_bids(events);
+        average_price_by_category(winning_bids(auction, bid))
+    }
+}
 
-    winning_bids(auction, bid)
+fn average_price_by_category(
+    winning_bids: Stream<(Auction, Bid), impl Operator<(Auction, Bid)> + 'static>,
+) {
+    winning_bids
         // GROUP BY category, AVG(price)
         .map(|(a, b)| (a.category, b.price))
         .group_by_avg(|(category, _)| *category, |(_, price)| *price as f64)
@@ -170,24 +379,25 @@ fn query4(events: Stream<Event, impl Operator<Event> + 'static>) {
 ///                   FROM Bid [RANGE 60 MINUTE SLIDE 1 MINUTE] B2
 ///                   GROUP BY B2.auction);
 /// ```
-fn query5(events: Stream<Event, impl Operator<Event> + 'static>) {
-    let window_descr = EventTimeWindow::sliding(10 * SECOND_MILLIS, 2 * SECOND_MILLIS);
-    let bid = events
-        .add_timestamps(timestamp_gen, watermark_gen)
-        .filter_map(filter_bid);
-
-    // count how bids in each auction, for every window
-    let counts = bid
-        .map(|b| b.auction)
-        .group_by(|a| *a)
-        .map(|_| ())
-        .window(window_descr.clone())
-        .map(|w| w.len())
-        .unkey();
-    counts
-        .window_all(window_descr)
-        .map(|w| *w.max_by_key(|(_, v)| *v).unwrap())
-        .for_each(std::mem::drop)
+fn query5(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    let (size, slide) = config.q5_window_ns;
+    let window_descr = EventTimeWindow::sliding(duration_millis(size), duration_millis(slide));
+    for events in timestamped_runs(events, config) {
+        let bid = events.filter_map(filter_bid);
+
+        // count how bids in each auction, for every window
+        let counts = bid
+            .map(|b| b.auction)
+            .group_by(|a| *a)
+            .map(|_| ())
+            .window(window_descr.clone())
+            .map(|w| w.len())
+            .unkey();
+        counts
+            .window_all(window_descr.clone())
+            .map(|w| *w.max_by_key(|(_, v)| *v).unwrap())
+            .for_each(std::mem::drop)
+    }
 }
 
 /// Query 6: Average Selling Price by Seller
@@ -200,17 +410,19 @@ fn query5(events: Stream<Event, impl Operator<Event> + 'static>) {
 ///       GROUP BY A.id, A.seller) [PARTITION BY A.seller ROWS 10] Q
 /// GROUP BY Q.seller;
 /// ```
-fn query6(events: Stream<Event, impl Operator<Event> + 'static>) {
-    let mut routes = events
-        .route()
-        .add_route(|e| matches!(e, Event::Auction(_)))
-        .add_route(|e| matches!(e, Event::Bid(_)))
-        .build()
-        .into_iter();
-    // let person = event.pop().unwrap().filter_map(filter_person);
-    let auction = routes.next().unwrap().map(unwrap_auction);
-    let bid = routes.next().unwrap().map(unwrap_bid);
-    winning_bids(auction, bid)
+fn query6(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    if config.spec {
+        average_price_by_seller(winning_bids_at_expiry(events, config))
+    } else {
+        let (auction, bid) = auctions_and_bids(events);
+        average_price_by_seller(winning_bids(auction, bid))
+    }
+}
+
+fn average_price_by_seller(
+    winning_bids: Stream<(Auction, Bid), impl Operator<(Auction, Bid)> + 'static>,
+) {
+    winning_bids
         // [PARTITION BY A.seller ROWS 10]
         .map(|(a, b)| (a.seller, b.price))
         .group_by(|(seller, _)| *seller)
@@ -233,19 +445,20 @@ fn query6(events: Stream<Event, impl Operator<Event> + 'static>) {
 /// WHERE B.price = (SELECT MAX(B1.price)
 ///                  FROM BID [RANGE 1 MINUTE SLIDE 1 MINUTE] B1);
 /// ```
-fn query7(events: Stream<Event, impl Operator<Event> + 'static>) {
-    let bid = events
-        .add_timestamps(timestamp_gen, watermark_gen)
-        .filter_map(filter_bid);
-    let window_descr = EventTimeWindow::tumbling(10 * SECOND_MILLIS);
-    bid.map(|b| (b.auction, b.price, b.bidder))
-        .key_by(|_| ())
-        .window(window_descr.clone())
-        .map(|w| *w.max_by_key(|(_, price, _)| price).unwrap())
-        .drop_key()
-        .window_all(window_descr)
-        .map(|w| *w.max_by_key(|(_, price, _)| price).unwrap())
-        .for_each(std::mem::drop)
+fn query7(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    let window_descr = EventTimeWindow::tumbling(duration_millis(config.q7_window_ns));
+    for events in timestamped_runs(events, config) {
+        events
+            .filter_map(filter_bid)
+            .map(|b| (b.auction, b.price, b.bidder))
+            .key_by(|_| ())
+            .window(window_descr.clone())
+            .map(|w| *w.max_by_key(|(_, price, _)| price).unwrap())
+            .drop_key()
+            .window_all(window_descr.clone())
+            .map(|w| *w.max_by_key(|(_, price, _)| price).unwrap())
+            .for_each(std::mem::drop)
+    }
 }
 
 /// Query 8: Monitor New Users
@@ -255,45 +468,401 @@ fn query7(events: Stream<Event, impl Operator<Event> + 'static>) {
 /// FROM Person [RANGE 12 HOUR] P, Auction [RANGE 12 HOUR] A
 /// WHERE P.id = A.seller;
 /// ```
-fn query8(events: Stream<Event, impl Operator<Event> + 'static>) {
-    let window_descr = EventTimeWindow::tumbling(10 * SECOND_MILLIS);
+fn query8(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    let window_descr = EventTimeWindow::tumbling(duration_millis(config.q8_window_ns));
+
+    for events in timestamped_runs(events, config) {
+        let mut routes = events
+            .route()
+            .add_route(|e| matches!(e, Event::Person(_)))
+            .add_route(|e| matches!(e, Event::Auction(_)))
+            .build()
+            .into_iter();
+
+        let person = routes
+            .next()
+            .unwrap()
+            .map(unwrap_person)
+            .map(|p| (p.id, p.name));
+        let auction = routes
+            .next()
+            .unwrap()
+            .map(unwrap_auction)
+            .map(|a| (a.seller, a.reserve));
+
+        person
+            .group_by(|(id, _)| *id)
+            .window(window_descr.clone())
+            .join(auction.group_by(|(seller, _)| *seller))
+            .drop_key()
+            .map(|((id, name), (_, reserve))| (id, name, reserve))
+            .for_each(std::mem::drop)
+    }
+}
+
+/// Query 9: Winning Bids
+///
+/// ```text
+/// SELECT A.*, B.*
+/// FROM Auction A, Bid B
+/// WHERE A.id = B.auction AND B.datetime < A.expires AND A.expires < CURRENT_TIME
+///   AND B.price = (SELECT MAX(B1.price) FROM Bid B1 WHERE B1.auction = A.id ...);
+/// ```
+fn query9(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    if config.spec {
+        winning_bids_at_expiry(events, config).for_each(std::mem::drop)
+    } else {
+        let (auction, bid) = auctions_and_bids(events);
+        winning_bids(auction, bid).for_each(std::mem::drop)
+    }
+}
+
+/// Query 11: User Sessions
+///
+/// ```text
+/// SELECT B.bidder, count(*) as bid_count
+/// FROM Bid B
+/// GROUP BY B.bidder, SESSION(B.dateTime, INTERVAL '10' SECOND);
+/// ```
+fn query11(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    for events in timestamped_runs(events, config) {
+        events
+            .filter_map(filter_bid)
+            .group_by(|b| b.bidder)
+            .map(|_| ())
+            .window(EventTimeWindow::session(duration_millis(config.q11_gap_ns)))
+            .map(|w| w.len())
+            .unkey()
+            .for_each(std::mem::drop)
+    }
+}
+
+/// Query 12: Processing Time Windows
+///
+/// ```text
+/// SELECT B.bidder, count(*) as bid_count
+/// FROM Bid B
+/// GROUP BY B.bidder, TUMBLE(PROCTIME(), INTERVAL '10' SECOND);
+/// ```
+fn query12(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    events
+        .filter_map(filter_bid)
+        .group_by(|b| b.bidder)
+        .map(|_| ())
+        .window(ProcessingTimeWindow::tumbling(Duration::from_nanos(
+            config.q12_window_ns as u64,
+        )))
+        .map(|w| w.len())
+        .unkey()
+        .for_each(std::mem::drop)
+}
+
+/// Query 13: Bounded Side Input Join
+///
+/// ```text
+/// SELECT B.auction, B.bidder, B.price, B.dateTime, S.value
+/// FROM Bid B
+/// JOIN side_input FOR SYSTEM_TIME AS OF B.p_time AS S
+/// ON mod(B.auction, 10000) = S.key;
+/// ```
+fn query13(events: Stream<Event, impl Operator<Event> + 'static>) {
+    let side_input = side_input();
+    events
+        .filter_map(filter_bid)
+        .map(move |b| {
+            let value = side_input[&side_input_key(b.auction)].clone();
+            (b.auction, b.bidder, b.price, b.date_time, value)
+        })
+        .for_each(std::mem::drop)
+}
+
+/// Query 14: Calculation
+///
+/// ```text
+/// SELECT auction, bidder, 0.908 * price as price,
+///     CASE
+///         WHEN HOUR(dateTime) >= 8 AND HOUR(dateTime) <= 18 THEN 'dayTime'
+///         WHEN HOUR(dateTime) <= 6 OR HOUR(dateTime) >= 20 THEN 'nightTime'
+///         ELSE 'otherTime'
+///     END AS bidTimeType,
+///     dateTime, extra, count_char(extra, 'c') AS c_counts
+/// FROM bid
+/// WHERE 0.908 * price > 1000000 AND 0.908 * price < 50000000;
+/// ```
+fn query14(events: Stream<Event, impl Operator<Event> + 'static>) {
+    events
+        .filter_map(filter_bid)
+        .map(|b| {
+            (
+                b.auction,
+                b.bidder,
+                b.price as f32 * 0.908,
+                bid_time_type(b.date_time),
+                b.date_time,
+                b.extra.matches('c').count(),
+            )
+        })
+        .filter(|&(_, _, price, _, _, _)| 1_000_000. < price && price < 50_000_000.)
+        .for_each(std::mem::drop)
+}
+
+/// Start of the window of a bid, and counts of bids, distinct bidders and distinct auctions. Index
+/// 0 counts all bids, indexes 1 to 3 count bids of the respective price rank.
+type BidStatistics = (Timestamp, [usize; 4], [usize; 4], [usize; 4]);
+
+fn bid_statistics<'a>(bids: impl Iterator<Item = &'a Bid>, window: i64) -> BidStatistics {
+    let mut start = Timestamp::MAX;
+    let mut counts = [0; 4];
+    let mut bidders: [HashSet<usize>; 4] = Default::default();
+    let mut auctions: [HashSet<usize>; 4] = Default::default();
+    for b in bids {
+        start = start.min(millis(b.date_time) / window * window);
+        for index in [0, price_rank(b.price)] {
+            counts[index] += 1;
+            bidders[index].insert(b.bidder);
+            auctions[index].insert(b.auction);
+        }
+    }
+    (
+        start,
+        counts,
+        bidders.map(|set| set.len()),
+        auctions.map(|set| set.len()),
+    )
+}
+
+/// Query 15: Bidding Statistics Report
+///
+/// ```text
+/// SELECT
+///      DATE_FORMAT(dateTime, 'yyyy-MM-dd') as `day`,
+///      count(*) AS total_bids,
+///      count(*) filter (where price < 10000) AS rank1_bids,
+///      count(*) filter (where price >= 10000 and price < 1000000) AS rank2_bids,
+///      count(*) filter (where price >= 1000000) AS rank3_bids,
+///      count(distinct bidder) AS total_bidders,
+///      ...
+///      count(distinct auction) AS total_auctions,
+///      ...
+/// FROM bid
+/// GROUP BY DATE_FORMAT(dateTime, 'yyyy-MM-dd');
+/// ```
+fn query15(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    let window = duration_millis(config.statistics_window_ns);
+    for events in timestamped_runs(events, config) {
+        events
+            .filter_map(filter_bid)
+            .window_all(EventTimeWindow::tumbling(window))
+            .map(move |w| bid_statistics(w, window))
+            .for_each(std::mem::drop)
+    }
+}
+
+/// Query 16: Channel Statistics Report
+///
+/// ```text
+/// SELECT
+///     channel,
+///     DATE_FORMAT(dateTime, 'yyyy-MM-dd') as `day`,
+///     ... (as in query 15)
+/// FROM bid
+/// GROUP BY channel, DATE_FORMAT(dateTime, 'yyyy-MM-dd');
+/// ```
+fn query16(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    let window = duration_millis(config.statistics_window_ns);
+    for events in timestamped_runs(events, config) {
+        events
+            .filter_map(filter_bid)
+            .group_by(|b| b.channel.clone())
+            .window(EventTimeWindow::tumbling(window))
+            .map(move |w| bid_statistics(w, window))
+            .unkey()
+            .for_each(std::mem::drop)
+    }
+}
+
+/// Query 17: Auction Statistics Report
+///
+/// ```text
+/// SELECT
+///      auction,
+///      DATE_FORMAT(dateTime, 'yyyy-MM-dd') as `day`,
+///      count(*) AS total_bids,
+///      count(*) filter (where price < 10000) AS rank1_bids,
+///      count(*) filter (where price >= 10000 and price < 1000000) AS rank2_bids,
+///      count(*) filter (where price >= 1000000) AS rank3_bids,
+///      min(price) AS min_price,
+///      max(price) AS max_price,
+///      avg(price) AS avg_price,
+///      sum(price) AS sum_price
+/// FROM bid
+/// GROUP BY auction, DATE_FORMAT(dateTime, 'yyyy-MM-dd');
+/// ```
+fn query17(events: Stream<Event, impl Operator<Event> + 'static>, config: &QueryConfig) {
+    let window = duration_millis(config.statistics_window_ns);
+    for events in timestamped_runs(events, config) {
+        events
+            .filter_map(filter_bid)
+            .group_by(|b| b.auction)
+            .window(EventTimeWindow::tumbling(window))
+            .map(move |w| {
+                let mut start = Timestamp::MAX;
+                let mut counts = [0; 4];
+                let (mut min, mut max, mut sum) = (usize::MAX, 0, 0);
+                for b in w {
+                    start = start.min(millis(b.date_time) / window * window);
+                    counts[0] += 1;
+                    counts[price_rank(b.price)] += 1;
+                    min = min.min(b.price);
+                    max = max.max(b.price);
+                    sum += b.price;
+                }
+                (start, counts, min, max, sum / counts[0], sum)
+            })
+            .unkey()
+            .for_each(std::mem::drop)
+    }
+}
+
+/// Query 18: Find last bid
+///
+/// ```text
+/// SELECT auction, bidder, price, channel, url, dateTime, extra
+///  FROM (SELECT *, ROW_NUMBER() OVER (PARTITION BY bidder, auction ORDER BY dateTime DESC) AS rank_number
+///        FROM bid)
+///  WHERE rank_number <= 1;
+/// ```
+fn query18(events: Stream<Event, impl Operator<Event> + 'static>) {
+    events
+        .filter_map(filter_bid)
+        .group_by(|b| (b.bidder, b.auction))
+        .rich_flat_map({
+            // The time of the last bid of the key
+            let mut last = None;
+            move |(_, b): (_, Bid)| {
+                if last.map_or(true, |last| last <= b.date_time) {
+                    last = Some(b.date_time);
+                    Some(b)
+                } else {
+                    None
+                }
+            }
+        })
+        .drop_key()
+        .for_each(std::mem::drop)
+}
 
+/// Query 19: Auction TOP-10 Price
+///
+/// ```text
+/// SELECT * FROM
+/// (SELECT *, ROW_NUMBER() OVER (PARTITION BY auction ORDER BY price DESC) AS rank_number FROM bid)
+/// WHERE rank_number <= 10;
+/// ```
+fn query19(events: Stream<Event, impl Operator<Event> + 'static>) {
+    events
+        .filter_map(filter_bid)
+        .map(|b| (b.auction, b.bidder, b.price))
+        .group_by(|(auction, _, _)| *auction)
+        .rich_flat_map({
+            // Descending top prices of the auction
+            let mut top: Vec<usize> = Vec::new();
+            move |(_, (auction, bidder, price))| {
+                let position = top.iter().position(|&other| other < price).unwrap_or(top.len());
+                if position < TOP_BIDS {
+                    top.insert(position, price);
+                    top.truncate(TOP_BIDS);
+                    Some((auction, bidder, price, position + 1))
+                } else {
+                    None
+                }
+            }
+        })
+        .drop_key()
+        .for_each(std::mem::drop)
+}
+
+/// Query 20: Expand bid with auction
+///
+/// ```text
+/// SELECT
+///     auction, bidder, price, channel, url, B.dateTime, B.extra,
+///     itemName, description, initialBid, reserve, A.dateTime, expires, seller, category, A.extra
+/// FROM
+///     bid AS B INNER JOIN auction AS A on B.auction = A.id
+/// WHERE A.category = 10;
+/// ```
+fn query20(events: Stream<Event, impl Operator<Event> + 'static>) {
     let mut routes = events
-        .add_timestamps(timestamp_gen, watermark_gen)
         .route()
-        .add_route(|e| matches!(e, Event::Person(_)))
+        .add_route(|e| matches!(e, Event::Bid(_)))
         .add_route(|e| matches!(e, Event::Auction(_)))
         .build()
         .into_iter();
 
-    let person = routes
-        .next()
-        .unwrap()
-        .map(unwrap_person)
-        .map(|p| (p.id, p.name));
+    let bid = routes.next().unwrap().map(unwrap_bid);
     let auction = routes
         .next()
         .unwrap()
         .map(unwrap_auction)
-        .map(|a| (a.seller, a.reserve));
+        .filter(|a| a.category == 10);
 
-    person
-        .group_by(|(id, _)| *id)
-        .window(window_descr)
-        .join(auction.group_by(|(seller, _)| *seller))
+    bid.join(auction, |b| b.auction, |a| a.id)
         .drop_key()
-        .map(|((id, name), (_, reserve))| (id, name, reserve))
         .for_each(std::mem::drop)
 }
 
-fn events(env: &mut StreamEnvironment, tot: usize) -> Stream<Event, impl Operator<Event>> {
-    env.stream_par_iter(move |i, n| {
-        nexmark::EventGenerator::default()
-            .with_offset(i)
-            .with_step(n)
-            .take(tot / n as usize + (i < tot as u64 % n) as usize)
-    })
-    .batch_mode(BatchMode::fixed(BATCH_SIZE))
+/// Query 21: Add channel id
+///
+/// ```text
+/// SELECT
+///     auction, bidder, price, channel,
+///     CASE
+///         WHEN lower(channel) = 'apple' THEN '0'
+///         WHEN lower(channel) = 'google' THEN '1'
+///         WHEN lower(channel) = 'facebook' THEN '2'
+///         WHEN lower(channel) = 'baidu' THEN '3'
+///         ELSE REGEXP_EXTRACT(url, '(&|^)channel_id=([^&]*)', 2)
+///         END
+///     AS channel_id FROM bid
+///     where REGEXP_EXTRACT(url, '(&|^)channel_id=([^&]*)', 2) is not null or
+///           lower(channel) in ('apple', 'google', 'facebook', 'baidu');
+/// ```
+fn query21(events: Stream<Event, impl Operator<Event> + 'static>) {
+    events
+        .filter_map(filter_bid)
+        .filter_map(|b| {
+            channel_id(&b.channel, &b.url).map(|id| (b.auction, b.bidder, b.price, b.channel, id))
+        })
+        .for_each(std::mem::drop)
+}
+
+/// Query 22: Get URL Directories
+///
+/// ```text
+/// SELECT
+///     auction, bidder, price, channel,
+///     SPLIT_INDEX(url, '/', 3) as dir1,
+///     SPLIT_INDEX(url, '/', 4) as dir2,
+///     SPLIT_INDEX(url, '/', 5) as dir3 FROM bid;
+/// ```
+fn query22(events: Stream<Event, impl Operator<Event> + 'static>) {
+    events
+        .filter_map(filter_bid)
+        .map(|b| {
+            let mut dirs = b.url.split('/').skip(3).map(String::from);
+            let mut next = || dirs.next().unwrap_or_default();
+            let (dir1, dir2, dir3) = (next(), next(), next());
+            (b.auction, b.bidder, b.price, b.channel, dir1, dir2, dir3)
+        })
+        .for_each(std::mem::drop)
+}
+
+fn events(
+    env: &mut StreamEnvironment,
+    source: &EventSource,
+) -> Stream<Event, impl Operator<Event>> {
+    noir_extra::nexmark_source(env, source.clone()).batch_mode(BatchMode::fixed(BATCH_SIZE))
 }
 
 fn unwrap_bid(e: Event) -> Bid {
@@ -338,30 +907,72 @@ fn main() {
     env_logger::init();
 
     let (config, args) = EnvironmentConfig::from_args();
-    if args.len() != 2 {
-        panic!("Pass the element count as argument");
+    // Generator and query options such as `--late-fraction 0.1` or `--spec true` follow the
+    // element count and the query
+    let args = Config::from(args.into_iter()).expect(
+        "Pass the element count, the query, and optionally --<option> <value> pairs as arguments",
+    );
+    let n: usize = args.get_as("0").expect("missing element count");
+    let i: usize = args.get_as("1").expect("missing query");
+    let spec: bool = args.get("spec").map_or(false, |spec| {
+        spec.parse().expect("--spec takes true or false")
+    });
+    let mut query_config = if spec {
+        QueryConfig::spec()
+    } else {
+        QueryConfig::default()
+    };
+    if let Some(lateness) = args.get("lateness") {
+        query_config.lateness = lateness.parse().unwrap();
     }
-    let n: usize = args[0].parse().unwrap();
-    let i: usize = args[1].parse().unwrap();
+    // Replay events from a log written by `nexmark-log` with as many partitions as replicas, or
+    // receive them from a `nexmark-producer`
+    let source = match (args.get("event-log"), args.get("tcp-source")) {
+        (Some(dir), _) => EventSource::Log {
+            dir: dir.into(),
+            format: args.get_or("event-log-format", "binary").parse().unwrap(),
+        },
+        (None, Some(addr)) => EventSource::Tcp { addr },
+        (None, None) => EventSource::Generator {
+            config: NEXMarkConfig::new(&args),
+            events: n,
+        },
+    };
     let mut env = StreamEnvironment::new(config);
     env.spawn_remote_workers();
 
     match i {
-        0 => query0(events(&mut env, n)),
-        1 => query1(events(&mut env, n)),
-        2 => query2(events(&mut env, n)),
-        3 => query3(events(&mut env, n)),
-        4 => query4(events(&mut env, n)),
-        5 => query5(events(&mut env, n)),
-        6 => query6(events(&mut env, n)),
-        7 => query7(events(&mut env, n)),
-        8 => query8(events(&mut env, n)),
+        0 => query0(events(&mut env, &source)),
+        1 => query1(events(&mut env, &source)),
+        2 => query2(events(&mut env, &source), &query_config),
+        3 => query3(events(&mut env, &source)),
+        4 => query4(events(&mut env, &source), &query_config),
+        5 => query5(events(&mut env, &source), &query_config),
+        6 => query6(events(&mut env, &source), &query_config),
+        7 => query7(events(&mut env, &source), &query_config),
+        8 => query8(events(&mut env, &source), &query_config),
+        9 => query9(events(&mut env, &source), &query_config),
+        11 => query11(events(&mut env, &source), &query_config),
+        12 => query12(events(&mut env, &source), &query_config),
+        13 => query13(events(&mut env, &source)),
+        14 => query14(events(&mut env, &source)),
+        15 => query15(events(&mut env, &source), &query_config),
+        16 => query16(events(&mut env, &source), &query_config),
+        17 => query17(events(&mut env, &source), &query_config),
+        18 => query18(events(&mut env, &source)),
+        19 => query19(events(&mut env, &source)),
+        20 => query20(events(&mut env, &source)),
+        21 => query21(events(&mut env, &source)),
+        22 => query22(events(&mut env, &source)),
         _ => panic!("Invalid query! {i}"),
     }
 
     let start = Instant::now();
     env.execute();
     println!("q{i}:elapsed:{:?}", start.elapsed());
+    if query_config.lateness == Lateness::SideOutput {
+        println!("q{i}:late_events:{}", LATE_EVENTS.load(Ordering::Relaxed));
+    }
 
     // eprintln!("Query{i}: {:?}", q.get());
 }
@@ -1 +1,58 @@
+use std::path::PathBuf;
 
+use nexmark_generator::config::NEXMarkConfig;
+use nexmark_generator::event::Event;
+use nexmark_generator::event_log::{partition_path, EventLogReader, Format};
+use nexmark_generator::tcp::TcpSource;
+use nexmark_generator::{worker_share, EventGenerator};
+use noir::operator::Operator;
+use noir::prelude::*;
+use noir::Stream;
+
+/// Where NEXMark events come from.
+#[derive(Clone)]
+pub enum EventSource {
+    /// Generate the first `events` events
+    Generator { config: NEXMarkConfig, events: usize },
+    /// Replay an event log from `dir`, with one partition per replica
+    Log { dir: PathBuf, format: Format },
+    /// Receive events from a `nexmark-producer` at `addr`, with one connection per replica
+    Tcp { addr: String },
+}
+
+/// The first `events` NEXMark events, where each replica generates the events of the timely
+/// worker with the same index.
+pub fn nexmark_events(
+    env: &mut StreamEnvironment,
+    config: NEXMarkConfig,
+    events: usize,
+) -> Stream<Event, impl Operator<Event>> {
+    nexmark_source(env, EventSource::Generator { config, events })
+}
+
+/// NEXMark events from `source`, where each replica produces the events of the timely worker
+/// with the same index.
+pub fn nexmark_source(
+    env: &mut StreamEnvironment,
+    source: EventSource,
+) -> Stream<Event, impl Operator<Event>> {
+    env.stream_par_iter(move |index, peers| -> Box<dyn Iterator<Item = Event> + Send> {
+        let (index, peers) = (index as usize, peers as usize);
+        match &source {
+            EventSource::Generator { config, events } => Box::new(
+                EventGenerator::new(config.clone(), index, peers)
+                    .take(worker_share(*events, index, peers)),
+            ),
+            EventSource::Log { dir, format } => {
+                let path = partition_path(dir, index, peers, *format);
+                Box::new(EventLogReader::open(&path, *format).unwrap_or_else(|e| {
+                    panic!("couldn't open event log {}: {e}", path.display())
+                }))
+            }
+            EventSource::Tcp { addr } => Box::new(
+                TcpSource::connect(addr.as_str(), index, peers)
+                    .unwrap_or_else(|e| panic!("couldn't connect to producer {addr}: {e}")),
+            ),
+        }
+    })
+}
@@ -0,0 +1,125 @@
+//! Checks that the noir and timely suites see the same NEXMark events for the same configuration
+//! and number of workers, through the sources each suite reads its events from.
+
+use std::sync::{Arc, Mutex};
+
+use nexmark::source::EventSource as TimelySource;
+use nexmark_generator::config::{Config, NEXMarkConfig};
+use nexmark_generator::event::Event;
+use nexmark_generator::event_log::{write_log, Format};
+use nexmark_generator::worker_share;
+use noir::prelude::*;
+use noir_extra::EventSource;
+use timely::dataflow::operators::{Concat, Inspect, Map, Probe};
+use timely::dataflow::{InputHandle, ProbeHandle};
+
+const EVENTS: usize = 10_000;
+const PEERS: usize = 3;
+
+fn config() -> NEXMarkConfig {
+    let mut config = Config::new();
+    config.insert("events-per-second", "10000".to_string());
+    // Event times start at zero in the timely suite
+    config.insert("base-time", "0".to_string());
+    // Delay some events, as the generator does in out-of-order runs
+    config.insert("out-of-order-group-size", "4".to_string());
+    config.insert("late-fraction", "0.1".to_string());
+    config.insert("bid-jitter-ms", "20".to_string());
+    NEXMarkConfig::new(&config)
+}
+
+/// Serialize events and sort them, as workers interleave their outputs arbitrarily.
+fn serialized(events: impl IntoIterator<Item = Event>) -> Vec<String> {
+    let mut events: Vec<_> = events
+        .into_iter()
+        .map(|event| serde_json::to_string(&event).unwrap())
+        .collect();
+    events.sort();
+    events
+}
+
+/// The events that the timely suite's workers read from `source` and split into bids, auctions
+/// and people, as `examples/timely.rs` does.
+fn timely_events(source: TimelySource) -> Vec<Event> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let shared = results.clone();
+    timely::execute(timely::Configuration::Process(PEERS), move |worker| {
+        let (index, peers) = (worker.index(), worker.peers());
+        let mut input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+        let shared = shared.clone();
+        worker.dataflow::<usize, _, _>(|scope| {
+            let (bids, auctions, people) = nexmark::source::demux(&input.to_stream(scope));
+            bids.map(Event::Bid)
+                .concat(&auctions.map(Event::Auction))
+                .concat(&people.map(Event::Person))
+                .inspect(move |event| shared.lock().unwrap().push(event.clone()))
+                .probe_with(&mut probe);
+        });
+        let events = source
+            .worker_events(index, peers)
+            .take(worker_share(EVENTS, index, peers));
+        for event in events {
+            input.send(event);
+        }
+        input.close();
+        while worker.step() {}
+    })
+    .unwrap();
+    let events = std::mem::take(&mut *results.lock().unwrap());
+    events
+}
+
+/// The events that the noir suite's source emits.
+fn noir_events() -> Vec<Event> {
+    let mut env = StreamEnvironment::new(EnvironmentConfig::local(PEERS as u64));
+    let output = noir_extra::nexmark_events(&mut env, config(), EVENTS).collect_vec();
+    env.execute();
+    output.get().unwrap()
+}
+
+#[test]
+fn noir_and_timely_events_match() {
+    let timely = serialized(timely_events(TimelySource::Generator(config())));
+    assert_eq!(timely.len(), EVENTS);
+    let noir = serialized(noir_events());
+    assert_eq!(noir.len(), EVENTS);
+    assert!(noir == timely);
+}
+
+/// The events that the noir suite's source replays from an event log in `dir`.
+fn noir_replayed_events(dir: &std::path::Path, format: Format) -> Vec<Event> {
+    let mut env = StreamEnvironment::new(EnvironmentConfig::local(PEERS as u64));
+    let source = EventSource::Log {
+        dir: dir.into(),
+        format,
+    };
+    let output = noir_extra::nexmark_source(&mut env, source).collect_vec();
+    env.execute();
+    output.get().unwrap()
+}
+
+#[test]
+fn replayed_events_match() {
+    let generated = serialized(timely_events(TimelySource::Generator(config())));
+    for format in [Format::Binary, Format::Json] {
+        let dir =
+            std::env::temp_dir().join(format!("noir-event-log-{}-{format:?}", std::process::id()));
+        write_log(&config(), EVENTS, PEERS, &dir, format).unwrap();
+        let timely = serialized(timely_events(TimelySource::Log {
+            dir: dir.clone(),
+            format,
+            base_time_ns: 0,
+        }));
+        let noir = serialized(noir_replayed_events(&dir, format));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            timely == generated,
+            "timely replayed a {format:?} log with changed events"
+        );
+        assert!(
+            noir == generated,
+            "noir replayed a {format:?} log with changed events"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+use nexmark_generator::event::{Event, Person};
+use nexmark_generator::event_log::{partition_path, partitions, EventLogReader, Format};
+use rayon::prelude::*;
+
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+/// Replay the partitions of an event log written by `nexmark-log` in parallel.
+fn events(dir: &Path, format: Format) -> impl ParallelIterator<Item = Event> + '_ {
+    let peers = partitions(dir, format).expect("couldn't find event log");
+    (0..peers).into_par_iter().flat_map_iter(move |index| {
+        EventLogReader::open(&partition_path(dir, index, peers, format), format)
+            .expect("couldn't open event log")
+    })
+}
+
+/// Query 0: Passthrough
+fn query0(events: impl ParallelIterator<Item = Event>) -> usize {
+    events.count()
+}
+
+/// Query 1: Currency Conversion
+fn query1(events: impl ParallelIterator<Item = Event>) -> usize {
+    events
+        .filter_map(|e| match e {
+            Event::Bid(b) => Some((b.auction, (b.price as f32 * 0.908) as usize, b.bidder)),
+            _ => None,
+        })
+        .count()
+}
+
+/// Query 2: Selection
+fn query2(events: impl ParallelIterator<Item = Event>) -> usize {
+    events
+        .filter_map(|e| match e {
+            Event::Bid(b) if b.auction % 123 == 0 => Some((b.auction, b.price)),
+            _ => None,
+        })
+        .count()
+}
+
+/// Query 3: Local Item Suggestion
+fn query3(events: impl ParallelIterator<Item = Event>) -> usize {
+    let (people, auctions): (Vec<_>, Vec<_>) = events
+        .filter_map(|e| match e {
+            Event::Person(p) if ["OR", "ID", "CA"].contains(&p.state.as_str()) => Some((Some(p), None)),
+            Event::Auction(a) if a.category == 10 => Some((None, Some(a))),
+            _ => None,
+        })
+        .unzip();
+    let people: HashMap<usize, Person> = people.into_iter().flatten().map(|p| (p.id, p)).collect();
+    auctions
+        .into_par_iter()
+        .flatten()
+        .filter_map(|a| people.get(&a.seller).map(|p| (p.name.clone(), p.city.clone(), p.state.clone(), a.id)))
+        .count()
+}
+
+fn main() {
+    env_logger::init();
+    if std::env::args().len() != 3 && std::env::args().len() != 4 {
+        panic!("Pass the event log directory, the query and optionally the log format as arguments");
+    }
+    let dir = std::env::args().nth(1).unwrap();
+    let i: usize = std::env::args().nth(2).unwrap().parse().unwrap();
+    // Only the queries without windows or state across events are ported to rayon
+    if i > 3 {
+        eprintln!("rayon-nexmark implements queries 0 to 3, not query {i}");
+        std::process::exit(2);
+    }
+    let format: Format = std::env::args().nth(3).map_or(Format::Binary, |f| f.parse().unwrap());
+    let events = events(Path::new(&dir), format);
+
+    let start = Instant::now();
+
+    let result = match i {
+        0 => query0(events),
+        1 => query1(events),
+        2 => query2(events),
+        3 => query3(events),
+        _ => unreachable!(),
+    };
+
+    let elapsed = start.elapsed();
+    eprintln!("Output: {:?}", result);
+    println!("{:?}", elapsed);
+}
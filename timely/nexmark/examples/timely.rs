@@ -14,37 +14,35 @@ extern crate abomonation;
 // #[global_allocator]
 // static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-use std::hash::Hash;
-use std::hash::Hasher;
 use std::sync::atomic::AtomicBool;
 use std::time::Instant;
 
-use clap::{Arg, App};
+use clap::{Arg, App, AppSettings};
 
 use streaming_harness::util::ToNanos;
 
 use timely::dataflow::{InputHandle, ProbeHandle};
 use timely::dataflow::operators::{Probe, Capture, capture::Replay};
 
-use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::Operator;
 use timely::dataflow::operators::Broadcast;
+use timely::dataflow::operators::Inspect;
 use timely::dataflow::Stream;
 use timely::dataflow::Scope;
 use timely::ExchangeData;
 
-use dynamic_scaling_mechanism::{ControlInst, Control};
+use dynamic_scaling_mechanism::{ControlInst, Control, ControlErrorPolicy, StatefulConfig};
+use dynamic_scaling_mechanism::assigner::{AnyAssigner, BinAssigner, ConsistentHash, Prefix, Range};
+use dynamic_scaling_mechanism::checkpoint::{CheckpointConfig, latest_checkpoint};
+use dynamic_scaling_mechanism::metrics::Metrics;
 use dynamic_scaling_mechanism::operator::StatefulOperator;
 
-use nexmark::event::Event;
+use nexmark::EventGenerator;
+use nexmark::config::RateShape;
+use nexmark::event_log::Format;
+use nexmark::source::EventSource;
 use nexmark::tools::ExperimentMapMode;
-use nexmark::queries::{NexmarkInput, NexmarkTimer};
-
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut h: ::fnv::FnvHasher = Default::default();
-    t.hash(&mut h);
-    h.finish()
-}
+use nexmark::queries::{LateEvents, Lateness, NexmarkInput, NexmarkTimer, QueryConfig};
 
 #[allow(dead_code)]
 fn verify<S: Scope, T: ExchangeData+Ord+::std::fmt::Debug>(correct: &Stream<S, T>, output: &Stream<S, T>) -> Stream<S, ()> {
@@ -86,23 +84,108 @@ fn main() {
 
     let matches = App::new("word_count")
         .arg(Arg::with_name("rate").long("rate").takes_value(true).required(true))
+        .arg(Arg::with_name("rate_shape").long("rate_shape").takes_value(true).required(false)
+            .validator(|arg| arg.parse::<RateShape>().map(|_| ())).requires_if("trace", "rate_trace"))
+        .arg(Arg::with_name("next_rate").long("next_rate").takes_value(true).required(false)
+            .validator(|arg| arg.parse::<f64>().map(|_| ()).map_err(|e| format!("couldn't parse next_rate: {}", e))))
+        .arg(Arg::with_name("rate_period").long("rate_period").takes_value(true).required(false)
+            .validator(|arg| arg.parse::<usize>().map(|_| ()).map_err(|e| format!("couldn't parse rate_period: {}", e))))
+        .arg(Arg::with_name("rate_trace").long("rate_trace").takes_value(true).required(false))
+        .arg(Arg::with_name("out_of_order_group_size").long("out_of_order_group_size").takes_value(true).required(false))
+        .arg(Arg::with_name("late_fraction").long("late_fraction").takes_value(true).required(false))
+        .arg(Arg::with_name("max_lateness_ms").long("max_lateness_ms").takes_value(true).required(false))
+        .arg(Arg::with_name("person_jitter_ms").long("person_jitter_ms").takes_value(true).required(false))
+        .arg(Arg::with_name("auction_jitter_ms").long("auction_jitter_ms").takes_value(true).required(false))
+        .arg(Arg::with_name("bid_jitter_ms").long("bid_jitter_ms").takes_value(true).required(false))
+        .arg(Arg::with_name("lateness").long("lateness").takes_value(true).required(false))
+        .arg(Arg::with_name("spec").long("spec"))
+        .arg(Arg::with_name("event_log").long("event_log").takes_value(true).required(false))
+        .arg(Arg::with_name("event_log_format").long("event_log_format").takes_value(true).required(false).requires("event_log")
+            .possible_values(&["binary", "framed", "json"]))
+        .arg(Arg::with_name("tcp_source").long("tcp_source").takes_value(true).required(false).conflicts_with("event_log"))
         .arg(Arg::with_name("duration").long("duration").takes_value(true).required(true))
         .arg(Arg::with_name("migration").long("migration").takes_value(true).required(true))
         .arg(Arg::with_name("time_dilation").long("time_dilation").takes_value(true).required(false))
+        .arg(Arg::with_name("bin_shift").long("bin_shift").takes_value(true).required(false))
+        .arg(Arg::with_name("state_chunk_size").long("state_chunk_size").takes_value(true).required(false))
+        .arg(Arg::with_name("bin_assigner").long("bin_assigner").takes_value(true).required(false))
+        .arg(Arg::with_name("static_partitioning").long("static_partitioning"))
+        .arg(Arg::with_name("checkpoint_dir").long("checkpoint_dir").takes_value(true).required(false))
+        .arg(Arg::with_name("checkpoint_interval").long("checkpoint_interval").takes_value(true).required(false).requires("checkpoint_dir"))
+        .arg(Arg::with_name("restore").long("restore").requires("checkpoint_dir"))
         .arg(Arg::with_name("queries").long("queries").takes_value(true).required(true).multiple(true).value_delimiter(" "))
         .arg(Arg::with_name("timely").multiple(true))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(nexmark::tools::validate_plan_subcommand())
         .get_matches();
+
+    if let Some(matches) = matches.subcommand_matches("validate-plan") {
+        ::std::process::exit(nexmark::tools::validate_plan(matches));
+    }
+
     let timely_args = matches.values_of("timely").map_or(Vec::new(), |vs| vs.map(String::from).collect());
 
 
     let rate: u64 = matches.value_of("rate").expect("rate absent").parse::<u64>().expect("couldn't parse rate");
 
+    // The rate varies over time between `rate` and `next_rate`, or follows a trace, and events may
+    // arrive out of order
+    let generator_config: Vec<(&str, String)> = [
+        ("rate_shape", "rate-shape"), ("next_rate", "next-event-rate"), ("rate_period", "rate-period"), ("rate_trace", "rate-trace"),
+        ("out_of_order_group_size", "out-of-order-group-size"), ("late_fraction", "late-fraction"), ("max_lateness_ms", "max-lateness-ms"),
+        ("person_jitter_ms", "person-jitter-ms"), ("auction_jitter_ms", "auction-jitter-ms"), ("bid_jitter_ms", "bid-jitter-ms"),
+    ].iter()
+        .filter_map(|&(arg, key)| matches.value_of(arg).map(|value| (key, value.to_string())))
+        .collect();
+
+    // Windows of seconds produce results during short runs, `--spec` selects the NEXMark windows
+    let mut query_config = if matches.is_present("spec") { QueryConfig::spec() } else { QueryConfig::default() };
+    query_config.lateness = matches.value_of("lateness").map_or(Lateness::Drop, |arg| arg.parse().expect("couldn't parse lateness"));
+
+    // Replay events from a log written by `nexmark-log` with the same rate and number of workers
+    let event_log: Option<::std::path::PathBuf> = matches.value_of("event_log").map(::std::path::PathBuf::from);
+    let event_log_format: Format = matches.value_of("event_log_format").map_or(Format::Binary, |arg| arg.parse().expect("couldn't parse event_log_format"));
+    // Receive events from a `nexmark-producer` at this address, which paces them at the same rate
+    let tcp_source: Option<String> = matches.value_of("tcp_source").map(String::from);
+
     let duration_ns: u64 = matches.value_of("duration").expect("duration absent").parse::<u64>().expect("couldn't parse duration") * 1_000_000_000;
 
     let map_mode: ExperimentMapMode = matches.value_of("migration").expect("migration file absent").parse().unwrap();
 
     let time_dilation = matches.value_of("time_dilation").map_or(1, |arg| arg.parse().unwrap_or(1));
 
+    let bin_shift = matches.value_of("bin_shift").map_or(::dynamic_scaling_mechanism::DEFAULT_BIN_SHIFT, |arg| arg.parse().expect("couldn't parse bin_shift"));
+    // Prefix and ring partitioning expect hashed keys, range partitioning splits the raw ids up to
+    // `max_key` evenly
+    let bin_assigner: Vec<_> = matches.value_of("bin_assigner").unwrap_or("prefix").split(':').collect();
+    let bin_assigner: AnyAssigner = match &bin_assigner[..] {
+        ["prefix"] => Prefix.into(),
+        ["range", max_key] => Range::uniform(0, max_key.parse().expect("couldn't parse max key"), bin_shift).into(),
+        ["ring", virtual_nodes] => ConsistentHash::new(bin_shift, virtual_nodes.parse().expect("couldn't parse virtual nodes")).into(),
+        other => panic!("unknown bin assigner {:?}, expected prefix, range:<max_key> or ring:<virtual_nodes>", other),
+    };
+    let mut stateful_config = StatefulConfig::new(bin_shift).with_control_error_policy(ControlErrorPolicy::Reject).with_bin_assigner(bin_assigner);
+    if let Some(state_chunk_size) = matches.value_of("state_chunk_size") {
+        stateful_config = stateful_config.with_state_chunk_size(state_chunk_size.parse().expect("couldn't parse state_chunk_size"));
+    }
+    if matches.is_present("static_partitioning") {
+        stateful_config = stateful_config.with_static_partitioning(true);
+    }
+
+    // Checkpoint every `checkpoint_interval` seconds, and restore from the latest complete checkpoint
+    let checkpoint_interval_ns: Option<u64> = matches.value_of("checkpoint_interval").map(|arg| arg.parse::<u64>().expect("couldn't parse checkpoint_interval") * 1_000_000_000);
+    let mut restore_time = None;
+    if let Some(checkpoint_dir) = matches.value_of("checkpoint_dir") {
+        let mut checkpoint_config = CheckpointConfig::new(checkpoint_dir);
+        if matches.is_present("restore") {
+            let checkpoint = latest_checkpoint::<usize>(checkpoint_config.dir()).expect("couldn't read checkpoints").expect("no complete checkpoint");
+            println!("restore\t{}\t{}", checkpoint.sequence, checkpoint.time);
+            checkpoint_config = checkpoint_config.with_restore(checkpoint.sequence);
+            restore_time = Some(checkpoint.time);
+        }
+        stateful_config = stateful_config.with_checkpoint(checkpoint_config);
+    }
+
     let queries: Vec<_> = matches.values_of("queries").unwrap().map(String::from).collect();
 
     assert_eq!(queries.len(), 1);
@@ -123,6 +206,12 @@ fn main() {
             FIRST.store(true, std::sync::atomic::Ordering::Relaxed);
         }
 
+        let metrics = Metrics::new();
+        let stateful_config = stateful_config.clone().with_metrics(metrics.clone());
+        let late_events = LateEvents::default();
+        // The number of late events per query, from their side outputs
+        let late_counts = std::rc::Rc::new(std::cell::RefCell::new(std::collections::HashMap::<String, usize>::new()));
+
         // Declare re-used input, control and probe handles.
         let mut input = InputHandle::new();
         let mut control_input = InputHandle::new();
@@ -146,6 +235,9 @@ fn main() {
                 people: &people,
                 closed_auctions: &closed_auctions,
                 closed_auctions_flex: &closed_auctions_flex,
+                stateful_config: stateful_config.clone(),
+                config: query_config,
+                late_events: &late_events,
             };
 
             let nexmark_timer = NexmarkTimer {
@@ -155,39 +247,7 @@ fn main() {
             eprintln!("starting {index:02}[{peers:02}]");
 
             worker.dataflow(|scope: &mut ::timely::dataflow::scopes::Child<_, usize>| {
-                use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
-                let mut demux = OperatorBuilder::new("NEXMark demux".to_string(), scope.clone());
-
-                let mut input = demux.new_input(&input.to_stream(scope), Pipeline);
-
-                let (mut b_out, bids_stream) = demux.new_output();
-                let (mut a_out, auctions_stream) = demux.new_output();
-                let (mut p_out, people_stream) = demux.new_output();
-
-                let mut demux_buffer = Vec::new();
-
-                demux.build(move |_capability| {
-                    move |_frontiers| {
-                        let mut b_out = b_out.activate();
-                        let mut a_out = a_out.activate();
-                        let mut p_out = p_out.activate();
-
-                        input.for_each(|time, data| {
-                            data.swap(&mut demux_buffer);
-                            let mut b_session = b_out.session(&time);
-                            let mut a_session = a_out.session(&time);
-                            let mut p_session = p_out.session(&time);
-
-                            for datum in demux_buffer.drain(..) {
-                                match datum {
-                                    nexmark::event::Event::Bid(b) => { b_session.give(b) },
-                                    nexmark::event::Event::Auction(a) => { a_session.give(a) },
-                                    nexmark::event::Event::Person(p) => { p_session.give(p) },
-                                }
-                            }
-                        });
-                    }
-                });
+                let (bids_stream, auctions_stream, people_stream) = ::nexmark::source::demux(&input.to_stream(scope));
 
                 bids_stream.capture_into(bids.clone());
                 auctions_stream.capture_into(auctions.clone());
@@ -208,8 +268,9 @@ fn main() {
             if queries.iter().any(|x| *x == "q0-flex") {
                 worker.dataflow(|scope| {
                     let control = Some(control.clone()).replay_into(scope);
+                    let ordered = stateful_config.bin_assigner().ordered();
                     input.to_stream(scope)
-                        .distribute(&control, |e| calculate_hash(&e.id()), "q0-flex")
+                        .distribute(&control, stateful_config.clone(), move |e| ::nexmark::partition_key(&e.id(), ordered), "q0-flex")
                         .probe_with(&mut probe);
                 });
             }
@@ -257,7 +318,7 @@ fn main() {
             }
 
             // Intermission: Close some auctions.
-            if queries.iter().any(|x| *x == "q4" || *x == "q6") {
+            if queries.iter().any(|x| *x == "q4" || *x == "q6" || *x == "q9") {
                 worker.dataflow(|scope| {
                     ::nexmark::queries::q4_q6_common(&nexmark_input, nexmark_timer, scope)
                         .capture_into(nexmark_input.closed_auctions.clone());
@@ -265,7 +326,7 @@ fn main() {
             }
 
             // Intermission: Close some auctions (using stateful).
-            if queries.iter().any(|x| *x == "q4-flex" || *x == "q6-flex") {
+            if queries.iter().any(|x| *x == "q4-flex" || *x == "q6-flex" || *x == "q9-flex") {
                 worker.dataflow(|scope| {
                     ::nexmark::queries::q4_q6_common_flex(&nexmark_input, nexmark_timer, scope)
                         .capture_into(nexmark_input.closed_auctions_flex.clone());
@@ -285,20 +346,16 @@ fn main() {
             }
 
             if queries.iter().any(|x| *x == "q5") {
-                // 60s windows, ticking in 1s intervals
-                // NEXMark default is 60 minutes, ticking in one minute intervals
-                let window_slice_count = 5;
-                let window_slide_ns = 2_000_000_000;
+                let (window_size_ns, window_slide_ns) = query_config.q5_window_ns;
+                let window_slice_count = window_size_ns / window_slide_ns;
                 worker.dataflow(|scope| {
                     ::nexmark::queries::q5(&nexmark_input, nexmark_timer, scope, window_slice_count, window_slide_ns).probe_with(&mut probe);
                 });
             }
 
             if queries.iter().any(|x| *x == "q5-flex") {
-                // 60s windows, ticking in 1s intervals
-                // NEXMark default is 60 minutes, ticking in one minute intervals
-                let window_slice_count = 5;
-                let window_slide_ns = 2_000_000_000;
+                let (window_size_ns, window_slide_ns) = query_config.q5_window_ns;
+                let window_slice_count = window_size_ns / window_slide_ns;
                 worker.dataflow(|scope| {
                     ::nexmark::queries::q5_flex(&nexmark_input, nexmark_timer, scope, window_slice_count, window_slide_ns).probe_with(&mut probe);
                 });
@@ -319,31 +376,213 @@ fn main() {
 
             if queries.iter().any(|x| *x == "q7") {
                 worker.dataflow(|scope| {
-                    // Window ticks every 10 seconds.
-                    // NEXMark default is different: ticks every 60s
-                    let window_size_ns = 10_000_000_000;
+                    let window_size_ns = query_config.q7_window_ns;
                     ::nexmark::queries::q7(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
                 });
             }
 
             if queries.iter().any(|x| *x == "q7-flex") {
                 worker.dataflow(|scope| {
-                    // Window ticks every 10 seconds.
-                    // NEXMark default is different: ticks every 60s
-                    let window_size_ns = 10_000_000_000;
+                    let window_size_ns = query_config.q7_window_ns;
                     ::nexmark::queries::q7_flex(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
                 });
             }
 
             if queries.iter().any(|x| *x == "q8") {
                 worker.dataflow(|scope| {
-                    ::nexmark::queries::q8(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                    ::nexmark::queries::q8(&nexmark_input, nexmark_timer, scope, query_config.q8_window_ns).probe_with(&mut probe);
                 });
             }
 
             if queries.iter().any(|x| *x == "q8-flex") {
                 worker.dataflow(|scope| {
-                    ::nexmark::queries::q8_flex(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                    ::nexmark::queries::q8_flex(&nexmark_input, nexmark_timer, scope, query_config.q8_window_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q9: Find the winning bid of each auction.
+            if queries.iter().any(|x| *x == "q9") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q9(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q9-flex: Find the winning bid of each auction.
+            if queries.iter().any(|x| *x == "q9-flex") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q9_flex(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q10: Log bids to files, one per worker and window.
+            if queries.iter().any(|x| *x == "q10") {
+                worker.dataflow(|scope| {
+                    // Window ticks every 10 seconds.
+                    let window_size_ns = 10_000_000_000;
+                    let dir = ::std::env::temp_dir().join("nexmark-q10");
+                    ::nexmark::queries::q10(&nexmark_input, nexmark_timer, scope, dir, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q11: Count bids per bidder session.
+            if queries.iter().any(|x| *x == "q11") {
+                worker.dataflow(|scope| {
+                    let session_gap_ns = query_config.q11_gap_ns;
+                    ::nexmark::queries::q11(&nexmark_input, nexmark_timer, scope, session_gap_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q11-flex: Count bids per bidder session.
+            if queries.iter().any(|x| *x == "q11-flex") {
+                worker.dataflow(|scope| {
+                    let session_gap_ns = query_config.q11_gap_ns;
+                    ::nexmark::queries::q11_flex(&nexmark_input, nexmark_timer, scope, session_gap_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q12: Count bids per bidder in processing-time windows.
+            if queries.iter().any(|x| *x == "q12") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.q12_window_ns;
+                    ::nexmark::queries::q12(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q12-flex: Count bids per bidder in processing-time windows.
+            if queries.iter().any(|x| *x == "q12-flex") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.q12_window_ns;
+                    ::nexmark::queries::q12_flex(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q13: Join bids with a bounded side input.
+            if queries.iter().any(|x| *x == "q13") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q13(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q14: Convert and classify some bids.
+            if queries.iter().any(|x| *x == "q14") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q14(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q15: Report bid statistics.
+            if queries.iter().any(|x| *x == "q15") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.statistics_window_ns;
+                    ::nexmark::queries::q15(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q15-flex: Report bid statistics.
+            if queries.iter().any(|x| *x == "q15-flex") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.statistics_window_ns;
+                    ::nexmark::queries::q15_flex(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q16: Report bid statistics per channel.
+            if queries.iter().any(|x| *x == "q16") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.statistics_window_ns;
+                    ::nexmark::queries::q16(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q16-flex: Report bid statistics per channel.
+            if queries.iter().any(|x| *x == "q16-flex") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.statistics_window_ns;
+                    ::nexmark::queries::q16_flex(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q17: Report bid statistics per auction.
+            if queries.iter().any(|x| *x == "q17") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.statistics_window_ns;
+                    ::nexmark::queries::q17(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q17-flex: Report bid statistics per auction.
+            if queries.iter().any(|x| *x == "q17-flex") {
+                worker.dataflow(|scope| {
+                    let window_size_ns = query_config.statistics_window_ns;
+                    ::nexmark::queries::q17_flex(&nexmark_input, nexmark_timer, scope, window_size_ns).probe_with(&mut probe);
+                });
+            }
+
+            // Q18: Find the last bid per bidder and auction.
+            if queries.iter().any(|x| *x == "q18") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q18(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q18-flex: Find the last bid per bidder and auction.
+            if queries.iter().any(|x| *x == "q18-flex") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q18_flex(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q19: Find the top bids per auction.
+            if queries.iter().any(|x| *x == "q19") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q19(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q19-flex: Find the top bids per auction.
+            if queries.iter().any(|x| *x == "q19-flex") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q19_flex(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q20: Join bids with auctions of a category.
+            if queries.iter().any(|x| *x == "q20") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q20(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q20-flex: Join bids with auctions of a category.
+            if queries.iter().any(|x| *x == "q20-flex") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q20_flex(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q21: Extract channel ids of bids.
+            if queries.iter().any(|x| *x == "q21") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q21(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Q22: Split URLs of bids into directories.
+            if queries.iter().any(|x| *x == "q22") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q22(&nexmark_input, nexmark_timer, scope).probe_with(&mut probe);
+                });
+            }
+
+            // Count the late events in the side outputs of the queries.
+            if query_config.lateness == Lateness::SideOutput {
+                worker.dataflow(|scope| {
+                    for (query, late) in nexmark_input.side_outputs(scope) {
+                        let late_counts = late_counts.clone();
+                        late
+                            .inspect_batch(move |_time, data| *late_counts.borrow_mut().entry(query.clone()).or_insert(0) += data.len())
+                            .probe_with(&mut probe);
+                    }
                 });
             }
         }
@@ -353,14 +592,27 @@ fn main() {
         // auction_proportion*sec_in_12h
         // config1.insert("in-flight-auctions", format!("{}", rate * 2592));
         config1.insert("events-per-second", format!("{}", rate));
-        config1.insert("first-event-number", format!("{}", index));
-        let mut config = nexmark::config::NEXMarkConfig::new(&config1);
-
-        let mut instructions: Vec<(u64, Vec<ControlInst>)> = map_mode.instructions(peers, duration_ns).unwrap();
+        for &(key, ref value) in generator_config.iter() {
+            config1.insert(key, value.clone());
+        }
+        // Event times start with the computation, such that they are comparable to input times
+        config1.insert("base-time", "0".to_string());
+        let config = nexmark::config::NEXMarkConfig::new(&config1);
+
+        let mut instructions: Vec<(u64, Vec<ControlInst>)> = map_mode.instructions(peers, duration_ns, bin_shift).unwrap();
+        if let Some(checkpoint_interval_ns) = checkpoint_interval_ns {
+            let mut checkpoint_ns = checkpoint_interval_ns;
+            while checkpoint_ns < duration_ns {
+                instructions.push((checkpoint_ns, vec![ControlInst::Checkpoint]));
+                checkpoint_ns += checkpoint_interval_ns;
+            }
+            // Stable sort keeps migrations before checkpoints at the same time
+            instructions.sort_by_key(|&(ts, _)| ts);
+        }
 
         if index == 0 {
             println!("time_dilation\t{}", time_dilation);
-            println!("bin_shift\t{}", ::dynamic_scaling_mechanism::BIN_SHIFT);
+            println!("bin_shift\t{}", bin_shift);
 
             for instruction in instructions.iter().take(10) {
                 // Format instructions first to be able to truncate the string representation
@@ -392,25 +644,26 @@ fn main() {
 
         let timer = ::std::time::Instant::now();
 
-        // Establish a start of the computation.
-        let elapsed_ns = timer.elapsed().to_nanos();
-        config.base_time_ns = elapsed_ns as usize;
-
         assert!(worker.peers() < 256);
 
         let input_times = {
-            let config = config.clone();
+            let config = EventGenerator::new(config.clone(), index, peers).config().clone();
             move || nexmark::config::NexMarkInputTimes::new(config.clone(), duration_ns, time_dilation, peers)
         };
 
+        let source = match (&event_log, &tcp_source) {
+            (&Some(ref dir), _) => EventSource::Log { dir: dir.clone(), format: event_log_format, base_time_ns: config.base_time_ns },
+            (_, &Some(ref addr)) => EventSource::Tcp { addr: addr.clone(), base_time_ns: config.base_time_ns },
+            (&None, &None) => EventSource::Generator(config),
+        };
+        let mut events = source.worker_events(index, peers);
+
         let mut output_metric_collector =
             ::streaming_harness::output::default::hdrhist_timeline_collector(
                 input_times(),
                 0, 1_000_000_000, duration_ns - 1_000_000_000, duration_ns,
                 250_000_000);
 
-        let mut events_so_far = 0;
-
         let mut input_times_gen =
             ::streaming_harness::input::SyntheticInputTimeGenerator::new(input_times());
 
@@ -467,12 +720,13 @@ fn main() {
 
             if let Some(it) = input_times_gen.iter_until(target_ns) {
                 let input = input.as_mut().unwrap();
+                // Events before a restored checkpoint are already reflected in the state
+                let replay = restore_time.map_or(true, |time| *input.time() >= time);
                 for _t in it {
-                    input.send(
-                        Event::create(
-                            events_so_far,
-                            &mut config));
-                    events_so_far += worker.peers();
+                    let event = events.next().expect("event source holds fewer events than the run");
+                    if replay {
+                        input.send(event);
+                    }
                 }
                 input.advance_to(target_ns as usize + count);
                 if let Some(control_input) = control_input.as_mut() {
@@ -492,6 +746,22 @@ fn main() {
             }
         }
 
+        // Report the late events each query saw on this worker
+        for (query, count) in late_counts.borrow().iter() {
+            println!("late_events\t{}\t{}\t{}", index, query, count);
+        }
+
+        // Report the records each operator's bins received from this worker, to compare bin assigners
+        for (operator, bin, bin_metrics) in metrics.drain_bins() {
+            println!("bin_records\t{}\t{}\t{}\t{}", index, operator, *bin, bin_metrics.records);
+        }
+
+        // Report the state each operator's migrations sent from this worker
+        for (operator, migration) in metrics.drain_migrations() {
+            let duration_ns = migration.finished.map_or(0, |finished| (finished - migration.started).to_nanos());
+            println!("migration\t{}\t{}\t{}\t{}\t{}\t{}\t{}", index, operator, migration.sequence, migration.bins, migration.records, migration.bytes, duration_ns);
+        }
+
         output_metric_collector.into_inner()
     }).expect("unsuccessful execution").join().into_iter().map(|x| x.unwrap()).collect();
 
@@ -17,7 +17,7 @@ use std::cell::RefCell;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
-use clap::{Arg, App};
+use clap::{Arg, App, AppSettings};
 
 use rand::{Rng, SeedableRng};
 use rand::rngs::SmallRng;
@@ -32,7 +32,8 @@ use timely::dataflow::Stream;
 use timely::dataflow::Scope;
 use timely::ExchangeData;
 
-use dynamic_scaling_mechanism::Control;
+use dynamic_scaling_mechanism::{Control, ControlErrorPolicy, StatefulConfig};
+use dynamic_scaling_mechanism::metrics::Metrics;
 use dynamic_scaling_mechanism::notificator::{Notify, TotalOrderFrontierNotificator};
 use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
 
@@ -122,10 +123,19 @@ fn main() {
         .arg(Arg::with_name("migration").long("migration").takes_value(true).required(true))
         .arg(Arg::with_name("domain").long("domain").takes_value(true).required(true))
         .arg(Arg::with_name("validate").long("validate"))
+        .arg(Arg::with_name("bin_shift").long("bin_shift").takes_value(true))
+        .arg(Arg::with_name("state_chunk_size").long("state_chunk_size").takes_value(true))
+        .arg(Arg::with_name("static_partitioning").long("static_partitioning"))
         .arg(Arg::with_name("timely").multiple(true))
         .arg(Arg::with_name("backend").long("backend").takes_value(true).possible_values(&["hashmap", "hashmapnative", "vec", "vecnative"]).default_value("hashmap"))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(nexmark::tools::validate_plan_subcommand())
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("validate-plan") {
+        ::std::process::exit(nexmark::tools::validate_plan(matches));
+    }
+
     let rate: u64 = matches.value_of("rate").expect("rate absent").parse::<u64>().expect("couldn't parse rate");
 
     let duration_ns: u64 = matches.value_of("duration").expect("duration absent").parse::<u64>().expect("couldn't parse duration") * 1_000_000_000;
@@ -136,6 +146,15 @@ fn main() {
 
     let validate: bool = matches.is_present("validate");
 
+    let bin_shift = matches.value_of("bin_shift").map_or(::dynamic_scaling_mechanism::DEFAULT_BIN_SHIFT, |arg| arg.parse().expect("couldn't parse bin_shift"));
+    let mut stateful_config = StatefulConfig::new(bin_shift).with_control_error_policy(ControlErrorPolicy::Reject);
+    if let Some(state_chunk_size) = matches.value_of("state_chunk_size") {
+        stateful_config = stateful_config.with_state_chunk_size(state_chunk_size.parse().expect("couldn't parse state_chunk_size"));
+    }
+    if matches.is_present("static_partitioning") {
+        stateful_config = stateful_config.with_static_partitioning(true);
+    }
+
     let backend: Backend = match matches.value_of("backend").expect("backend missing") {
         "hashmap" => Backend::HashMap,
         "hashmapnative" => Backend::HashMapNative,
@@ -155,6 +174,9 @@ fn main() {
         let peers = worker.peers();
         let index = worker.index();
 
+        let metrics = Metrics::new();
+        let stateful_config = stateful_config.clone().with_metrics(metrics.clone());
+
         // Declare re-used input, control and probe handles.
         let mut input: Handle<_, ()> = InputHandle::new();
         let mut control_input = InputHandle::new();
@@ -207,11 +229,11 @@ fn main() {
                                 match backend {
                                     Backend::Vector => {
                                         let mut session = output.session(cap);
-                                        let max_number = (key_space >> ::dynamic_scaling_mechanism::BIN_SHIFT).next_power_of_two();
+                                        let max_number = (key_space >> bin_shift).next_power_of_two();
                                         println!("max_number: {}", max_number);
-                                        let bin_count = 1 << ::dynamic_scaling_mechanism::BIN_SHIFT;
+                                        let bin_count = 1 << bin_shift;
                                         for bin in index * bin_count / peers..(index + 1) * bin_count / peers {
-                                            let number = word_generator.word_at((max_number << ::dynamic_scaling_mechanism::BIN_SHIFT) + bin);
+                                            let number = word_generator.word_at((max_number << bin_shift) + bin);
                                             assert!(number < 2 * key_space);
                                             session.give((number, 1));
                                         }
@@ -263,7 +285,7 @@ fn main() {
                         .stateful_state_machine(|key: &_, val, agg: &mut u64| {
                             *agg += val;
                             (false, Some((*key, *agg)))
-                        }, |key| calculate_hash(key), &control)
+                        }, |key| calculate_hash(key), &control, stateful_config.clone())
                         .probe_with(&mut probe))
                 },
                 Backend::HashMapNative => {
@@ -302,7 +324,7 @@ fn main() {
             let vec_output = match backend {
                 Backend::Vector => {
                     Some(input
-                        .stateful_unary(&control, move |(k, _v)| (*k as u64) << (64 - ::dynamic_scaling_mechanism::BIN_SHIFT), "StateMachine", move |cap, data, bin, output| {
+                        .stateful_unary(&control, stateful_config.clone(), move |(k, _v)| (*k as u64) << (64 - bin_shift), "StateMachine", move |cap, data, bin, output| {
                             let states: &mut Vec<u64> = bin.state();
                             let mut session_cap = cap.clone();
                             for (time, (key, val)) in data.drain(..) {
@@ -311,7 +333,7 @@ fn main() {
                                 }
                                 let mut session = output.session(&session_cap);
                                 let states_len = states.len();
-                                let position = key >> ::dynamic_scaling_mechanism::BIN_SHIFT;
+                                let position = key >> bin_shift;
                                 if states.len() <= position {
                                     states.extend(::std::iter::repeat(0).take(position - states_len + 1))
                                 }
@@ -374,10 +396,10 @@ fn main() {
             }
         });
 
-        let mut instructions = map_mode.instructions(peers, duration_ns).unwrap();
+        let mut instructions = map_mode.instructions(peers, duration_ns, bin_shift).unwrap();
 
         if index == 0 {
-            println!("bin_shift\t{}", ::dynamic_scaling_mechanism::BIN_SHIFT);
+            println!("bin_shift\t{}", bin_shift);
 
             for instruction in instructions.iter().take(10) {
                 // Format instructions first to be able to truncate the string representation
@@ -505,6 +527,12 @@ fn main() {
         for (value, prob, count) in element_hdr.ccdf() {
             println!("count_ccdf\t{}\t{}\t{}", value, prob, count);
         }
+
+        // Report the state each migration sent from this worker
+        for (operator, migration) in metrics.drain_migrations() {
+            let duration_ns = migration.finished.map_or(0, |finished| (finished - migration.started).to_nanos());
+            println!("migration\t{}\t{}\t{}\t{}\t{}\t{}\t{}", index, operator, migration.sequence, migration.bins, migration.records, migration.bytes, duration_ns);
+        }
         output_metric_collector.into_inner()
     }).expect("unsuccessful execution").join().into_iter().map(|x| x.unwrap()).collect();
 
@@ -61,6 +61,8 @@ class Experiment(object):
         self.binary = config.pop("binary")
 
         self.other_args = " ".join(map(lambda kv: "--{} {}".format(kv[0], shlex.quote(str(kv[1]))), iter(config.items())))
+        if self._fake_stateful:
+            self.other_args += " --static_partitioning"
 
     def compute_directory_name(self, config):
         keys = sorted(config.keys())
@@ -79,10 +81,7 @@ class Experiment(object):
         return self._directory_name
 
     def get_features(self):
-        features = ["dynamic_scaling_mechanism/bin-{}".format(self._bin_shift)]
-        if self._fake_stateful:
-            features.append("fake_stateful")
-        return features
+        return []
 
     def get_features_encoded(self):
         return "+".join(map(lambda s: s.replace("/", "@"), sorted(self.get_features())))
@@ -171,9 +170,10 @@ class Experiment(object):
                     'processes': self._processes,
                     'p': p,
                     'workers': self._workers,
+                    'bin_shift': self._bin_shift,
                     'ARGS': self.other_args,
                 }
-                return "RUST_BACKTRACE=1 hwloc-bind socket:{p}.pu:even -- ./{dir}/release/{binary} --migration {cwd}/{migration} --rate {rate} {ARGS} -- --hostfile {cwd}/{hostfile} -n {processes} -p {p} -w {workers}".format(**params)
+                return "RUST_BACKTRACE=1 hwloc-bind socket:{p}.pu:even -- ./{dir}/release/{binary} --migration {cwd}/{migration} --rate {rate} --bin_shift {bin_shift} {ARGS} -- --hostfile {cwd}/{hostfile} -n {processes} -p {p} -w {workers}".format(**params)
             commands = [(self.single_machine_id, make_command(p), self.get_result_file_name("stdout", p), self.get_result_file_name("stderr", p)) for p in range(0, self._processes)]
             return commands
         else:
@@ -188,11 +188,12 @@ class Experiment(object):
                     'processes': self._processes,
                     'p': p,
                     'workers': self._workers,
+                    'bin_shift': self._bin_shift,
                     'ARGS': self.other_args,
                 }
                 # Enable perf per process and report every 2s
-                # return "RUST_BACKTRACE=1 perf record -o perf.{p} --switch-output=2s -- hwloc-bind socket:0.pu:even -- ./{dir}/release/{binary} --migration {cwd}/{migration} --rate {rate} {ARGS} -- --hostfile {cwd}/{hostfile} -n {processes} -p {p} -w {workers}".format(**params)
-                return "RUST_BACKTRACE=1 hwloc-bind socket:0.pu:even -- ./{dir}/release/{binary} --migration {cwd}/{migration} --rate {rate} {ARGS} -- --hostfile {cwd}/{hostfile} -n {processes} -p {p} -w {workers}".format(**params)
+                # return "RUST_BACKTRACE=1 perf record -o perf.{p} --switch-output=2s -- hwloc-bind socket:0.pu:even -- ./{dir}/release/{binary} --migration {cwd}/{migration} --rate {rate} --bin_shift {bin_shift} {ARGS} -- --hostfile {cwd}/{hostfile} -n {processes} -p {p} -w {workers}".format(**params)
+                return "RUST_BACKTRACE=1 hwloc-bind socket:0.pu:even -- ./{dir}/release/{binary} --migration {cwd}/{migration} --rate {rate} --bin_shift {bin_shift} {ARGS} -- --hostfile {cwd}/{hostfile} -n {processes} -p {p} -w {workers}".format(**params)
             commands = [(self.base_machine_id + p, make_command(p), self.get_result_file_name("stdout", p), self.get_result_file_name("stderr", p)) for p in range(0, self._processes)]
             return commands
 
@@ -0,0 +1,181 @@
+//! Assignment of keys to bins.
+//!
+//! `stateful` operators map each record's key to a bin with the [`BinAssigner`] of their
+//! [`StatefulConfig`]. [`Prefix`], the default, selects the top bits of the key and expects keys
+//! to be hashes. [`Range`] partitions the raw, ordered key domain, keeping nearby keys in the same
+//! bin, and [`ConsistentHash`] places bins on a hash ring with virtual nodes. [`AnyAssigner`]
+//! selects one of them at run time.
+//!
+//! The assigner is a type parameter of the configuration, such that operators resolve bins
+//! without dynamic dispatch.
+//!
+//! [`BinAssigner`]: trait.BinAssigner.html
+//! [`StatefulConfig`]: ../struct.StatefulConfig.html
+//! [`Prefix`]: struct.Prefix.html
+//! [`Range`]: struct.Range.html
+//! [`ConsistentHash`]: struct.ConsistentHash.html
+//! [`AnyAssigner`]: enum.AnyAssigner.html
+
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+use ::{Key, key_to_bin};
+
+/// Maps keys to bins.
+pub trait BinAssigner: Clone+Debug+Send+Sync+'static {
+    /// Determine the bin of `key` among `1 << bin_shift` bins.
+    fn bin(&self, key: Key, bin_shift: usize) -> usize;
+
+    /// Whether the assigner partitions keys by their order, in which case key functions should
+    /// return raw ordered keys, such as ids, instead of hashes. Defaults to `false`.
+    fn ordered(&self) -> bool {
+        false
+    }
+}
+
+/// Select the top `bin_shift` bits of a key, as `key_to_bin` does.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Prefix;
+
+impl BinAssigner for Prefix {
+    #[inline(always)]
+    fn bin(&self, key: Key, bin_shift: usize) -> usize {
+        key_to_bin(key, bin_shift)
+    }
+}
+
+/// Partition raw, ordered keys into contiguous ranges.
+///
+/// Bin `0` holds the keys less than the first bound, bin `i` the keys from bound `i - 1` up to
+/// bound `i`. Keys beyond the last bound, or beyond the number of bins, go to the last bin. The
+/// key function must preserve the order of keys rather than hash them, as hashes would spread
+/// nearby keys over all bins.
+#[derive(Clone, Debug)]
+pub struct Range {
+    bounds: Vec<u64>,
+}
+
+impl Range {
+    /// Construct a range partitioning from its bounds. Panics if the bounds are not strictly
+    /// increasing.
+    pub fn new(bounds: Vec<u64>) -> Self {
+        assert!(bounds.windows(2).all(|w| w[0] < w[1]), "range bounds must be strictly increasing");
+        Self { bounds }
+    }
+
+    /// Split the keys `min..max` into `1 << bin_shift` ranges of equal width.
+    pub fn uniform(min: u64, max: u64, bin_shift: usize) -> Self {
+        assert!(min < max, "empty key range {}..{}", min, max);
+        let bins = 1u64 << bin_shift;
+        let width = ::std::cmp::max((max - min) / bins, 1);
+        Self::new((1..bins).map(|i| min.saturating_add(i * width)).take_while(|b| *b < max).collect())
+    }
+}
+
+impl BinAssigner for Range {
+    #[inline]
+    fn bin(&self, key: Key, bin_shift: usize) -> usize {
+        let bin = match self.bounds.binary_search(&key.0) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+        ::std::cmp::min(bin, (1 << bin_shift) - 1)
+    }
+
+    fn ordered(&self) -> bool {
+        true
+    }
+}
+
+/// Place bins on a consistent-hash ring, with several virtual nodes per bin.
+///
+/// A key belongs to the bin of the first virtual node at or after it on the ring. Keys are used
+/// as positions directly and thus should be hashes.
+#[derive(Clone, Debug)]
+pub struct ConsistentHash {
+    ring: Vec<(u64, usize)>,
+    bin_shift: usize,
+}
+
+impl ConsistentHash {
+    /// Construct a ring for `1 << bin_shift` bins with `virtual_nodes` nodes each. Panics if
+    /// `virtual_nodes` is zero.
+    pub fn new(bin_shift: usize, virtual_nodes: usize) -> Self {
+        assert!(virtual_nodes > 0, "consistent hashing requires at least one virtual node per bin");
+        let mut ring = Vec::with_capacity((1 << bin_shift) * virtual_nodes);
+        for bin in 0..1 << bin_shift {
+            for node in 0..virtual_nodes {
+                let mut h: ::fnv::FnvHasher = Default::default();
+                (bin, node).hash(&mut h);
+                ring.push((h.finish(), bin));
+            }
+        }
+        ring.sort();
+        Self { ring, bin_shift }
+    }
+}
+
+impl BinAssigner for ConsistentHash {
+    #[inline]
+    fn bin(&self, key: Key, bin_shift: usize) -> usize {
+        debug_assert_eq!(bin_shift, self.bin_shift, "ring constructed for a different bin shift");
+        let position = match self.ring.binary_search_by_key(&key.0, |&(point, _)| point) {
+            Ok(index) | Err(index) => index,
+        };
+        self.ring.get(position).unwrap_or(&self.ring[0]).1
+    }
+}
+
+/// One of the provided assigners, for selecting the assigner at run time.
+#[derive(Clone, Debug)]
+pub enum AnyAssigner {
+    /// Assign keys with `Prefix`.
+    Prefix(Prefix),
+    /// Assign keys with `Range`.
+    Range(Range),
+    /// Assign keys with `ConsistentHash`.
+    ConsistentHash(ConsistentHash),
+}
+
+impl Default for AnyAssigner {
+    fn default() -> Self {
+        AnyAssigner::Prefix(Prefix)
+    }
+}
+
+impl BinAssigner for AnyAssigner {
+    #[inline]
+    fn bin(&self, key: Key, bin_shift: usize) -> usize {
+        match *self {
+            AnyAssigner::Prefix(ref assigner) => assigner.bin(key, bin_shift),
+            AnyAssigner::Range(ref assigner) => assigner.bin(key, bin_shift),
+            AnyAssigner::ConsistentHash(ref assigner) => assigner.bin(key, bin_shift),
+        }
+    }
+
+    fn ordered(&self) -> bool {
+        match *self {
+            AnyAssigner::Prefix(ref assigner) => assigner.ordered(),
+            AnyAssigner::Range(ref assigner) => assigner.ordered(),
+            AnyAssigner::ConsistentHash(ref assigner) => assigner.ordered(),
+        }
+    }
+}
+
+impl From<Prefix> for AnyAssigner {
+    fn from(assigner: Prefix) -> Self {
+        AnyAssigner::Prefix(assigner)
+    }
+}
+
+impl From<Range> for AnyAssigner {
+    fn from(assigner: Range) -> Self {
+        AnyAssigner::Range(assigner)
+    }
+}
+
+impl From<ConsistentHash> for AnyAssigner {
+    fn from(assigner: ConsistentHash) -> Self {
+        AnyAssigner::ConsistentHash(assigner)
+    }
+}
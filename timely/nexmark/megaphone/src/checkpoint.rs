@@ -0,0 +1,187 @@
+//! Checkpointing of bins to local disk.
+//!
+//! A `Control` with `ControlInst::Checkpoint` instructs `stateful` operators configured with a
+//! [`CheckpointConfig`] to write the bins they own once the configuration is installed. At this
+//! point, all data before the configuration's time has been applied and no later data has, so
+//! the checkpoint is consistent at that time. Each bin is written with its pending notifications.
+//!
+//! Checkpoints are laid out as `<dir>/<sequence>/<operator>/bin-<bin>`, where `sequence` is the
+//! configuration's sequence number and `operator` is the operator's address. After writing its
+//! bins, each worker writes `done-<worker>`, recording the number of peers, the time and the map
+//! of bins to workers the checkpoint was taken with.
+//!
+//! To restore, find a complete checkpoint with [`latest_checkpoint`], construct the dataflow
+//! with [`CheckpointConfig::with_restore`] and replay inputs from the checkpoint's time. With the
+//! same number of workers, operators start from the checkpoint's map. Otherwise, they start from
+//! the initial round-robin map, so the number of workers can change on restart. Either way, bins
+//! are read by their owner under the map the operators start from.
+//!
+//! Failing to write a checkpoint is reported as `ControlError::CheckpointFailed` and handled
+//! according to the operators' `ControlErrorPolicy`. The checkpoint is then never complete.
+//!
+//! [`CheckpointConfig`]: struct.CheckpointConfig.html
+//! [`CheckpointConfig::with_restore`]: struct.CheckpointConfig.html#method.with_restore
+//! [`latest_checkpoint`]: fn.latest_checkpoint.html
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use abomonation::Abomonation;
+
+/// Where to write checkpoints to, and whether to restore from one.
+#[derive(Clone, Debug)]
+pub struct CheckpointConfig {
+    dir: PathBuf,
+    restore: Option<u64>,
+}
+
+impl CheckpointConfig {
+    /// Write checkpoints to `dir`.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into(), restore: None }
+    }
+
+    /// Restore state from the checkpoint with sequence number `sequence` on construction.
+    pub fn with_restore(mut self, sequence: u64) -> Self {
+        self.restore = Some(sequence);
+        self
+    }
+
+    /// The checkpoint directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The sequence number of the checkpoint to restore from, if any.
+    pub fn restore(&self) -> Option<u64> {
+        self.restore
+    }
+}
+
+/// A complete checkpoint found on disk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Checkpoint<T> {
+    /// The sequence number of the configuration that requested the checkpoint.
+    pub sequence: u64,
+    /// The time at which the checkpoint is consistent.
+    pub time: T,
+}
+
+/// The bins' data and pending notifications.
+pub(crate) type BinCheckpoint<T, W, M> = (Vec<W>, Vec<(T, M)>);
+
+fn operator_dir(dir: &Path, sequence: u64, operator: &str) -> PathBuf {
+    dir.join(sequence.to_string()).join(operator)
+}
+
+/// Encode `value` to `path`, replacing an existing file only once writing succeeded.
+fn write_encoded<A: Abomonation>(path: &Path, value: &A) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    unsafe { ::abomonation::encode(value, &mut bytes)?; }
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, &bytes)?;
+    fs::rename(&temporary, path)
+}
+
+/// Decode a value of type `A` from `path`.
+fn read_encoded<A: Abomonation+Clone>(path: &Path) -> io::Result<A> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut bytes)?;
+    unsafe { ::abomonation::decode::<A>(&mut bytes) }
+        .map(|(value, _)| value.clone())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: corrupt checkpoint", path.display())))
+}
+
+/// Write a bin's contents.
+pub(crate) fn write_bin<T: Abomonation, W: Abomonation, M: Abomonation>(dir: &Path, sequence: u64, operator: &str, bin: usize, checkpoint: &BinCheckpoint<T, W, M>) -> io::Result<()> {
+    let operator_dir = operator_dir(dir, sequence, operator);
+    fs::create_dir_all(&operator_dir)?;
+    write_encoded(&operator_dir.join(format!("bin-{}", bin)), checkpoint)
+}
+
+/// A worker's record of completing its part of a checkpoint: the number of peers, the time and
+/// the map of bins to workers.
+type Done<T> = (usize, T, Vec<usize>);
+
+/// Mark a worker's part of a checkpoint as complete.
+pub(crate) fn write_done<T: Abomonation+Clone>(dir: &Path, sequence: u64, operator: &str, worker: usize, peers: usize, time: &T, map: &[usize]) -> io::Result<()> {
+    let operator_dir = operator_dir(dir, sequence, operator);
+    fs::create_dir_all(&operator_dir)?;
+    let done: Done<T> = (peers, time.clone(), map.to_vec());
+    write_encoded(&operator_dir.join(format!("done-{}", worker)), &done)
+}
+
+/// Read the number of peers and the map of bins to workers an operator's checkpoint was taken
+/// with, or `None` if no worker completed it.
+pub(crate) fn read_map<T: Abomonation+Clone>(dir: &Path, sequence: u64, operator: &str) -> io::Result<Option<(usize, Vec<usize>)>> {
+    let path = operator_dir(dir, sequence, operator).join("done-0");
+    if path.exists() {
+        let (peers, _time, map): Done<T> = read_encoded(&path)?;
+        Ok(Some((peers, map)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Read a bin's contents, or `None` if the bin held no state when the checkpoint was taken.
+pub(crate) fn read_bin<T: Abomonation+Clone, W: Abomonation+Clone, M: Abomonation+Clone>(dir: &Path, sequence: u64, operator: &str, bin: usize) -> io::Result<Option<BinCheckpoint<T, W, M>>> {
+    let path = operator_dir(dir, sequence, operator).join(format!("bin-{}", bin));
+    if path.exists() {
+        read_encoded(&path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Determine whether all workers completed an operator's checkpoint, returning its time.
+fn complete_operator<T: Abomonation+Clone>(operator_dir: &Path) -> io::Result<Option<T>> {
+    let mut done: Vec<Done<T>> = Vec::new();
+    for entry in fs::read_dir(operator_dir)? {
+        let path = entry?.path();
+        if path.file_name().and_then(|name| name.to_str()).map_or(false, |name| name.starts_with("done-") && !name.ends_with(".tmp")) {
+            done.push(read_encoded(&path)?);
+        }
+    }
+    Ok(match done.first() {
+        Some(&(peers, ref time, _)) if done.len() == peers => Some(time.clone()),
+        _ => None,
+    })
+}
+
+/// Find the checkpoint with the highest sequence number that all workers of all operators
+/// completed.
+///
+/// As operators are only known by the files they wrote, a checkpoint is considered complete if
+/// it has as many operators as the checkpoint with the most operators.
+pub fn latest_checkpoint<T: Abomonation+Clone>(dir: &Path) -> io::Result<Option<Checkpoint<T>>> {
+    if !dir.exists() {
+        return Ok(None);
+    }
+    let mut sequences = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if let Some(sequence) = entry.file_name().to_str().and_then(|name| name.parse::<u64>().ok()) {
+            let operators = fs::read_dir(entry.path())?.collect::<io::Result<Vec<_>>>()?;
+            sequences.push((sequence, operators));
+        }
+    }
+    let operator_count = sequences.iter().map(|s| s.1.len()).max().unwrap_or(0);
+    sequences.sort_by_key(|s| ::std::cmp::Reverse(s.0));
+    for (sequence, operators) in sequences {
+        if operators.len() != operator_count {
+            continue;
+        }
+        let mut time = None;
+        for operator in &operators {
+            time = complete_operator::<T>(&operator.path())?;
+            if time.is_none() {
+                break;
+            }
+        }
+        if let Some(time) = time {
+            return Ok(Some(Checkpoint { sequence, time }));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,191 @@
+//! Serialization of whole bins for migration.
+//!
+//! By default, a bin's state `D` is migrated as the sequence of records it iterates into, each
+//! of which is serialized by timely. Wrapping the state in [`Encoded`] instead encodes all records
+//! of a bin into a single byte blob with a [`BinCodec`], for example to compress large states.
+//! `Encoded` dereferences to the wrapped state, so operators' logic can use it unchanged:
+//!
+//! ```ignore
+//! stream.stateful_unary(&control, config, key, "Count", |cap, data, bin: &mut Bin<_, Encoded<HashMap<u64, u64>, Lz4<AbomonationCodec>>, _>, output| {
+//!     for (_time, (key, value)) in data.drain(..) {
+//!         *bin.state().entry(key).or_insert(0) += value;
+//!     }
+//! })
+//! ```
+//!
+//! [`AbomonationCodec`] is always available. [`BincodeCodec`], [`Lz4`] and [`Zstd`] require the
+//! `codec-bincode`, `codec-lz4` and `codec-zstd` features.
+//!
+//! An encoded bin is a single record with respect to `StatefulConfig::with_state_chunk_size`.
+//!
+//! [`Encoded`]: struct.Encoded.html
+//! [`BinCodec`]: trait.BinCodec.html
+//! [`AbomonationCodec`]: struct.AbomonationCodec.html
+//! [`BincodeCodec`]: struct.BincodeCodec.html
+//! [`Lz4`]: struct.Lz4.html
+//! [`Zstd`]: struct.Zstd.html
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+use abomonation::Abomonation;
+
+/// Encodes the records of a bin to bytes and back.
+pub trait BinCodec<W>: Clone+Default+'static {
+    /// Append the encoding of `records` to `bytes`.
+    fn encode(&self, records: Vec<W>, bytes: &mut Vec<u8>) -> io::Result<()>;
+    /// Decode records from `bytes`, appending them to `records`.
+    fn decode(&self, bytes: &[u8], records: &mut Vec<W>) -> io::Result<()>;
+}
+
+/// Encode records with abomonation, as timely does for individual records.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AbomonationCodec;
+
+impl<W: Abomonation+Clone> BinCodec<W> for AbomonationCodec {
+    fn encode(&self, records: Vec<W>, bytes: &mut Vec<u8>) -> io::Result<()> {
+        unsafe { ::abomonation::encode(&records, bytes) }
+    }
+
+    fn decode(&self, bytes: &[u8], records: &mut Vec<W>) -> io::Result<()> {
+        let mut bytes = bytes.to_vec();
+        let decoded = unsafe { ::abomonation::decode::<Vec<W>>(&mut bytes) }
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "corrupt abomonation-encoded bin"))?;
+        records.extend(decoded.0.iter().cloned());
+        Ok(())
+    }
+}
+
+/// Encode records with bincode.
+#[cfg(feature = "codec-bincode")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "codec-bincode")]
+impl<W: ::serde::Serialize+::serde::de::DeserializeOwned> BinCodec<W> for BincodeCodec {
+    fn encode(&self, records: Vec<W>, bytes: &mut Vec<u8>) -> io::Result<()> {
+        ::bincode::serialize_into(bytes, &records).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn decode(&self, bytes: &[u8], records: &mut Vec<W>) -> io::Result<()> {
+        let decoded: Vec<W> = ::bincode::deserialize(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        records.extend(decoded);
+        Ok(())
+    }
+}
+
+/// Compress the encoding of another codec with LZ4.
+#[cfg(feature = "codec-lz4")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lz4<C> {
+    inner: C,
+}
+
+#[cfg(feature = "codec-lz4")]
+impl<W, C: BinCodec<W>> BinCodec<W> for Lz4<C> {
+    fn encode(&self, records: Vec<W>, bytes: &mut Vec<u8>) -> io::Result<()> {
+        let mut raw = Vec::new();
+        self.inner.encode(records, &mut raw)?;
+        bytes.extend(::lz4::block::compress(&raw, None, true)?);
+        Ok(())
+    }
+
+    fn decode(&self, bytes: &[u8], records: &mut Vec<W>) -> io::Result<()> {
+        let raw = ::lz4::block::decompress(bytes, None)?;
+        self.inner.decode(&raw, records)
+    }
+}
+
+/// Compress the encoding of another codec with Zstandard, at its default level.
+#[cfg(feature = "codec-zstd")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Zstd<C> {
+    inner: C,
+}
+
+#[cfg(feature = "codec-zstd")]
+impl<W, C: BinCodec<W>> BinCodec<W> for Zstd<C> {
+    fn encode(&self, records: Vec<W>, bytes: &mut Vec<u8>) -> io::Result<()> {
+        let mut raw = Vec::new();
+        self.inner.encode(records, &mut raw)?;
+        bytes.extend(::zstd::stream::encode_all(&raw[..], 0)?);
+        Ok(())
+    }
+
+    fn decode(&self, bytes: &[u8], records: &mut Vec<W>) -> io::Result<()> {
+        let raw = ::zstd::stream::decode_all(bytes)?;
+        self.inner.decode(&raw, records)
+    }
+}
+
+/// Bin state `D` that is migrated as a single blob encoded by `C`.
+pub struct Encoded<D, C> {
+    state: D,
+    codec: C,
+}
+
+impl<D, C> Encoded<D, C> {
+    /// Unwrap the state.
+    pub fn into_inner(self) -> D {
+        self.state
+    }
+}
+
+impl<D: Clone, C: Clone> Clone for Encoded<D, C> {
+    fn clone(&self) -> Self {
+        Self { state: self.state.clone(), codec: self.codec.clone() }
+    }
+}
+
+impl<D: Default, C: Default> Default for Encoded<D, C> {
+    fn default() -> Self {
+        Self { state: Default::default(), codec: Default::default() }
+    }
+}
+
+impl<D, C> Deref for Encoded<D, C> {
+    type Target = D;
+    fn deref(&self) -> &D {
+        &self.state
+    }
+}
+
+impl<D, C> DerefMut for Encoded<D, C> {
+    fn deref_mut(&mut self) -> &mut D {
+        &mut self.state
+    }
+}
+
+impl<D, C> IntoIterator for Encoded<D, C>
+    where
+        D: IntoIterator,
+        C: BinCodec<D::Item>,
+{
+    type Item = Vec<u8>;
+    type IntoIter = ::std::option::IntoIter<Vec<u8>>;
+
+    /// Encode the state, yielding nothing for empty states.
+    fn into_iter(self) -> Self::IntoIter {
+        let records: Vec<_> = self.state.into_iter().collect();
+        if records.is_empty() {
+            return None.into_iter();
+        }
+        let mut bytes = Vec::new();
+        self.codec.encode(records, &mut bytes).unwrap_or_else(|e| panic!("failed to encode bin: {}", e));
+        Some(bytes).into_iter()
+    }
+}
+
+impl<D, C> Extend<Vec<u8>> for Encoded<D, C>
+    where
+        D: IntoIterator+Extend<<D as IntoIterator>::Item>,
+        C: BinCodec<D::Item>,
+{
+    fn extend<I: IntoIterator<Item=Vec<u8>>>(&mut self, iter: I) {
+        let mut records = Vec::new();
+        for bytes in iter {
+            self.codec.decode(&bytes, &mut records).unwrap_or_else(|e| panic!("failed to decode bin: {}", e));
+        }
+        self.state.extend(records);
+    }
+}
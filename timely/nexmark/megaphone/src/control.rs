@@ -0,0 +1,91 @@
+//! Validation of `Control` streams.
+//!
+//! The `stateful` operators handle malformed configurations according to their
+//! [`ControlErrorPolicy`]. To observe errors as data instead, a control stream can be passed
+//! through [`ValidateControl::validate_control`], which forwards well-formed configurations and
+//! reports malformed ones on a separate stream.
+//!
+//! [`ControlErrorPolicy`]: ../enum.ControlErrorPolicy.html
+//! [`ValidateControl::validate_control`]: trait.ValidateControl.html#tymethod.validate_control
+
+use fnv::FnvHashMap as HashMap;
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Capability;
+use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
+use timely::progress::frontier::Antichain;
+
+use assigner::BinAssigner;
+use ::{Control, ControlError, ControlSet, ControlSetBuilder, StatefulConfig};
+
+/// Split a control stream into well-formed controls and errors.
+pub trait ValidateControl<S: Scope> {
+    /// Validate the configurations described by a control stream.
+    ///
+    /// All controls at a time are held back until the time is complete. If they form a valid
+    /// configuration, they are forwarded unchanged on the first stream. Otherwise, the controls
+    /// are dropped and the error is reported on the second stream. Each worker validates the
+    /// controls it receives, and the initial map is the round-robin assignment used by `stateful`.
+    fn validate_control<A: BinAssigner>(&self, config: &StatefulConfig<A>) -> (Stream<S, Control>, Stream<S, ControlError>);
+}
+
+impl<S: Scope> ValidateControl<S> for Stream<S, Control> {
+    fn validate_control<A: BinAssigner>(&self, config: &StatefulConfig<A>) -> (Stream<S, Control>, Stream<S, ControlError>) {
+        let bin_shift = config.bin_shift();
+        let peers = self.scope().peers();
+
+        let mut builder = OperatorBuilder::new("ValidateControl".into(), self.scope());
+        let mut control_in = builder.new_input(self, Pipeline);
+        let (mut control_out, control_stream) = builder.new_output();
+        let (mut error_out, error_stream) = builder.new_output();
+
+        builder.build(move |_capability| {
+            // Controls per time, with capabilities for both outputs
+            let mut pending: HashMap<S::Timestamp, (Capability<S::Timestamp>, Capability<S::Timestamp>, Vec<Control>)> = Default::default();
+
+            // The configuration resulting from all valid controls so far
+            let mut current: ControlSet<S::Timestamp> = ControlSet {
+                sequence: 0,
+                frontier: Antichain::from_elem(Default::default()),
+                map: (0..peers).cycle().take(1 << bin_shift).collect(),
+                checkpoint: false,
+            };
+
+            let mut buffer = vec![];
+
+            move |frontiers| {
+                let mut control_out = control_out.activate();
+                let mut error_out = error_out.activate();
+
+                control_in.for_each(|time, data| {
+                    data.swap(&mut buffer);
+                    pending.entry(time.time().clone())
+                        .or_insert_with(|| (time.retain_for_output(0), time.retain_for_output(1), Vec::new()))
+                        .2.extend(buffer.drain(..));
+                });
+
+                let mut complete: Vec<_> = pending.keys().filter(|t| !frontiers[0].less_equal(t)).cloned().collect();
+                complete.sort();
+                for time in complete {
+                    let (control_cap, error_cap, controls) = pending.remove(&time).expect("time must be pending");
+                    let mut builder: ControlSetBuilder<S::Timestamp> = Default::default();
+                    builder.frontier(vec![time.clone()].into_iter());
+                    let result = controls.iter().cloned()
+                        .map(|control| builder.apply(control))
+                        .collect::<Result<(), _>>()
+                        .and_then(|()| builder.build(&current, bin_shift));
+                    match result {
+                        Ok(set) => {
+                            current = set;
+                            control_out.session(&control_cap).give_iterator(controls.into_iter());
+                        },
+                        Err(error) => error_out.session(&error_cap).give(error),
+                    }
+                }
+            }
+        });
+
+        (control_stream, error_stream)
+    }
+}
@@ -0,0 +1,350 @@
+//! Load-aware rebalancing of bins.
+//!
+//! A [`LoadTracker`] passed to `stateful` operators through their [`StatefulConfig`] counts
+//! records and keys per bin. [`Rebalance::rebalance`] periodically collects the load of all
+//! workers, asks a [`RebalancePolicy`] for a new assignment of bins to workers, and emits the
+//! `Control` messages to migrate to it.
+//!
+//! The controller observes the operators' output to know when all records at a time have been
+//! counted, and its controls are fed back to the operators' control input through a loop:
+//!
+//! ```ignore
+//! let (handle, control) = scope.feedback(1);
+//! let output = input.stateful_state_machine(fold, hash, &control, config.clone());
+//! output.rebalance(&control, tracker, &config, Greedy, 0).connect_loop(handle);
+//! ```
+//!
+//! [`LoadTracker`]: struct.LoadTracker.html
+//! [`StatefulConfig`]: ../struct.StatefulConfig.html
+//! [`Rebalance::rebalance`]: trait.Rebalance.html#tymethod.rebalance
+//! [`RebalancePolicy`]: trait.RebalancePolicy.html
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use fnv::FnvHashSet as HashSet;
+
+use timely::Data;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+use timely::dataflow::operators::{Broadcast, Filter, Operator};
+use timely::order::TotalOrder;
+use timely::progress::frontier::Antichain;
+
+use ::{BinId, Control, ControlInst, ControlSet, ControlSetBuilder, Key, StatefulConfig};
+use assigner::BinAssigner;
+use notificator::TotalOrderFrontierNotificator;
+
+/// The load observed for a bin during one report interval.
+#[derive(Abomonation, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BinLoad {
+    /// Records routed to the bin since the last report.
+    pub records: usize,
+    /// Distinct keys routed to the bin since the last report. This approximates the bin's active
+    /// state and thus its migration cost, but does not measure the state.
+    pub keys: usize,
+}
+
+/// Per-worker load counters for `stateful` operators.
+///
+/// Clones share the same counters, and all `stateful` operators configured with the same tracker
+/// contribute to it. Counters are per worker, so each worker should construct its own tracker.
+///
+/// Loads are observed where records are routed, not where they are processed. As a key can be
+/// routed from several workers, the distinct keys are an upper bound. Operators count locally
+/// and publish their counts once per activation. All counts, including the keys, are reset by
+/// each report, so the tracker's memory is bounded by the keys seen in one report interval.
+#[derive(Clone, Debug, Default)]
+pub struct LoadTracker {
+    inner: Arc<Mutex<LoadCounters>>,
+}
+
+/// Counters tracked by a `LoadTracker`, or accumulated by an operator before publishing them.
+#[derive(Debug, Default)]
+pub(crate) struct LoadCounters {
+    records: Vec<usize>,
+    keys: Vec<HashSet<Key>>,
+}
+
+impl LoadCounters {
+    /// Account for a record with key `key` routed to `bin`.
+    #[inline]
+    pub(crate) fn record(&mut self, bin: usize, key: Key) {
+        if self.records.len() <= bin {
+            self.records.resize(bin + 1, 0);
+            self.keys.resize(bin + 1, HashSet::default());
+        }
+        self.records[bin] += 1;
+        self.keys[bin].insert(key);
+    }
+
+    /// Whether no records were counted.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.records.iter().all(|records| *records == 0)
+    }
+
+    /// Add the counts of `other` to `self`, leaving `other` empty but allocated.
+    fn merge_from(&mut self, other: &mut LoadCounters) {
+        if self.records.len() < other.records.len() {
+            self.records.resize(other.records.len(), 0);
+            self.keys.resize(other.records.len(), HashSet::default());
+        }
+        for (bin, records) in other.records.iter_mut().enumerate() {
+            self.records[bin] += *records;
+            *records = 0;
+        }
+        for (bin, keys) in other.keys.iter_mut().enumerate() {
+            self.keys[bin].extend(keys.drain());
+        }
+    }
+}
+
+impl LoadTracker {
+    /// Construct a new tracker without any load.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Publish the counts an operator accumulated locally, resetting them.
+    pub(crate) fn publish(&self, counters: &mut LoadCounters) {
+        if !counters.is_empty() {
+            self.inner.lock().expect("load tracker poisoned").merge_from(counters);
+        }
+    }
+
+    /// Report the load of all bins with data since the last report and reset all counts.
+    pub fn drain(&self) -> Vec<(BinId, BinLoad)> {
+        let mut counters = self.inner.lock().expect("load tracker poisoned");
+        let LoadCounters { ref mut records, ref mut keys } = *counters;
+        let mut loads = Vec::new();
+        for (bin, (records, keys)) in records.iter_mut().zip(keys.iter_mut()).enumerate() {
+            if *records > 0 {
+                loads.push((BinId(bin), BinLoad { records: *records, keys: keys.len() }));
+                *records = 0;
+                // Release the memory of bins that became idle
+                *keys = HashSet::default();
+            }
+        }
+        loads
+    }
+}
+
+/// Decides on an assignment of bins to workers given their load.
+pub trait RebalancePolicy {
+    /// Compute a new map from the current `map` and the `loads`, indexed by bin, across `peers`
+    /// workers. Returns `None` to keep the current map.
+    fn rebalance(&mut self, map: &[usize], peers: usize, loads: &[BinLoad]) -> Option<Vec<usize>>;
+}
+
+/// Reassign all bins from scratch, placing the busiest bin on the least-loaded worker first.
+///
+/// Yields the best balance, but might move many bins.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Greedy;
+
+impl RebalancePolicy for Greedy {
+    fn rebalance(&mut self, map: &[usize], peers: usize, loads: &[BinLoad]) -> Option<Vec<usize>> {
+        let mut bins: Vec<usize> = (0..map.len()).collect();
+        bins.sort_by_key(|&bin| ::std::cmp::Reverse(loads[bin].records));
+        let mut worker_loads = vec![0; peers];
+        let mut new_map = map.to_vec();
+        for bin in bins {
+            // Prefer the current owner among equally-loaded workers to avoid needless migrations.
+            let target = (0..peers)
+                .min_by_key(|&worker| (worker_loads[worker], worker != map[bin]))
+                .expect("no workers");
+            worker_loads[target] += loads[bin].records;
+            new_map[bin] = target;
+        }
+        if new_map[..] != map[..] { Some(new_map) } else { None }
+    }
+}
+
+/// Move bins from the busiest to the least busy worker while the busiest worker's load exceeds
+/// the mean load by more than a factor of `imbalance`, moving the busiest bin that still improves
+/// the balance first.
+#[derive(Clone, Copy, Debug)]
+pub struct Threshold {
+    imbalance: f64,
+}
+
+impl Threshold {
+    /// Construct a new threshold policy. Panics if `imbalance` is less than 1.
+    pub fn new(imbalance: f64) -> Self {
+        assert!(imbalance >= 1., "imbalance must be at least 1, found {}", imbalance);
+        Self { imbalance }
+    }
+}
+
+impl RebalancePolicy for Threshold {
+    fn rebalance(&mut self, map: &[usize], peers: usize, loads: &[BinLoad]) -> Option<Vec<usize>> {
+        rebalance_above(self.imbalance, map, peers, loads, |load| load.records as f64)
+    }
+}
+
+/// Like `Threshold`, but prefers moving bins that carry the most records per distinct key,
+/// approximating the least state migrated to restore balance.
+#[derive(Clone, Copy, Debug)]
+pub struct MinMigrationCost {
+    imbalance: f64,
+}
+
+impl MinMigrationCost {
+    /// Construct a new min-migration-cost policy. Panics if `imbalance` is less than 1.
+    pub fn new(imbalance: f64) -> Self {
+        assert!(imbalance >= 1., "imbalance must be at least 1, found {}", imbalance);
+        Self { imbalance }
+    }
+}
+
+impl RebalancePolicy for MinMigrationCost {
+    fn rebalance(&mut self, map: &[usize], peers: usize, loads: &[BinLoad]) -> Option<Vec<usize>> {
+        rebalance_above(self.imbalance, map, peers, loads, |load| load.records as f64 / (load.keys + 1) as f64)
+    }
+}
+
+/// Move bins with the highest `score` off the busiest worker while it is more than `imbalance`
+/// times the mean load.
+fn rebalance_above<F: Fn(&BinLoad)->f64>(imbalance: f64, map: &[usize], peers: usize, loads: &[BinLoad], score: F) -> Option<Vec<usize>> {
+    let mut worker_loads = vec![0; peers];
+    for (bin, worker) in map.iter().enumerate() {
+        worker_loads[*worker] += loads[bin].records;
+    }
+    let total: usize = worker_loads.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let mean = total as f64 / peers as f64;
+
+    let mut new_map = map.to_vec();
+    loop {
+        let busiest = (0..peers).max_by_key(|&worker| worker_loads[worker]).expect("no workers");
+        let idlest = (0..peers).min_by_key(|&worker| worker_loads[worker]).expect("no workers");
+        if (worker_loads[busiest] as f64) <= imbalance * mean {
+            break;
+        }
+        // Moving a bin only helps if it does not turn the idlest worker into the busiest.
+        let gap = worker_loads[busiest] - worker_loads[idlest];
+        let candidate = (0..new_map.len())
+            .filter(|&bin| new_map[bin] == busiest && loads[bin].records > 0 && loads[bin].records < gap)
+            .max_by(|&a, &b| score(&loads[a]).partial_cmp(&score(&loads[b])).expect("score must not be NaN"));
+        match candidate {
+            Some(bin) => {
+                worker_loads[busiest] -= loads[bin].records;
+                worker_loads[idlest] += loads[bin].records;
+                new_map[bin] = idlest;
+            },
+            None => break,
+        }
+    }
+    if new_map[..] != map[..] { Some(new_map) } else { None }
+}
+
+/// Provides the `rebalance` method.
+pub trait Rebalance<S: Scope> {
+    /// Construct a controller that rebalances bins based on the load recorded in `tracker`.
+    ///
+    /// `self` must be the output of the `stateful` operators recording to `tracker`: once a time
+    /// is complete in it, all records at that time have been counted, and every worker reports
+    /// its load. Once all workers reported, `policy` decides on a new map and the controller
+    /// emits `Control` messages at the same time to every worker. They must be fed back to the
+    /// operators' `control` input through a loop that advances time, as the operators cannot
+    /// accept configurations for times they might already have processed.
+    ///
+    /// The controller starts from the initial round-robin map and tracks the current map by
+    /// applying the configurations on `control`, which must be the operators' control input,
+    /// and its own decisions. Configurations are numbered starting at `first_sequence`, which
+    /// must be larger than sequence numbers issued by any other control source. Configurations
+    /// from other sources are applied in time order, which might differ from their sequence
+    /// order relative to the controller's own.
+    fn rebalance<P: RebalancePolicy+'static, A: BinAssigner>(&self, control: &Stream<S, Control>, tracker: LoadTracker, config: &StatefulConfig<A>, policy: P, first_sequence: u64) -> Stream<S, Control>;
+}
+
+impl<S: Scope, D: Data> Rebalance<S> for Stream<S, D>
+    where
+        S::Timestamp: TotalOrder,
+{
+    fn rebalance<P: RebalancePolicy+'static, A: BinAssigner>(&self, control: &Stream<S, Control>, tracker: LoadTracker, config: &StatefulConfig<A>, mut policy: P, first_sequence: u64) -> Stream<S, Control> {
+        let peers = self.scope().peers();
+        let index = self.scope().index();
+        let bins = config.bins();
+        let bin_shift = config.bin_shift();
+
+        // Report local loads once a time is complete
+        let reports = self.unary_frontier(Pipeline, "LoadReport", move |_cap, _info| {
+            let mut notificator = TotalOrderFrontierNotificator::new();
+            move |input, output| {
+                input.for_each(|time, _data| {
+                    notificator.notify_at(&time.retain());
+                });
+                notificator.for_each(&[input.frontier()], |cap, _time, _not| {
+                    output.session(&cap).give_iterator(tracker.drain().into_iter());
+                });
+            }
+        });
+
+        // Aggregate reports on worker 0 and decide on a new map. Every worker receives all
+        // controls, so worker 0 only considers its own copy of them.
+        let other_controls = control.filter(move |control: &Control| index == 0 && control.sequence() < first_sequence);
+        reports.binary_frontier(&other_controls, Exchange::new(|_| 0), Pipeline, "Rebalance", move |_cap, _info| {
+            let mut notificator = TotalOrderFrontierNotificator::new();
+            let mut current: ControlSet<S::Timestamp> = ControlSet {
+                sequence: 0,
+                frontier: Antichain::from_elem(Default::default()),
+                map: (0..peers).cycle().take(bins).collect(),
+                checkpoint: false,
+            };
+            // Controls of other sources by time, applied once the time is complete
+            let mut controls: BTreeMap<S::Timestamp, Vec<Control>> = BTreeMap::new();
+            let mut sequence = first_sequence;
+            let mut buffer = Vec::new();
+            let mut control_buffer = Vec::new();
+            move |reports, control, output| {
+                reports.for_each(|time, data| {
+                    data.swap(&mut buffer);
+                    let cap = time.retain();
+                    for report in buffer.drain(..) {
+                        notificator.notify_at_data(&cap, cap.time().clone(), report);
+                    }
+                });
+                control.for_each(|time, data| {
+                    data.swap(&mut control_buffer);
+                    controls.entry(time.time().clone()).or_insert_with(Vec::new).extend(control_buffer.drain(..));
+                });
+
+                // Apply other sources' configurations that are complete
+                while controls.keys().next().map_or(false, |time| !control.frontier().less_equal(time)) {
+                    let time = controls.keys().next().cloned().unwrap();
+                    let mut builder: ControlSetBuilder<S::Timestamp> = Default::default();
+                    let applied = controls.remove(&time).unwrap().into_iter().map(|c| builder.apply(c)).collect::<Result<Vec<_>, _>>();
+                    // The `stateful` operators reject or panic on malformed configurations
+                    if let Ok(set) = applied.and_then(|_| builder.build(&current, bin_shift)) {
+                        current.map = set.map;
+                    }
+                }
+
+                let mut loads = vec![BinLoad::default(); bins];
+                let mut notified = None;
+                notificator.for_each_data(&[reports.frontier(), control.frontier()], |cap, time, (BinId(bin), load), _not| {
+                    loads[bin].records += load.records;
+                    loads[bin].keys += load.keys;
+                    notified = Some(cap.delayed(&time));
+                });
+                if let Some(cap) = notified {
+                    let map = &mut current.map;
+                    if let Some(new_map) = policy.rebalance(map, peers, &loads) {
+                        let moves: Vec<_> = new_map.iter().enumerate()
+                            .filter(|&(bin, worker)| map[bin] != *worker)
+                            .map(|(bin, worker)| ControlInst::Move(BinId(bin), *worker))
+                            .collect();
+                        let count = moves.len();
+                        output.session(&cap).give_iterator(moves.into_iter().map(|inst| Control::new(sequence, count, inst)));
+                        sequence += 1;
+                        *map = new_map;
+                    }
+                }
+            }
+        })
+        .broadcast()
+    }
+}
@@ -1,13 +1,28 @@
-//! General purpose state transition operator.
+//! Migratable equi-joins.
+//!
+//! The [`Join`] trait provides inner, left outer and windowed joins on keyed streams, built on
+//! [`StatefulOperator::stateful_binary_frontier`]. Both sides keep all values per key in a
+//! [`JoinState`], which migrates with its bin. Windowed values are dropped as the input frontier
+//! passes their expiry, whether or not further records arrive for their bin.
+//! Keys are hashed, so assigners that partition ordered keys see hashes rather than raw keys.
+//!
+//! [`Join`]: trait.Join.html
+//! [`StatefulOperator::stateful_binary_frontier`]: ../operator/trait.StatefulOperator.html#tymethod.stateful_binary_frontier
+//! [`JoinState`]: struct.JoinState.html
+use std::collections::BTreeMap;
 use std::hash::Hash;
 
 use fnv::FnvHashMap as HashMap;
 
-use timely::ExchangeData;
+use timely::{Data, ExchangeData};
 use timely::dataflow::{Stream, Scope};
+use timely::order::TotalOrder;
+use timely::progress::{PathSummary, Timestamp};
 
+use assigner::BinAssigner;
+use notificator::NotifyTimestamp;
 use operator::StatefulOperator;
-use ::Bin;
+use ::{Bin, Control, StatefulConfig};
 
 fn calculate_hash<T: Hash>(t: &T) -> u64 {
     use ::std::hash::Hasher;
@@ -16,50 +31,200 @@ fn calculate_hash<T: Hash>(t: &T) -> u64 {
     h.finish()
 }
 
-trait BinarySkeleton<S, K, V>
+/// Join state of one side: all values per key, with the time at which each value expires.
+#[derive(Clone)]
+pub struct JoinState<T: Ord, K: Hash+Eq, V> {
+    values: HashMap<K, Vec<(Option<T>, V)>>,
+    expiry: BTreeMap<T, Vec<K>>,
+}
+
+impl<T: Ord, K: Hash+Eq, V> Default for JoinState<T, K, V> {
+    fn default() -> Self {
+        Self { values: Default::default(), expiry: Default::default() }
+    }
+}
+
+impl<T: Ord+Clone, K: Hash+Eq+Clone, V> JoinState<T, K, V> {
+    /// Add a value for `key`, to be dropped at time `expires` if provided.
+    pub fn insert(&mut self, key: K, expires: Option<T>, value: V) {
+        if let Some(ref expires) = expires {
+            self.expiry.entry(expires.clone()).or_insert_with(Vec::new).push(key.clone());
+        }
+        self.values.entry(key).or_insert_with(Vec::new).push((expires, value));
+    }
+
+    /// The values for `key` that have not expired.
+    pub fn get<'a>(&'a self, key: &K) -> impl Iterator<Item=&'a V>+'a {
+        self.values.get(key).into_iter().flat_map(|values| values.iter().map(|(_, value)| value))
+    }
+
+    /// Drop all values expiring at or before `time`.
+    pub fn expire(&mut self, time: &T) {
+        self.expire_frontier(::std::slice::from_ref(time));
+    }
+
+    /// Drop all values that no time at or beyond `frontier` can match, i.e., those expiring at or
+    /// before all of its elements. An empty frontier drops all values that expire.
+    pub fn expire_frontier(&mut self, frontier: &[T]) {
+        let expired = |expires: &T| frontier.iter().all(|time| expires <= time);
+        while self.expiry.keys().next().map_or(false, |expires| expired(expires)) {
+            let expires = self.expiry.keys().next().cloned().unwrap();
+            for key in self.expiry.remove(&expires).unwrap() {
+                let empty = self.values.get_mut(&key).map_or(false, |values| {
+                    values.retain(|(value_expires, _)| value_expires.as_ref().map_or(true, |e| !expired(e)));
+                    values.is_empty()
+                });
+                if empty {
+                    self.values.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// The number of values stored.
+    pub fn len(&self) -> usize {
+        self.values.values().map(|values| values.len()).sum()
+    }
+
+    /// Whether no values are stored.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<T: Ord+'static, K: Hash+Eq+Clone+'static, V: 'static> IntoIterator for JoinState<T, K, V> {
+    type Item = (K, Option<T>, V);
+    type IntoIter = Box<dyn Iterator<Item=Self::Item>>;
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.values.into_iter().flat_map(|(key, values)| {
+            values.into_iter().map(move |(expires, value)| (key.clone(), expires, value))
+        }))
+    }
+}
+
+impl<T: Ord+Clone, K: Hash+Eq+Clone, V> Extend<(K, Option<T>, V)> for JoinState<T, K, V> {
+    fn extend<I: IntoIterator<Item=(K, Option<T>, V)>>(&mut self, iter: I) {
+        for (key, expires, value) in iter {
+            self.insert(key, expires, value);
+        }
+    }
+}
+
+/// Migratable equi-joins on streams of `(key, value)` pairs.
+///
+/// Each value is matched against the values of the other side that arrived at the same or earlier
+/// times. Results are produced at the later of the two times.
+pub trait Join<S, K, V>
     where
-        S: Scope, // The containing scope
-        S::Timestamp: ::timely::order::TotalOrder,
+        S: Scope,
+        S::Timestamp: TotalOrder+NotifyTimestamp,
         K: ExchangeData+Hash+Eq,
-        V: ExchangeData, // Input data
+        V: ExchangeData+Eq,
 {
-    fn left_join<V2>(&mut self, other: &Stream<S, (K, V2)>, name: &str, control: &Stream<S, ::Control>) -> Stream<S, (K, V, V2)>
+    /// Inner join, producing all pairs of values with equal keys.
+    fn join<V2, A>(&self, other: &Stream<S, (K, V2)>, control: &Stream<S, Control>, config: StatefulConfig<A>, name: &str) -> Stream<S, (K, V, V2)>
+        where
+            V2: ExchangeData+Eq,
+            A: BinAssigner,
+    ;
+
+    /// Left outer join. In addition to the pairs of `join`, produces `(key, value, None)` for
+    /// values of this stream for which no value of `other` has arrived yet.
+    fn left_join<V2, A>(&self, other: &Stream<S, (K, V2)>, control: &Stream<S, Control>, config: StatefulConfig<A>, name: &str) -> Stream<S, (K, V, Option<V2>)>
+        where
+            V2: ExchangeData+Eq,
+            A: BinAssigner,
+    ;
+
+    /// Inner join of values whose times are less than `window` apart. Values are dropped from
+    /// the state once the input frontier has passed their time by `window`.
+    fn window_join<V2, A>(&self, other: &Stream<S, (K, V2)>, window: <S::Timestamp as Timestamp>::Summary, control: &Stream<S, Control>, config: StatefulConfig<A>, name: &str) -> Stream<S, (K, V, V2)>
         where
             V2: ExchangeData+Eq,
-;
+            A: BinAssigner,
+    ;
 }
 
-impl<S, K, V> BinarySkeleton<S, K, V> for Stream<S, (K, V)>
+impl<S, K, V> Join<S, K, V> for Stream<S, (K, V)>
 where
     S: Scope, // The containing scope
-    S::Timestamp: ::timely::order::TotalOrder,
+    S::Timestamp: TotalOrder+NotifyTimestamp+ExchangeData+Hash,
     K: ExchangeData+Hash+Eq,
     V: ExchangeData+Eq, // Input data
 {
-    fn left_join<V2>(&mut self, other: &Stream<S, (K, V2)>, name: &str, control: &Stream<S, ::Control>) -> Stream<S, (K, V, V2)>
+    fn join<V2, A>(&self, other: &Stream<S, (K, V2)>, control: &Stream<S, Control>, config: StatefulConfig<A>, name: &str) -> Stream<S, (K, V, V2)>
         where
             V2: ExchangeData+Eq,
+            A: BinAssigner,
     {
-        self.stateful_binary(&control, other, |t| calculate_hash(&t.0), |t| calculate_hash(&t.0), name, |cap, data, bin1: &mut Bin<_, HashMap<K, V>, _>, bin2: &mut Bin<_, HashMap<K, Vec<V2>>, _>, output| {
-            let mut session = output.session(&cap);
-            let bin: &mut HashMap<_, _> = bin2.state();
-            for (_time, (key, value)) in data {
-                if let Some(mut d2) = bin.remove(&key) {
-                    session.give_iterator(d2.drain(..).map(|d| (key.clone(), value.clone(), d)));
-                }
-                bin1.state().insert(key.clone(), value.clone());
-            };
-        }, |cap, data, bin1, bin2, output| {
-            let mut session = output.session(&cap);
-            let state1 = bin1.state();
-            let state2 = bin2.state();
-            for (_time, (key, value)) in data {
-                if let Some(d1) = state1.get(&key) {
-                    session.give((key.clone(), d1.clone(), value.clone()));
-                } else {
-                    state2.entry(key.clone()).or_insert_with(Vec::new).push(value.clone());
-                }
-            };
-        })
+        join_core(self, other, None, control, config, name, |key: &K, v1: &V, v2: Option<&V2>| v2.map(|v2| (key.clone(), v1.clone(), v2.clone())))
+    }
+
+    fn left_join<V2, A>(&self, other: &Stream<S, (K, V2)>, control: &Stream<S, Control>, config: StatefulConfig<A>, name: &str) -> Stream<S, (K, V, Option<V2>)>
+        where
+            V2: ExchangeData+Eq,
+            A: BinAssigner,
+    {
+        join_core(self, other, None, control, config, name, |key: &K, v1: &V, v2: Option<&V2>| Some((key.clone(), v1.clone(), v2.cloned())))
+    }
+
+    fn window_join<V2, A>(&self, other: &Stream<S, (K, V2)>, window: <S::Timestamp as Timestamp>::Summary, control: &Stream<S, Control>, config: StatefulConfig<A>, name: &str) -> Stream<S, (K, V, V2)>
+        where
+            V2: ExchangeData+Eq,
+            A: BinAssigner,
+    {
+        join_core(self, other, Some(window), control, config, name, |key: &K, v1: &V, v2: Option<&V2>| v2.map(|v2| (key.clone(), v1.clone(), v2.clone())))
     }
 }
+
+/// Join two keyed streams, passing matched pairs and unmatched left values to `result`.
+///
+/// The operator folds ready times of both inputs in increasing order, so each record is matched
+/// against all records of the other side at earlier times, and results are produced at the time
+/// of the later record. The right input is the operator's first input, so at equal times right
+/// values are stored before left values look them up.
+fn join_core<S, K, V, V2, R, F, A>(left: &Stream<S, (K, V)>, right: &Stream<S, (K, V2)>, window: Option<<S::Timestamp as Timestamp>::Summary>, control: &Stream<S, Control>, config: StatefulConfig<A>, name: &str, result: F) -> Stream<S, R>
+    where
+        S: Scope,
+        S::Timestamp: TotalOrder+NotifyTimestamp+ExchangeData+Hash,
+        K: ExchangeData+Hash+Eq,
+        V: ExchangeData+Eq,
+        V2: ExchangeData+Eq,
+        R: Data,
+        F: Fn(&K, &V, Option<&V2>)->Option<R>+Clone+'static,
+        A: BinAssigner,
+{
+    let window2 = window.clone();
+    let result2 = result.clone();
+    right.stateful_binary_frontier(control, config, left, |t| calculate_hash(&t.0), |t| calculate_hash(&t.0), name, move |cap, data, bin2: &mut Bin<_, JoinState<S::Timestamp, K, V2>, _>, bin1: &mut Bin<_, JoinState<S::Timestamp, K, V>, _>, output| {
+        let mut session = output.session(&cap);
+        for (time, (key, value)) in data.drain(..) {
+            bin1.state().expire(&time);
+            bin2.state().expire(&time);
+            for value1 in bin1.state().get(&key) {
+                session.give_iterator(result2(&key, value1, Some(&value)).into_iter());
+            }
+            let expires = window2.as_ref().and_then(|window| window.results_in(&time));
+            bin2.state().insert(key, expires, value);
+        }
+    }, move |cap, data, bin2, bin1, output| {
+        let mut session = output.session(&cap);
+        for (time, (key, value)) in data.drain(..) {
+            bin1.state().expire(&time);
+            bin2.state().expire(&time);
+            let mut matched = false;
+            for value2 in bin2.state().get(&key) {
+                matched = true;
+                session.give_iterator(result(&key, &value, Some(value2)).into_iter());
+            }
+            if !matched {
+                session.give_iterator(result(&key, &value, None).into_iter());
+            }
+            let expires = window.as_ref().and_then(|window| window.results_in(&time));
+            bin1.state().insert(key, expires, value);
+        }
+    }, |frontier, bin2, bin1| {
+        bin1.state().expire_frontier(frontier);
+        bin2.state().expire_frontier(frontier);
+    })
+}
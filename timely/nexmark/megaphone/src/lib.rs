@@ -5,16 +5,27 @@ extern crate fnv;
 extern crate timely;
 extern crate abomonation;
 #[macro_use] extern crate abomonation_derive;
-
-mod stateful;
+#[cfg(feature = "codec-bincode")] extern crate bincode;
+#[cfg(feature = "codec-bincode")] extern crate serde;
+#[cfg(feature = "codec-lz4")] extern crate lz4;
+#[cfg(feature = "codec-zstd")] extern crate zstd;
+
+pub mod stateful;
+pub mod assigner;
+pub mod checkpoint;
+pub mod codec;
+pub mod control;
+pub mod controller;
 pub mod state_machine;
 pub mod join;
+pub mod metrics;
 pub mod notificator;
 pub mod operator;
 
-use timely::order::{PartialOrder, TotalOrder};
+use timely::order::PartialOrder;
 use timely::progress::frontier::Antichain;
-use timely::progress::Timestamp;
+
+use notificator::{Notify, NotifyTimestamp};
 
 /// A control message consisting of a sequence number, a total count of messages to be expected
 /// and an instruction.
@@ -44,20 +55,179 @@ type KeyType = u64;
 pub struct Key(KeyType);
 
 impl Key {
-    /// Calculate the bin id for this key.
-    ///
-    /// Warning: will go away once `BIN_SHIFT` ceases to exist.
-    pub fn bin(self) -> usize {
-        key_to_bin(self)
+    /// Construct a new `Key` from a key hash or ordered key.
+    pub fn new(key: u64) -> Self {
+        Key(key)
+    }
+
+    /// Calculate the bin id for this key with `1 << bin_shift` bins.
+    pub fn bin(self, bin_shift: usize) -> usize {
+        key_to_bin(self, bin_shift)
     }
 }
 
-/// Compute the bin for a key.
-///
-/// Warning: will go away once `BIN_SHIFT` ceases to exist.
+/// Compute the bin for a key, selecting the top `bin_shift` bits of the key.
 #[inline(always)]
-pub fn key_to_bin(key: Key) -> usize {
-    (key.0 >> (::std::mem::size_of::<KeyType>() * 8 - BIN_SHIFT)) as usize
+pub fn key_to_bin(key: Key, bin_shift: usize) -> usize {
+    (key.0 >> (::std::mem::size_of::<KeyType>() * 8 - bin_shift)) as usize
+}
+
+/// The bin shift used if nothing else is configured, i.e. 256 bins.
+pub const DEFAULT_BIN_SHIFT: usize = 8;
+
+/// The largest supported bin shift.
+pub const MAX_BIN_SHIFT: usize = 20;
+
+/// Determines how a `stateful` operator reacts to a malformed set of `Control` messages.
+///
+/// Errors are detected once the control input is complete for the configuration's time, or when
+/// installing it, and are never ignored silently: either the computation stops, or the error is
+/// reported as data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ControlErrorPolicy {
+    /// Panic with a message stating the configuration's time and the `ControlError`, bringing
+    /// down the computation.
+    Panic,
+    /// Drop the configuration, keep the currently active map and report the `ControlError` at
+    /// the configuration's time on the `errors` stream of the `StateStream`.
+    Reject,
+}
+
+/// Configuration shared by all instances of a `stateful` operator, assigning keys to bins with
+/// the `BinAssigner` `A`.
+#[derive(Clone, Debug)]
+pub struct StatefulConfig<A = assigner::Prefix> {
+    bin_shift: usize,
+    control_error_policy: ControlErrorPolicy,
+    state_chunk_size: usize,
+    load_tracker: Option<controller::LoadTracker>,
+    checkpoint: Option<checkpoint::CheckpointConfig>,
+    metrics: Option<metrics::Metrics>,
+    bin_assigner: A,
+    static_partitioning: bool,
+}
+
+impl StatefulConfig {
+    /// Construct a new configuration with `1 << bin_shift` bins.
+    ///
+    /// Panics if `bin_shift` is not in `1..=MAX_BIN_SHIFT`.
+    pub fn new(bin_shift: usize) -> Self {
+        assert!(bin_shift > 0 && bin_shift <= MAX_BIN_SHIFT, "bin shift must be in 1..={}, found {}", MAX_BIN_SHIFT, bin_shift);
+        Self { bin_shift, control_error_policy: ControlErrorPolicy::Panic, state_chunk_size: usize::max_value(), load_tracker: None, checkpoint: None, metrics: None, bin_assigner: assigner::Prefix, static_partitioning: false }
+    }
+
+}
+
+impl<A: assigner::BinAssigner> StatefulConfig<A> {
+    /// Select how to handle malformed configurations. Defaults to `ControlErrorPolicy::Panic`.
+    pub fn with_control_error_policy(mut self, policy: ControlErrorPolicy) -> Self {
+        self.control_error_policy = policy;
+        self
+    }
+
+    /// Limit the number of state records a `stateful` operator sends per activation while migrating
+    /// bins. Smaller chunks spread a migration over more activations, trading migration duration
+    /// for latency. Defaults to sending all state at once.
+    ///
+    /// Panics if `records` is zero.
+    pub fn with_state_chunk_size(mut self, records: usize) -> Self {
+        assert!(records > 0, "state chunk size must be positive");
+        self.state_chunk_size = records;
+        self
+    }
+
+    /// Record per-bin load in `tracker`, for example to drive a rebalancing controller.
+    pub fn with_load_tracker(mut self, tracker: controller::LoadTracker) -> Self {
+        self.load_tracker = Some(tracker);
+        self
+    }
+
+    /// Write checkpoints requested by `ControlInst::Checkpoint` and restore state as configured
+    /// by `checkpoint`.
+    pub fn with_checkpoint(mut self, checkpoint: checkpoint::CheckpointConfig) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Report per-bin statistics and migration progress to `metrics`.
+    pub fn with_metrics(mut self, metrics: metrics::Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Assign keys to bins with `assigner`. Defaults to `assigner::Prefix`.
+    pub fn with_bin_assigner<B: assigner::BinAssigner>(self, assigner: B) -> StatefulConfig<B> {
+        StatefulConfig {
+            bin_shift: self.bin_shift,
+            control_error_policy: self.control_error_policy,
+            state_chunk_size: self.state_chunk_size,
+            load_tracker: self.load_tracker,
+            checkpoint: self.checkpoint,
+            metrics: self.metrics,
+            bin_assigner: assigner,
+            static_partitioning: self.static_partitioning,
+        }
+    }
+
+    /// Keep the initial assignment of bins to workers and ignore all `Control` messages. Data
+    /// takes the same path as with migrations enabled, but does not wait for the control input,
+    /// which provides a baseline to measure the overhead of migrations against. Defaults to
+    /// `false`.
+    pub fn with_static_partitioning(mut self, static_partitioning: bool) -> Self {
+        self.static_partitioning = static_partitioning;
+        self
+    }
+
+    /// The number of bits of a key used to determine its bin.
+    pub fn bin_shift(&self) -> usize {
+        self.bin_shift
+    }
+
+    /// The number of bins, `1 << bin_shift`.
+    pub fn bins(&self) -> usize {
+        1 << self.bin_shift
+    }
+
+    /// The policy applied to malformed configurations.
+    pub fn control_error_policy(&self) -> ControlErrorPolicy {
+        self.control_error_policy
+    }
+
+    /// The maximum number of state records sent per activation.
+    pub fn state_chunk_size(&self) -> usize {
+        self.state_chunk_size
+    }
+
+    /// The tracker recording per-bin load, if any.
+    pub fn load_tracker(&self) -> Option<&controller::LoadTracker> {
+        self.load_tracker.as_ref()
+    }
+
+    /// The checkpoint configuration, if any.
+    pub fn checkpoint(&self) -> Option<&checkpoint::CheckpointConfig> {
+        self.checkpoint.as_ref()
+    }
+
+    /// The metrics handle, if any.
+    pub fn metrics(&self) -> Option<&metrics::Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// The assignment of keys to bins.
+    pub fn bin_assigner(&self) -> &A {
+        &self.bin_assigner
+    }
+
+    /// Whether `Control` messages are ignored.
+    pub fn static_partitioning(&self) -> bool {
+        self.static_partitioning
+    }
+}
+
+impl Default for StatefulConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_BIN_SHIFT)
+    }
 }
 
 impl ::std::ops::Deref for BinId {
@@ -81,6 +251,8 @@ pub enum ControlInst {
     Map(Vec<usize>),
     /// Provide a map update
     Move(BinId, /*worker*/ usize),
+    /// Checkpoint all bins once the configuration is installed
+    Checkpoint,
     /// No-op
     None,
 }
@@ -90,8 +262,49 @@ impl Control {
     pub fn new(sequence: u64, count: usize, inst: ControlInst) -> Self {
         Self { sequence, count, inst }
     }
+
+    /// The sequence number of the configuration this `Control` belongs to.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+}
+
+/// Reasons why a set of `Control` messages does not form a valid configuration.
+#[derive(Abomonation, Clone, Debug, Eq, PartialEq)]
+pub enum ControlError {
+    /// More `Control`s were received than announced by their count
+    TooManyControls(/*count*/ usize),
+    /// Fewer `Control`s were received than announced by their count
+    MissingControls(/*missing*/ usize),
+    /// A `Control` does not share the sequence number of the others at the same time
+    InconsistentSequence(/*expected*/ u64, /*found*/ u64),
+    /// A map does not provide exactly one worker per bin
+    InvalidMapLength(/*expected*/ usize, /*found*/ usize),
+    /// A move refers to a bin that does not exist
+    InvalidBin(BinId, /*bins*/ usize),
+    /// No `Control` was received
+    Empty,
+    /// Writing the checkpoint requested by a configuration failed. The configuration is still
+    /// installed.
+    CheckpointFailed(/*sequence*/ u64, /*error*/ String),
 }
 
+impl ::std::fmt::Display for ControlError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            ControlError::TooManyControls(count) => write!(f, "received more than {} controls", count),
+            ControlError::MissingControls(missing) => write!(f, "missing {} controls", missing),
+            ControlError::InconsistentSequence(expected, found) => write!(f, "inconsistent sequence number: expected {}, found {}", expected, found),
+            ControlError::InvalidMapLength(expected, found) => write!(f, "provided map does not have correct len: {} != {}", expected, found),
+            ControlError::InvalidBin(bin, bins) => write!(f, "bin {} out of range, only {} bins", *bin, bins),
+            ControlError::Empty => write!(f, "no controls received"),
+            ControlError::CheckpointFailed(sequence, ref error) => write!(f, "checkpoint {} failed: {}", sequence, error),
+        }
+    }
+}
+
+impl ::std::error::Error for ControlError {}
+
 /// A compiled set of control instructions
 #[derive(Debug)]
 pub struct ControlSet<T> {
@@ -101,6 +314,8 @@ pub struct ControlSet<T> {
     pub frontier: Antichain<T>,
     /// Explicit mapping of bins to workers
     pub map: Vec<usize>,
+    /// Whether to checkpoint state when installing this configuration
+    pub checkpoint: bool,
 }
 
 impl<T> ControlSet<T> {
@@ -126,18 +341,22 @@ impl<T: PartialOrder> ControlSetBuilder<T> {
 
     /// Add a new `Control` to this builder.
     ///
-    /// TODO: Currently, it will assert if count and sequence numbers do not match. Should fail
-    /// gracefully instead.
-    pub fn apply(&mut self, control: Control) {
+    /// Returns an error if count and sequence numbers do not match. The builder should be
+    /// discarded after an error.
+    pub fn apply(&mut self, control: Control) -> Result<(), ControlError> {
         if self.count.is_none() {
             self.count = Some(control.count);
         }
         if let Some(ref mut count) = self.count {
-            assert!(*count > 0, "Received incorrect number of Controls");
+            if *count == 0 {
+                return Err(ControlError::TooManyControls(control.count));
+            }
             *count -= 1;
         }
         if let Some(sequence) = self.sequence {
-            assert_eq!(sequence, control.sequence, "Received control with inconsistent sequence number");
+            if sequence != control.sequence {
+                return Err(ControlError::InconsistentSequence(sequence, control.sequence));
+            }
         } else {
             self.sequence = Some(control.sequence);
         }
@@ -145,7 +364,7 @@ impl<T: PartialOrder> ControlSetBuilder<T> {
             ControlInst::None => {},
             inst => self.instructions.push(inst),
         };
-
+        Ok(())
     }
 
     /// Provide a frontier to be used to construct the configuration's `Antichain` from.
@@ -153,58 +372,80 @@ impl<T: PartialOrder> ControlSetBuilder<T> {
         self.frontier.extend(caps);
     }
 
-    /// Build a `ControlSet` by consuming this builder.
-    pub fn build(self, previous: &ControlSet<T>) -> ControlSet<T> {
-        assert_eq!(0, self.count.unwrap_or(0));
+    /// Build a `ControlSet` by consuming this builder. Maps must provide `1 << bin_shift` entries.
+    pub fn build(self, previous: &ControlSet<T>, bin_shift: usize) -> Result<ControlSet<T>, ControlError> {
+        let missing = self.count.unwrap_or(0);
+        if missing > 0 {
+            return Err(ControlError::MissingControls(missing));
+        }
+        let sequence = self.sequence.ok_or(ControlError::Empty)?;
         let mut frontier = Antichain::new();
         for f in self.frontier {frontier.insert(f);}
 
         let mut map = previous.map().clone();
+        let mut checkpoint = false;
 
         for inst in self.instructions {
             match inst {
                 ControlInst::Map(ref new_map) => {
-                    assert_eq!(1 << BIN_SHIFT, new_map.len(), "provided map does not have correct len: {} != {}", 1 << BIN_SHIFT, new_map.len());
+                    if new_map.len() != 1 << bin_shift {
+                        return Err(ControlError::InvalidMapLength(1 << bin_shift, new_map.len()));
+                    }
                     map.clear();
                     map.extend( new_map.iter());
                 },
                 ControlInst::Move(BinId(bin), target) => {
-                    assert!(bin < (1 << BIN_SHIFT));
+                    if bin >= map.len() {
+                        return Err(ControlError::InvalidBin(BinId(bin), map.len()));
+                    }
                     map[bin] = target
                 },
+                ControlInst::Checkpoint => checkpoint = true,
                 ControlInst::None => {},
             }
         }
 
-        ControlSet {
-            sequence: self.sequence.unwrap(),
+        Ok(ControlSet {
+            sequence,
             frontier,
             map,
-        }
+            checkpoint,
+        })
     }
 }
 
-/// State abstraction. It encapsulates state assorted by bins and a notificator.
-pub struct State<T, D, N>
+/// State abstraction. It encapsulates state assorted by bins and a notificator, and finds the
+/// bin of a key with the `BinAssigner` `A`.
+pub struct State<T, D, N, A = assigner::Prefix>
     where
-        T: Timestamp + TotalOrder,
+        T: NotifyTimestamp,
 {
     bins: Vec<Option<Bin<T, D, N>>>,
+    bin_shift: usize,
+    assigner: A,
 }
 
-impl<T, D, N> State<T, D, N>
+impl<T, D, N, A> State<T, D, N, A>
     where
-        T: Timestamp + TotalOrder,
+        T: NotifyTimestamp,
+        A: assigner::BinAssigner,
 {
     /// Construct a new `State` with the provided vector of bins and a default `FrontierNotificator`.
-    fn new(bins: Vec<Option<Bin<T, D, N>>>) -> Self {
-        Self { bins }
+    fn new(bins: Vec<Option<Bin<T, D, N>>>, bin_shift: usize, assigner: A) -> Self {
+        debug_assert_eq!(bins.len(), 1 << bin_shift);
+        Self { bins, bin_shift, assigner }
     }
 
     /// Get the state associated with a key from this bin. Asserts if the state is not available.
     pub fn get(&mut self, key: Key) -> &mut Bin<T, D, N> {
-        assert!(self.bins[key_to_bin(key)].is_some(), "Accessing bin {} for key {:?}", key_to_bin(key), key);
-        self.bins[key_to_bin(key)].as_mut().expect("Trying to access non-available bin")
+        let bin = self.assigner.bin(key, self.bin_shift);
+        assert!(self.bins[bin].is_some(), "Accessing bin {} for key {:?}", bin, key);
+        self.bins[bin].as_mut().expect("Trying to access non-available bin")
+    }
+
+    /// The bin shift this state was constructed with.
+    pub fn bin_shift(&self) -> usize {
+        self.bin_shift
     }
 
     /// Iterate all bins. This might go away.
@@ -221,7 +462,7 @@ impl<T, D, N> State<T, D, N>
 /// A bin with data and a notificator.
 pub struct Bin<T, D, N>
     where
-        T: Timestamp + TotalOrder,
+        T: NotifyTimestamp,
 {
     data: D,
     notificator: ::stateful::Notificator<T, N>,
@@ -229,7 +470,7 @@ pub struct Bin<T, D, N>
 
 impl<T, D, N> Bin<T, D, N>
     where
-        T: Timestamp + TotalOrder,
+        T: NotifyTimestamp,
 {
     /// Obtain a mutable reference to the associated state object.
     pub fn state(&mut self) -> &mut D {
@@ -244,93 +485,14 @@ impl<T, D, N> Bin<T, D, N>
 
 impl<T, D, N> Default for Bin<T, D, N>
     where
-        T: Timestamp + TotalOrder,
+        T: NotifyTimestamp,
         D: Default,
 {
     /// Creates an empty `HashMap<K, V, S>`, with the `Default` value for the hasher.
     fn default() -> Self {
         Self {
             data: Default::default(),
-            notificator: ::stateful::Notificator::new(),
+            notificator: <::stateful::Notificator<T, N> as Notify<T, N>>::new(),
         }
     }
 }
-/// Static bin-shift parameter. Enable feature "bin-1" with no default features to set this value.
-#[cfg(feature = "bin-1")]
-pub const BIN_SHIFT: usize = 1;
-
-/// Static bin-shift parameter. Enable feature "bin-2" with no default features to set this value.
-#[cfg(feature = "bin-2")]
-pub const BIN_SHIFT: usize = 2;
-
-/// Static bin-shift parameter. Enable feature "bin-3" with no default features to set this value.
-#[cfg(feature = "bin-3")]
-pub const BIN_SHIFT: usize = 3;
-
-/// Static bin-shift parameter. Enable feature "bin-4" with no default features to set this value.
-#[cfg(feature = "bin-4")]
-pub const BIN_SHIFT: usize = 4;
-
-/// Static bin-shift parameter. Enable feature "bin-5" with no default features to set this value.
-#[cfg(feature = "bin-5")]
-pub const BIN_SHIFT: usize = 5;
-
-/// Static bin-shift parameter. Enable feature "bin-6" with no default features to set this value.
-#[cfg(feature = "bin-6")]
-pub const BIN_SHIFT: usize = 6;
-
-/// Static bin-shift parameter. Enable feature "bin-7" with no default features to set this value.
-#[cfg(feature = "bin-7")]
-pub const BIN_SHIFT: usize = 7;
-
-/// Static bin-shift parameter. Enable feature "bin-8" with no default features to set this value.
-#[cfg(feature = "bin-8")]
-pub const BIN_SHIFT: usize = 8;
-
-/// Static bin-shift parameter. Enable feature "bin-9" with no default features to set this value.
-#[cfg(feature = "bin-9")]
-pub const BIN_SHIFT: usize = 9;
-
-/// Static bin-shift parameter. Enable feature "bin-10" with no default features to set this value.
-#[cfg(feature = "bin-10")]
-pub const BIN_SHIFT: usize = 10;
-
-/// Static bin-shift parameter. Enable feature "bin-11" with no default features to set this value.
-#[cfg(feature = "bin-11")]
-pub const BIN_SHIFT: usize = 11;
-
-/// Static bin-shift parameter. Enable feature "bin-12" with no default features to set this value.
-#[cfg(feature = "bin-12")]
-pub const BIN_SHIFT: usize = 12;
-
-/// Static bin-shift parameter. Enable feature "bin-13" with no default features to set this value.
-#[cfg(feature = "bin-13")]
-pub const BIN_SHIFT: usize = 13;
-
-/// Static bin-shift parameter. Enable feature "bin-14" with no default features to set this value.
-#[cfg(feature = "bin-14")]
-pub const BIN_SHIFT: usize = 14;
-
-/// Static bin-shift parameter. Enable feature "bin-15" with no default features to set this value.
-#[cfg(feature = "bin-15")]
-pub const BIN_SHIFT: usize = 15;
-
-/// Static bin-shift parameter. Enable feature "bin-16" with no default features to set this value.
-#[cfg(feature = "bin-16")]
-pub const BIN_SHIFT: usize = 16;
-
-/// Static bin-shift parameter. Enable feature "bin-17" with no default features to set this value.
-#[cfg(feature = "bin-17")]
-pub const BIN_SHIFT: usize = 17;
-
-/// Static bin-shift parameter. Enable feature "bin-18" with no default features to set this value.
-#[cfg(feature = "bin-18")]
-pub const BIN_SHIFT: usize = 18;
-
-/// Static bin-shift parameter. Enable feature "bin-19" with no default features to set this value.
-#[cfg(feature = "bin-19")]
-pub const BIN_SHIFT: usize = 19;
-
-#[cfg(feature = "bin-20")]
-/// Static bin-shift parameter. Enable feature "bin-20" with no default features to set this value.
-pub const BIN_SHIFT: usize = 20;
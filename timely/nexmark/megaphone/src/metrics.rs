@@ -0,0 +1,231 @@
+//! Observability of `stateful` operators.
+//!
+//! A [`Metrics`] handle passed to `stateful` operators through their [`StatefulConfig`] collects
+//! per-bin statistics and the progress of migrations. Controllers and benchmark harnesses can
+//! poll the handle instead of parsing the operators' output.
+//!
+//! Each operator accumulates its metrics locally and publishes them to the handle at most once
+//! per [`Metrics::with_interval`], and once its inputs are complete. Metrics are kept apart per
+//! operator, identified by the operator's dataflow address.
+//!
+//! [`Metrics`]: struct.Metrics.html
+//! [`Metrics::with_interval`]: struct.Metrics.html#method.with_interval
+//! [`StatefulConfig`]: ../struct.StatefulConfig.html
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ::BinId;
+
+/// Identifies a `stateful` operator by its dataflow address, such as `"0-3"`.
+pub type OperatorId = String;
+
+/// Statistics of a bin, as observed by one worker.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BinMetrics {
+    /// Records this worker routed to the bin since the last report.
+    pub records: usize,
+    /// State records of the bin this worker migrated since the last report, if it did.
+    pub state_len: Option<usize>,
+    /// Notifications pending for the bin at this worker when the operator last published.
+    pub pending: usize,
+}
+
+/// The state a worker sent for a configuration.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigrationMetrics {
+    /// The sequence number of the configuration.
+    pub sequence: u64,
+    /// Bins sent to other workers.
+    pub bins: usize,
+    /// State records sent so far.
+    pub records: usize,
+    /// Serialized size of the state sent so far.
+    pub bytes: usize,
+    /// When the configuration was installed.
+    pub started: Instant,
+    /// When all state was sent, or `None` if the migration is in progress.
+    pub finished: Option<Instant>,
+}
+
+/// Per-worker metrics for `stateful` operators.
+///
+/// Clones share the same metrics. All `stateful` operators configured with the same handle
+/// report to it, each under its own `OperatorId`. Metrics are per worker, so each worker should
+/// construct its own handle.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    inner: Arc<Mutex<BTreeMap<OperatorId, OperatorMetrics>>>,
+    interval: Duration,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self { inner: Default::default(), interval: Duration::from_secs(1) }
+    }
+}
+
+/// Metrics of one operator, either published to a `Metrics` handle or accumulated locally.
+#[derive(Debug, Default)]
+struct OperatorMetrics {
+    bins: Vec<BinMetrics>,
+    migrations: Vec<MigrationMetrics>,
+}
+
+impl OperatorMetrics {
+    #[inline]
+    fn bin(&mut self, bin: usize) -> &mut BinMetrics {
+        if self.bins.len() <= bin {
+            self.bins.resize(bin + 1, Default::default());
+        }
+        &mut self.bins[bin]
+    }
+
+    #[inline]
+    fn migration(&mut self, sequence: u64) -> Option<&mut MigrationMetrics> {
+        self.migrations.iter_mut().find(|m| m.sequence == sequence)
+    }
+}
+
+impl Metrics {
+    /// Construct a new handle without any metrics, to which operators publish every second.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Let operators publish their metrics at most once per `interval`. Operators publish at
+    /// the end of an activation, so reports can lag by up to `interval` plus the time between
+    /// activations.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Construct the local recorder of operator `operator`.
+    pub(crate) fn recorder(&self, operator: OperatorId) -> MetricsRecorder {
+        MetricsRecorder {
+            metrics: self.clone(),
+            operator,
+            local: Default::default(),
+            published: Instant::now(),
+        }
+    }
+
+    /// Report the metrics of all bins that saw records, state or notifications, and reset the
+    /// record counts and state lengths.
+    pub fn drain_bins(&self) -> Vec<(OperatorId, BinId, BinMetrics)> {
+        let mut operators = self.inner.lock().expect("metrics poisoned");
+        let mut bins = Vec::new();
+        for (operator, state) in operators.iter_mut() {
+            for (bin, metrics) in state.bins.iter_mut().enumerate() {
+                if *metrics != Default::default() {
+                    bins.push((operator.clone(), BinId(bin), *metrics));
+                    metrics.records = 0;
+                    metrics.state_len = None;
+                }
+            }
+        }
+        bins
+    }
+
+    /// Report all migrations, including those in progress.
+    pub fn migrations(&self) -> Vec<(OperatorId, MigrationMetrics)> {
+        let operators = self.inner.lock().expect("metrics poisoned");
+        operators.iter()
+            .flat_map(|(operator, state)| state.migrations.iter().map(move |m| (operator.clone(), m.clone())))
+            .collect()
+    }
+
+    /// Report and forget finished migrations.
+    pub fn drain_migrations(&self) -> Vec<(OperatorId, MigrationMetrics)> {
+        let mut operators = self.inner.lock().expect("metrics poisoned");
+        let mut finished = Vec::new();
+        for (operator, state) in operators.iter_mut() {
+            let (done, in_progress) = state.migrations.drain(..).partition::<Vec<_>, _>(|m| m.finished.is_some());
+            state.migrations = in_progress;
+            finished.extend(done.into_iter().map(|m| (operator.clone(), m)));
+        }
+        finished
+    }
+}
+
+/// Accumulates the metrics of an operator instance without synchronization, and publishes them
+/// to its `Metrics` handle.
+pub(crate) struct MetricsRecorder {
+    metrics: Metrics,
+    operator: OperatorId,
+    local: OperatorMetrics,
+    published: Instant,
+}
+
+impl MetricsRecorder {
+    /// Account for a record routed to `bin`.
+    #[inline]
+    pub(crate) fn record(&mut self, bin: usize) {
+        self.local.bin(bin).records += 1;
+    }
+
+    /// Account for the start of a migration of `bin` with `state_len` state records.
+    pub(crate) fn migration_started(&mut self, sequence: u64, bin: usize, state_len: usize) {
+        self.local.bin(bin).state_len = Some(state_len);
+        if let Some(migration) = self.local.migration(sequence) {
+            migration.bins += 1;
+            return;
+        }
+        self.local.migrations.push(MigrationMetrics {
+            sequence,
+            bins: 1,
+            records: 0,
+            bytes: 0,
+            started: Instant::now(),
+            finished: None,
+        });
+    }
+
+    /// Account for `records` state records of `bytes` serialized size sent for `sequence`.
+    pub(crate) fn migrated(&mut self, sequence: u64, records: usize, bytes: usize) {
+        if let Some(migration) = self.local.migration(sequence) {
+            migration.records += records;
+            migration.bytes += bytes;
+        }
+    }
+
+    /// Mark the migration for `sequence` as finished.
+    pub(crate) fn migration_finished(&mut self, sequence: u64) {
+        if let Some(migration) = self.local.migration(sequence) {
+            migration.finished = Some(Instant::now());
+        }
+    }
+
+    /// Whether the operator should publish, which it must once its inputs are `complete`.
+    pub(crate) fn publish_due(&self, complete: bool) -> bool {
+        complete || self.published.elapsed() >= self.metrics.interval
+    }
+
+    /// Publish the accumulated metrics and the number of notifications `pending` for each bin.
+    pub(crate) fn publish<I: IntoIterator<Item=usize>>(&mut self, pending: I) {
+        let mut operators = self.metrics.inner.lock().expect("metrics poisoned");
+        let shared = operators.entry(self.operator.clone()).or_insert_with(Default::default);
+        for (bin, pending) in pending.into_iter().enumerate() {
+            shared.bin(bin).pending = pending;
+        }
+        for (bin, local) in self.local.bins.iter_mut().enumerate() {
+            let shared = shared.bin(bin);
+            shared.records += local.records;
+            if local.state_len.is_some() {
+                shared.state_len = local.state_len;
+            }
+            *local = Default::default();
+        }
+        for local in self.local.migrations.iter() {
+            match shared.migration(local.sequence) {
+                Some(migration) => *migration = local.clone(),
+                None => shared.migrations.push(local.clone()),
+            }
+        }
+        // Finished migrations will not change anymore
+        self.local.migrations.retain(|m| m.finished.is_none());
+        self.published = Instant::now();
+    }
+}
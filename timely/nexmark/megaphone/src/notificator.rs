@@ -1,21 +1,100 @@
 //! Specialized notificators for Megaphone.
 
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, BTreeMap};
 
-use timely::order::TotalOrder;
+use std::time::Duration;
+
+use timely::order::{Product, TotalOrder};
 use timely::progress::frontier::MutableAntichain;
 use timely::progress::Timestamp;
 use timely::dataflow::operators::Capability;
 
 /// Common trait to all notificator implementations.
-///
-/// Currently only prvides `drain`. TODO: Allow to schedule notifications.
 pub trait Notify<T: Timestamp, D> {
-    /// Drain all pending notifications that are not in advance of `frontiers`.
+    /// Allocates a notificator without pending notifications.
+    fn new() -> Self where Self: Sized;
+
+    /// Requests a notification with `data` at `time`, which must be greater or equal to the time
+    /// of `cap`.
+    fn notify_at_data(&mut self, cap: &Capability<T>, time: T, data: D);
+
+    /// Drain pending notifications that are not in advance of `frontiers`.
     ///
     /// If drain returns `Some(cap)` this indicates that notifications were enqueud to `buffer`.
-    /// The buffer may be cleared by `drain`.
+    /// The buffer may be cleared by `drain`. All enqueued times are greater or equal to the
+    /// capability's time. For partially ordered times, this might not hold for all available
+    /// notifications at once, so callers should drain until `None` is returned.
     fn drain(&mut self, frontiers: &[&MutableAntichain<T>], buffer: &mut Vec<(T, D)>) -> Option<Capability<T>>;
+
+    /// Descructures the notificator to obtain pending `(time, data)` pairs.
+    fn pending(self) -> impl Iterator<Item=(T, D)> where Self: Sized;
+
+    /// Iterate pending `(time, data)` pairs without consuming them.
+    fn pending_iter(&self) -> impl Iterator<Item=(&T, &D)>;
+
+    /// The number of pending notifications.
+    fn pending_len(&self) -> usize;
+
+    /// Repeatedly calls `logic` till exhaustion of the notifications made available by inspecting
+    /// the frontiers.
+    ///
+    /// `logic` receives a capability for `t`, the timestamp being notified.
+    #[inline]
+    fn for_each_data<F: FnMut(&Capability<T>, T, D, &mut Self)>(&mut self, frontiers: &[&MutableAntichain<T>], mut logic: F) where Self: Sized {
+        let mut vec = Vec::new();
+        while let Some(cap) = self.drain(frontiers, &mut vec) {
+            for (time, data) in vec.drain(..) {
+                logic(&cap, time, data, self);
+            }
+        }
+    }
+}
+
+/// Timestamps that select the notificator stateful operators use.
+///
+/// Totally ordered timestamps use the `TotalOrderFrontierNotificator`, which retains a single
+/// capability and serves notifications from a heap. Other timestamps, such as the `Product`s of
+/// iterative scopes, use the `PartialOrderFrontierNotificator`, whose `drain` is linear in the
+/// number of pending notifications.
+///
+/// The primitive integers, `()`, `Duration` and `Product` implement this trait. Other timestamps
+/// implement it by naming their notificator, or with the `total_order_notify_timestamp!` macro if
+/// they are totally ordered:
+///
+/// ```ignore
+/// #[macro_use]
+/// extern crate dynamic_scaling_mechanism;
+///
+/// // `MyTime` is a `Timestamp` and `TotalOrder`
+/// total_order_notify_timestamp!(MyTime);
+///
+/// // or, for timestamps that are only partially ordered
+/// impl NotifyTimestamp for MyPartialTime {
+///     type Notificator<D> = PartialOrderFrontierNotificator<MyPartialTime, D>;
+/// }
+/// ```
+pub trait NotifyTimestamp: Timestamp {
+    /// The notificator for notifications with data `D`.
+    type Notificator<D>: Notify<Self, D>;
+}
+
+/// Implements `NotifyTimestamp` for totally ordered timestamp types, which then use the
+/// `TotalOrderFrontierNotificator`.
+#[macro_export]
+macro_rules! total_order_notify_timestamp {
+    ($($t:ty),*) => {
+        $(
+            impl $crate::notificator::NotifyTimestamp for $t {
+                type Notificator<D> = $crate::notificator::TotalOrderFrontierNotificator<$t, D>;
+            }
+        )*
+    }
+}
+
+total_order_notify_timestamp!((), usize, u8, u16, u32, u64, u128, isize, i8, i16, i32, i64, i128, Duration);
+
+impl<TOuter: Timestamp, TInner: Timestamp> NotifyTimestamp for Product<TOuter, TInner> {
+    type Notificator<D> = PartialOrderFrontierNotificator<Product<TOuter, TInner>, D>;
 }
 
 /// Tracks requests for notification and delivers available notifications.
@@ -231,10 +310,40 @@ impl<T: Timestamp + TotalOrder, D> TotalOrderFrontierNotificator<T, D> {
     pub fn pending(self) -> impl Iterator<Item=(T, D)> {
         self.pending.into_iter().map(|e| (e.element, e.data))
     }
+
+    /// Iterate pending `(time, data)` pairs without consuming them, in no particular order.
+    pub fn pending_iter(&self) -> impl Iterator<Item=(&T, &D)> {
+        self.pending.iter().map(|e| (&e.element, &e.data))
+    }
+
+    /// The number of pending notifications.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
 }
 
 impl<T: Timestamp + TotalOrder, D> Notify<T, D> for TotalOrderFrontierNotificator<T, D> {
 
+    fn new() -> Self {
+        TotalOrderFrontierNotificator::new()
+    }
+
+    fn notify_at_data(&mut self, cap: &Capability<T>, time: T, data: D) {
+        TotalOrderFrontierNotificator::notify_at_data(self, cap, time, data)
+    }
+
+    fn pending(self) -> impl Iterator<Item=(T, D)> {
+        TotalOrderFrontierNotificator::pending(self)
+    }
+
+    fn pending_iter(&self) -> impl Iterator<Item=(&T, &D)> {
+        TotalOrderFrontierNotificator::pending_iter(self)
+    }
+
+    fn pending_len(&self) -> usize {
+        TotalOrderFrontierNotificator::pending_len(self)
+    }
+
     #[inline]
     fn drain(&mut self, frontiers: &[&MutableAntichain<T>], buffer: &mut Vec<(T, D)>) -> Option<Capability<T>> {
         // By invariant, nothing in self.available is greater_equal anything in self.pending.
@@ -271,6 +380,147 @@ impl<T: Timestamp + TotalOrder, D> Notify<T, D> for TotalOrderFrontierNotificato
     }
 }
 
+/// Tracks requests for notification and delivers available notifications, for partially ordered
+/// time domains.
+///
+/// Like the `TotalOrderFrontierNotificator`, notifications carry data and are served once no
+/// frontier element is less-or-equal to them. Instead of a single capability, the notificator
+/// retains a capability for each minimal pending time. Each call to `drain` delivers the
+/// available notifications greater or equal to one of these times, in increasing time order.
+///
+/// Minimal times are determined assuming that the timestamps' `Ord` implementation extends their
+/// partial order, as is the case for timely's timestamps.
+#[derive(Default)]
+pub struct PartialOrderFrontierNotificator<T: Timestamp, D = ()> {
+    capabilities: Vec<Capability<T>>,
+    pending: BTreeMap<T, Vec<D>>,
+}
+
+impl<T: Timestamp> PartialOrderFrontierNotificator<T, ()> {
+
+    /// Requests a notification at the time associated with capability `cap`.
+    #[inline]
+    pub fn notify_at(&mut self, cap: &Capability<T>) {
+        self.notify_at_data(cap, cap.time().clone(), ());
+    }
+
+    /// Repeatedly calls `logic` till exhaustion of the notifications made available by inspecting
+    /// the frontiers.
+    ///
+    /// `logic` receives a capability for `t`, the timestamp being notified.
+    #[inline]
+    pub fn for_each<'a, F: FnMut(&Capability<T>, T, &mut Self)>(&mut self, frontiers: &'a [&'a MutableAntichain<T>], mut logic: F) {
+        self.for_each_data(frontiers, |cap, time, (), notificator| logic(cap, time, notificator));
+    }
+}
+
+impl<T: Timestamp, D> PartialOrderFrontierNotificator<T, D> {
+    /// Allocates a new `PartialOrderFrontierNotificator`.
+    pub fn new() -> Self {
+        Self {
+            capabilities: Vec::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Requests a notification with `data` at `time`, which must be greater or equal to the time
+    /// of `cap`.
+    #[inline]
+    pub fn notify_at_data(&mut self, cap: &Capability<T>, time: T, data: D) {
+        assert!(cap.time().less_equal(&time), "provided capability must be <= notification time, found {:?} and {:?}", cap.time(), time);
+        if !self.capabilities.iter().any(|c| c.time().less_equal(&time)) {
+            self.capabilities.retain(|c| !time.less_equal(c.time()));
+            self.capabilities.push(cap.delayed(&time));
+        }
+        self.pending.entry(time).or_insert_with(Vec::new).push(data);
+    }
+
+    /// Repeatedly calls `logic` till exhaustion of the notifications made available by inspecting
+    /// the frontiers.
+    ///
+    /// `logic` receives a capability for `t`, the timestamp being notified.
+    #[inline]
+    pub fn for_each_data<'a, F: FnMut(&Capability<T>, T, D, &mut Self)>(&mut self, frontiers: &'a [&'a MutableAntichain<T>], mut logic: F) {
+        let mut vec = Vec::new();
+        while let Some(cap) = self.drain(frontiers, &mut vec) {
+            for (time, data) in vec.drain(..) {
+                logic(&cap, time, data, self);
+            }
+        }
+    }
+
+    /// Descructures the notificator to obtain pending `(time, data)` pairs.
+    pub fn pending(self) -> impl Iterator<Item=(T, D)> {
+        self.pending.into_iter().flat_map(|(time, data)| data.into_iter().map(move |d| (time.clone(), d)))
+    }
+
+    /// Iterate pending `(time, data)` pairs without consuming them.
+    pub fn pending_iter(&self) -> impl Iterator<Item=(&T, &D)> {
+        self.pending.iter().flat_map(|(time, data)| data.iter().map(move |d| (time, d)))
+    }
+
+    /// The number of pending notifications.
+    pub fn pending_len(&self) -> usize {
+        self.pending.values().map(|data| data.len()).sum()
+    }
+}
+
+impl<T: Timestamp, D> Notify<T, D> for PartialOrderFrontierNotificator<T, D> {
+
+    fn new() -> Self {
+        PartialOrderFrontierNotificator::new()
+    }
+
+    fn notify_at_data(&mut self, cap: &Capability<T>, time: T, data: D) {
+        PartialOrderFrontierNotificator::notify_at_data(self, cap, time, data)
+    }
+
+    fn pending(self) -> impl Iterator<Item=(T, D)> {
+        PartialOrderFrontierNotificator::pending(self)
+    }
+
+    fn pending_iter(&self) -> impl Iterator<Item=(&T, &D)> {
+        PartialOrderFrontierNotificator::pending_iter(self)
+    }
+
+    fn pending_len(&self) -> usize {
+        PartialOrderFrontierNotificator::pending_len(self)
+    }
+
+    fn drain(&mut self, frontiers: &[&MutableAntichain<T>], buffer: &mut Vec<(T, D)>) -> Option<Capability<T>> {
+        buffer.clear();
+        let available = |time: &T| frontiers.iter().all(|f| !f.less_equal(time));
+
+        // Deliver the first available time and all available times greater than it
+        let first = self.pending.keys().find(|t| available(t))?.clone();
+        let times: Vec<T> = self.pending.keys().filter(|t| first.less_equal(t) && available(t)).cloned().collect();
+        for time in times {
+            let data = self.pending.remove(&time).expect("time must be pending");
+            buffer.extend(data.into_iter().map(|d| (time.clone(), d)));
+        }
+        let result = self.capabilities.iter()
+            .find(|c| c.time().less_equal(&first))
+            .expect("no capability for pending notification")
+            .delayed(&first);
+
+        // Retain a capability for each remaining minimal time. Iterating in `Ord` order visits
+        // minimal times before any time greater than them.
+        let mut capabilities: Vec<Capability<T>> = Vec::new();
+        for time in self.pending.keys() {
+            if !capabilities.iter().any(|c| c.time().less_equal(time)) {
+                let cap = self.capabilities.iter()
+                    .find(|c| c.time().less_equal(time))
+                    .expect("no capability for pending notification")
+                    .delayed(time);
+                capabilities.push(cap);
+            }
+        }
+        self.capabilities = capabilities;
+
+        Some(result)
+    }
+}
+
 struct OrderReversed<T, D> {
     pub element: T,
     pub data: D,
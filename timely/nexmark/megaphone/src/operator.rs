@@ -10,17 +10,19 @@ use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
 use timely::Data;
 use timely::dataflow::operators::Capability;
 use timely::dataflow::operators::generic::OutputHandle;
-use timely::order::TotalOrder;
+use timely::progress::Antichain;
+use timely::order::PartialOrder;
 
-use ::{Bin, Control, Key, State};
+use ::{Bin, Control, Key, State, StatefulConfig};
+use assigner::BinAssigner;
 use stateful::{Stateful, apply_state_updates, Notificator};
-use notificator::{Notify};
+use notificator::{Notify, NotifyTimestamp};
 
 /// Building blocks for single- and dual-input stateful operators.
 pub trait StatefulOperator<G, D1>
     where
         G: Scope,
-        G::Timestamp: TotalOrder,
+        G::Timestamp: NotifyTimestamp,
         D1: ExchangeData + Eq,
 {
     /// Stateful operator with a single input.
@@ -33,7 +35,8 @@ pub trait StatefulOperator<G, D1>
             &mut Vec<(G::Timestamp, D1)>,
             &mut Bin<G::Timestamp, S, D1>,
             &mut OutputHandle<G::Timestamp, D2, Tee<G::Timestamp, D2>>) + 'static,    // state update logic
-    >(&self, control: &Stream<G, Control>, key: B, name: &str, fold: F) -> Stream<G, D2>
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, key: B, name: &str, fold: F) -> Stream<G, D2>
     ;
 
     /// Stateful operator with a single input and input transformation.
@@ -47,15 +50,19 @@ pub trait StatefulOperator<G, D1>
             &mut Vec<(G::Timestamp, N)>,
             &mut Bin<G::Timestamp, S, N>,
             &mut OutputHandle<G::Timestamp, D2, Tee<G::Timestamp, D2>>) + 'static,    // state update logic
-        C: FnMut(&mut State<G::Timestamp, S, N>,
+        C: FnMut(&mut State<G::Timestamp, S, N, A>,
             &Capability<G::Timestamp>,
             G::Timestamp,
             RefOrMut<Vec<(usize, Key, D1)>>,
             &mut OutputHandle<G::Timestamp, D2, Tee<G::Timestamp, D2>>) + 'static,
-    >(&self, control: &Stream<G, Control>, key: B, name: &str, consume: C, fold: F) -> Stream<G, D2>
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, key: B, name: &str, consume: C, fold: F) -> Stream<G, D2>
     ;
 
     /// Stateful operator with two inputs.
+    ///
+    /// The ready data of each pair of bins is folded in increasing time order across both inputs,
+    /// one time at a time and with a capability for that time. At equal times, `fold1` runs first.
     fn stateful_binary<
         D2: ExchangeData+Eq,                         // input type
         D3: Data,                                    // output type
@@ -75,7 +82,37 @@ pub trait StatefulOperator<G, D1>
             &mut Bin<G::Timestamp, S1, D1>,
             &mut Bin<G::Timestamp, S2, D2>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic, input 2
-    >(&self, control: &Stream<G, Control>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, fold1: F1, fold2: F2) -> Stream<G, D3>
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, fold1: F1, fold2: F2) -> Stream<G, D3>
+    ;
+
+    /// Stateful operator with two inputs, which passes the joint frontier of its inputs to
+    /// `frontier` for each pair of bins held by this worker whenever the frontier advances, after
+    /// all data before it has been folded. This allows dropping state independently of arriving data.
+    fn stateful_binary_frontier<
+        D2: ExchangeData+Eq,                         // input type
+        D3: Data,                                    // output type
+        B1: Fn(&D1)->u64+'static,                    // Key extraction function, input 1
+        B2: Fn(&D2)->u64+'static,                    // Key extraction function, input 2
+        S1: Clone+IntoIterator<Item=W1>+Extend<W1>+Default+'static, // State type, input 1
+        S2: Clone+IntoIterator<Item=W2>+Extend<W2>+Default+'static, // State type, input 2
+        W1: ExchangeData,                            // State format on the wire, input 1
+        W2: ExchangeData,                            // State format on the wire, input 2
+        F1: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, D1)>,
+            &mut Bin<G::Timestamp, S1, D1>,
+            &mut Bin<G::Timestamp, S2, D2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic, input 1
+        F2: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, D2)>,
+            &mut Bin<G::Timestamp, S1, D1>,
+            &mut Bin<G::Timestamp, S2, D2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic, input 2
+        E: FnMut(&[G::Timestamp],
+            &mut Bin<G::Timestamp, S1, D1>,
+            &mut Bin<G::Timestamp, S2, D2>) + 'static,    // frontier logic
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, fold1: F1, fold2: F2, frontier: E) -> Stream<G, D3>
     ;
 
     /// Stateful operator with two inputs and input transformation.
@@ -100,30 +137,72 @@ pub trait StatefulOperator<G, D1>
             &mut Bin<G::Timestamp, S1, N1>,
             &mut Bin<G::Timestamp, S2, N2>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
-        C1: FnMut(&mut State<G::Timestamp, S1, N1>,
+        C1: FnMut(&mut State<G::Timestamp, S1, N1, A>,
+            &Capability<G::Timestamp>,
+            G::Timestamp,
+            RefOrMut<Vec<(usize, Key, D1)>>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
+        C2: FnMut(&mut State<G::Timestamp, S2, N2, A>,
+            &Capability<G::Timestamp>,
+            G::Timestamp,
+            RefOrMut<Vec<(usize, Key, D2)>>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, input1: C1, input2: C2, fold1: F1, fold2: F2) -> Stream<G, D3>
+    ;
+
+    /// Stateful operator with two inputs, input transformation and frontier logic, see
+    /// `stateful_binary_frontier`.
+    fn stateful_binary_input_frontier<
+        D2: ExchangeData+Eq,                         // input type
+        D3: Data,                                    // output type
+        N1: ExchangeData,
+        N2: ExchangeData,
+        B1: Fn(&D1)->u64+'static,
+        B2: Fn(&D2)->u64+'static,
+        S1: Clone+IntoIterator<Item=W1>+Extend<W1>+Default+'static,
+        S2: Clone+IntoIterator<Item=W2>+Extend<W2>+Default+'static,
+        W1: ExchangeData,                            // State format on the wire
+        W2: ExchangeData,                            // State format on the wire
+        F1: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, N1)>,
+            &mut Bin<G::Timestamp, S1, N1>,
+            &mut Bin<G::Timestamp, S2, N2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
+        F2: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, N2)>,
+            &mut Bin<G::Timestamp, S1, N1>,
+            &mut Bin<G::Timestamp, S2, N2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
+        C1: FnMut(&mut State<G::Timestamp, S1, N1, A>,
             &Capability<G::Timestamp>,
             G::Timestamp,
             RefOrMut<Vec<(usize, Key, D1)>>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
-        C2: FnMut(&mut State<G::Timestamp, S2, N2>,
+        C2: FnMut(&mut State<G::Timestamp, S2, N2, A>,
             &Capability<G::Timestamp>,
             G::Timestamp,
             RefOrMut<Vec<(usize, Key, D2)>>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
-    >(&self, control: &Stream<G, Control>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, input1: C1, input2: C2, fold1: F1, fold2: F2) -> Stream<G, D3>
+        E: FnMut(&[G::Timestamp],
+            &mut Bin<G::Timestamp, S1, N1>,
+            &mut Bin<G::Timestamp, S2, N2>) + 'static,    // frontier logic
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, input1: C1, input2: C2, fold1: F1, fold2: F2, frontier: E) -> Stream<G, D3>
     ;
 
     /// Move state to a worker as specified in the control input. Do not maintain state.
-    fn distribute<B1>(&self, control: &Stream<G, Control>, key: B1, name: &str) -> Stream<G, (usize, Key, D1)>
+    fn distribute<B1, A>(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, key: B1, name: &str) -> Stream<G, (usize, Key, D1)>
     where
         B1: Fn(&D1)->u64+'static,
+        A: BinAssigner,
     ;
 }
 
 impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
     where
         G: Scope, // The containing scope
-        G::Timestamp: TotalOrder,
+        G::Timestamp: NotifyTimestamp,
         D1: ExchangeData+Eq, // Input data
 {
     fn stateful_unary<
@@ -135,9 +214,10 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
             &mut Vec<(G::Timestamp, D1)>,
             &mut Bin<G::Timestamp, S, D1>,
             &mut OutputHandle<G::Timestamp, D2, Tee<G::Timestamp, D2>>) + 'static,    // state update logic
-    >(&self, control: &Stream<G, Control>, key: B, name: &str, mut fold: F) -> Stream<G, D2>
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, key: B, name: &str, mut fold: F) -> Stream<G, D2>
     {
-        let stateful = self.stateful(key, control);
+        let stateful = self.stateful(key, control, config);
         let states = stateful.state.clone();
 
         let mut builder = OperatorBuilder::new(name.to_owned(), self.scope());
@@ -149,7 +229,7 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
 
         let mut state_update_buffer = vec![];
 
-        let mut notificator = Notificator::new();
+        let mut notificator = <Notificator<G::Timestamp, Vec<(usize, Key, D1)>> as Notify<_, _>>::new();
 
         let mut not_drain = Vec::new();
         let mut bin_drain = Vec::new();
@@ -172,7 +252,7 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
                     notificator.notify_at_data(&cap, cap.time().clone(), data_buffer);
                 }
 
-                if let Some(cap) = notificator.drain(&[&frontiers[0], &frontiers[1]], &mut not_drain) {
+                while let Some(cap) = notificator.drain(&[&frontiers[0], &frontiers[1]], &mut not_drain) {
                     for (time, mut keyed_data) in not_drain.drain(..) {
                         for (_, key_id, d) in keyed_data.drain(..) {
                             states.get(key_id).notificator.notify_at_data(&cap, time.clone(), d);
@@ -183,7 +263,7 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
                 // go through each time with data
                 for bin in states.bins.iter_mut().filter(|b| b.is_some()) {
                     let bin = bin.as_mut().unwrap();
-                    if let Some(cap) = bin.notificator().drain(&[&frontiers[0], &frontiers[1]], &mut bin_drain) {
+                    while let Some(cap) = bin.notificator().drain(&[&frontiers[0], &frontiers[1]], &mut bin_drain) {
                         fold(&cap, &mut bin_drain, bin, &mut output_handle);
                     }
                 }
@@ -204,14 +284,15 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
             &mut Vec<(G::Timestamp, N)>,
             &mut Bin<G::Timestamp, S, N>,
             &mut OutputHandle<G::Timestamp, D2, Tee<G::Timestamp, D2>>) + 'static,    // state update logic
-        C: FnMut(&mut State<G::Timestamp, S, N>,
+        C: FnMut(&mut State<G::Timestamp, S, N, A>,
             &Capability<G::Timestamp>,
             G::Timestamp,
             RefOrMut<Vec<(usize, Key, D1)>>,
             &mut OutputHandle<G::Timestamp, D2, Tee<G::Timestamp, D2>>) + 'static,
-    >(&self, control: &Stream<G, Control>, key: B, name: &str, mut consume: C, mut fold: F) -> Stream<G, D2>
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, key: B, name: &str, mut consume: C, mut fold: F) -> Stream<G, D2>
     {
-        let stateful = self.stateful(key, control);
+        let stateful = self.stateful(key, control, config);
         let states = stateful.state.clone();
 
         let mut builder = OperatorBuilder::new(name.to_owned(), self.scope());
@@ -222,7 +303,7 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
         let (mut output, stream) = builder.new_output();
 
         let mut state_update_buffer = vec![];
-        let mut notificator = Notificator::new();
+        let mut notificator = <Notificator<G::Timestamp, Vec<(usize, Key, D1)>> as Notify<_, _>>::new();
 
         let mut not_drain = Vec::new();
         let mut bin_drain = Vec::new();
@@ -248,7 +329,7 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
 //                    }
                 }
 
-                if let Some(cap) = notificator.drain(&[&frontiers[0], &frontiers[1]], &mut not_drain) {
+                while let Some(cap) = notificator.drain(&[&frontiers[0], &frontiers[1]], &mut not_drain) {
                     for (time, mut data) in not_drain.drain(..) {
                         consume(&mut states, &cap, time, RefOrMut::Mut(&mut data), &mut output_handle);
                     }
@@ -257,7 +338,7 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
                 // go through each time with data
                 for bin in states.bins.iter_mut().filter(|b| b.is_some()) {
                     let bin = bin.as_mut().unwrap();
-                    if let Some(cap) = bin.notificator().drain(&[&frontiers[0], &frontiers[1]], &mut bin_drain) {
+                    while let Some(cap) = bin.notificator().drain(&[&frontiers[0], &frontiers[1]], &mut bin_drain) {
                         fold(&cap, &mut bin_drain, bin, &mut output_handle);
                     }
                 }
@@ -287,13 +368,43 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
             &mut Bin<G::Timestamp, S1, D1>,
             &mut Bin<G::Timestamp, S2, D2>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
-    >(&self, control: &Stream<G, Control>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, fold1: F1, fold2: F2) -> Stream<G, D3>
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, fold1: F1, fold2: F2) -> Stream<G, D3>
+    {
+        self.stateful_binary_frontier(control, config, other, key1, key2, name, fold1, fold2,
+            |_frontier: &[G::Timestamp], _bin1: &mut Bin<G::Timestamp, S1, D1>, _bin2: &mut Bin<G::Timestamp, S2, D2>| {})
+    }
+
+    fn stateful_binary_frontier<
+        D2: ExchangeData+Eq,                         // input type
+        D3: Data,                                    // output type
+        B1: Fn(&D1)->u64+'static,
+        B2: Fn(&D2)->u64+'static,
+        S1: Clone+IntoIterator<Item=W1>+Extend<W1>+Default+'static,
+        S2: Clone+IntoIterator<Item=W2>+Extend<W2>+Default+'static,
+        W1: ExchangeData,                            // State format on the wire
+        W2: ExchangeData,                            // State format on the wire
+        F1: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, D1)>,
+            &mut Bin<G::Timestamp, S1, D1>,
+            &mut Bin<G::Timestamp, S2, D2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
+        F2: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, D2)>,
+            &mut Bin<G::Timestamp, S1, D1>,
+            &mut Bin<G::Timestamp, S2, D2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
+        E: FnMut(&[G::Timestamp],
+            &mut Bin<G::Timestamp, S1, D1>,
+            &mut Bin<G::Timestamp, S2, D2>) + 'static,    // frontier logic
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, fold1: F1, fold2: F2, frontier: E) -> Stream<G, D3>
     {
 
         let mut data1_buffer = vec![];
         let mut data2_buffer = vec![];
 
-        self.stateful_binary_input(control, other, key1, key2, name,
+        self.stateful_binary_input_frontier(control, config, other, key1, key2, name,
             move |state, cap, time, data, _output| {
                 data.swap(&mut data1_buffer);
                 for (_worker, key_id, d) in data1_buffer.drain(..) {
@@ -305,7 +416,7 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
                for (_worker, key_id, d) in data2_buffer.drain(..) {
                    state.get(key_id).notificator().notify_at_data(&cap, time.clone(), d);
                }
-           }, fold1, fold2)
+           }, fold1, fold2, frontier)
     }
 
     fn stateful_binary_input<
@@ -329,20 +440,62 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
             &mut Bin<G::Timestamp, S1, N1>,
             &mut Bin<G::Timestamp, S2, N2>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
-        C1: FnMut(&mut State<G::Timestamp, S1, N1>,
+        C1: FnMut(&mut State<G::Timestamp, S1, N1, A>,
             &Capability<G::Timestamp>,
             G::Timestamp,
             RefOrMut<Vec<(usize, Key, D1)>>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
-        C2: FnMut(&mut State<G::Timestamp, S2, N2>,
+        C2: FnMut(&mut State<G::Timestamp, S2, N2, A>,
             &Capability<G::Timestamp>,
             G::Timestamp,
             RefOrMut<Vec<(usize, Key, D2)>>,
             &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
-    >(&self, control: &Stream<G, Control>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, mut consume1: C1, mut consume2: C2, mut fold1: F1, mut fold2: F2) -> Stream<G, D3>
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, consume1: C1, consume2: C2, fold1: F1, fold2: F2) -> Stream<G, D3>
     {
-        let stateful1 = self.stateful(key1, &control);
-        let stateful2 = other.stateful(key2, &control);
+        self.stateful_binary_input_frontier(control, config, other, key1, key2, name, consume1, consume2, fold1, fold2,
+            |_frontier: &[G::Timestamp], _bin1: &mut Bin<G::Timestamp, S1, N1>, _bin2: &mut Bin<G::Timestamp, S2, N2>| {})
+    }
+
+    fn stateful_binary_input_frontier<
+        D2: ExchangeData+Eq,                         // input type
+        D3: Data,                                    // output type
+        N1: ExchangeData,
+        N2: ExchangeData,
+        B1: Fn(&D1)->u64+'static,
+        B2: Fn(&D2)->u64+'static,
+        S1: Clone+IntoIterator<Item=W1>+Extend<W1>+Default+'static,
+        S2: Clone+IntoIterator<Item=W2>+Extend<W2>+Default+'static,
+        W1: ExchangeData,                            // State format on the wire
+        W2: ExchangeData,                            // State format on the wire
+        F1: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, N1)>,
+            &mut Bin<G::Timestamp, S1, N1>,
+            &mut Bin<G::Timestamp, S2, N2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
+        F2: FnMut(&Capability<G::Timestamp>,
+            &mut Vec<(G::Timestamp, N2)>,
+            &mut Bin<G::Timestamp, S1, N1>,
+            &mut Bin<G::Timestamp, S2, N2>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,    // state update logic
+        C1: FnMut(&mut State<G::Timestamp, S1, N1, A>,
+            &Capability<G::Timestamp>,
+            G::Timestamp,
+            RefOrMut<Vec<(usize, Key, D1)>>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
+        C2: FnMut(&mut State<G::Timestamp, S2, N2, A>,
+            &Capability<G::Timestamp>,
+            G::Timestamp,
+            RefOrMut<Vec<(usize, Key, D2)>>,
+            &mut OutputHandle<G::Timestamp, D3, Tee<G::Timestamp, D3>>) + 'static,
+        E: FnMut(&[G::Timestamp],
+            &mut Bin<G::Timestamp, S1, N1>,
+            &mut Bin<G::Timestamp, S2, N2>) + 'static,    // frontier logic
+        A: BinAssigner,                              // Assignment of keys to bins
+    >(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, other: &Stream<G, D2>, key1: B1, key2: B2, name: &str, mut consume1: C1, mut consume2: C2, mut fold1: F1, mut fold2: F2, mut frontier: E) -> Stream<G, D3>
+    {
+        let stateful1 = self.stateful(key1, &control, config.clone());
+        let stateful2 = other.stateful(key2, &control, config);
         let states1 = stateful1.state.clone();
         let states2 = stateful2.state.clone();
 
@@ -358,13 +511,19 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
         let mut not2_drain = Vec::new();
         let mut bin1_drain = Vec::new();
         let mut bin2_drain = Vec::new();
+        let mut ready1 = Vec::new();
+        let mut ready2 = Vec::new();
+        let mut ready_caps = Vec::new();
 
         builder.build(move |_capability| {
             let mut state1_update_buffer = vec![];
             let mut state2_update_buffer = vec![];
 
-            let mut notificator1 = Notificator::new();
-            let mut notificator2 = Notificator::new();
+            let mut notificator1 = <Notificator<G::Timestamp, Vec<(usize, Key, D1)>> as Notify<_, _>>::new();
+            let mut notificator2 = <Notificator<G::Timestamp, Vec<(usize, Key, D2)>> as Notify<_, _>>::new();
+
+            // The joint input frontier last passed to `frontier`
+            let mut last_frontier: Option<Antichain<G::Timestamp>> = None;
 
             move |frontiers| {
                 let mut output_handle = output.activate();
@@ -396,13 +555,13 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
                     notificator2.notify_at_data(&cap.retain(), time, data2_buffer);
                 }
 
-                if let Some(cap) = notificator1.drain(&[&frontiers[0], &frontiers[1]], &mut not1_drain) {
+                while let Some(cap) = notificator1.drain(&[&frontiers[0], &frontiers[1]], &mut not1_drain) {
                     for (time, mut data) in not1_drain.drain(..) {
                         consume1(&mut states1, &cap, time, RefOrMut::Mut(&mut data), &mut output_handle);
                     }
                 }
 
-                if let Some(cap) = notificator2.drain(&[&frontiers[2], &frontiers[3]], &mut not2_drain) {
+                while let Some(cap) = notificator2.drain(&[&frontiers[2], &frontiers[3]], &mut not2_drain) {
                     for (time, mut data) in not2_drain.drain(..) {
                         consume2(&mut states2, &cap, time, RefOrMut::Mut(&mut data), &mut output_handle);
                     }
@@ -411,12 +570,62 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
                 // go through each time with data
                 for (bin1, bin2) in states1.bins.iter_mut().zip(states2.bins.iter_mut()).filter(|(b1, b2)| b1.is_some() && b2.is_some()) {
                     let (bin1, bin2) = (bin1.as_mut().unwrap(), bin2.as_mut().unwrap());
-                    if let Some(cap) = bin1.notificator().drain(&[&frontiers[0], &frontiers[1], &frontiers[2], &frontiers[3]], &mut bin1_drain) {
-                        fold1(&cap, &mut bin1_drain, bin1, bin2, &mut output_handle);
+                    // Folds may request further notifications, so repeat until none are ready
+                    loop {
+                        while let Some(cap) = bin1.notificator().drain(&[&frontiers[0], &frontiers[1], &frontiers[2], &frontiers[3]], &mut bin1_drain) {
+                            ready1.extend(bin1_drain.drain(..));
+                            ready_caps.push(cap);
+                        }
+                        while let Some(cap) = bin2.notificator().drain(&[&frontiers[0], &frontiers[1], &frontiers[2], &frontiers[3]], &mut bin2_drain) {
+                            ready2.extend(bin2_drain.drain(..));
+                            ready_caps.push(cap);
+                        }
+                        if ready_caps.is_empty() {
+                            break;
+                        }
+                        // Fold both inputs one time at a time, so each side sees all earlier data of the other
+                        ready1.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
+                        ready2.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
+                        let mut ready1_iter = ready1.drain(..).peekable();
+                        let mut ready2_iter = ready2.drain(..).peekable();
+                        loop {
+                            let time = match (ready1_iter.peek(), ready2_iter.peek()) {
+                                (Some((t1, _)), Some((t2, _))) => ::std::cmp::min(t1, t2).clone(),
+                                (Some((time, _)), None) | (None, Some((time, _))) => time.clone(),
+                                (None, None) => break,
+                            };
+                            let cap = ready_caps.iter().find(|cap| cap.time().less_equal(&time)).expect("no capability for ready time").delayed(&time);
+                            while ready1_iter.peek().map_or(false, |(t, _)| *t == time) {
+                                bin1_drain.push(ready1_iter.next().unwrap());
+                            }
+                            if !bin1_drain.is_empty() {
+                                fold1(&cap, &mut bin1_drain, bin1, bin2, &mut output_handle);
+                                bin1_drain.clear();
+                            }
+                            while ready2_iter.peek().map_or(false, |(t, _)| *t == time) {
+                                bin2_drain.push(ready2_iter.next().unwrap());
+                            }
+                            if !bin2_drain.is_empty() {
+                                fold2(&cap, &mut bin2_drain, bin1, bin2, &mut output_handle);
+                                bin2_drain.clear();
+                            }
+                        }
+                        ready_caps.clear();
                     }
-                    if let Some(cap) = bin2.notificator().drain(&[&frontiers[0], &frontiers[1], &frontiers[2], &frontiers[3]], &mut bin2_drain) {
-                        fold2(&cap, &mut bin2_drain, bin1, bin2, &mut output_handle);
+                }
+
+                // Pass the joint input frontier to all bins when it advances
+                let mut joint_frontier = Antichain::new();
+                for input_frontier in frontiers.iter() {
+                    for time in input_frontier.frontier().iter() {
+                        joint_frontier.insert(time.clone());
+                    }
+                }
+                if last_frontier.as_ref().map_or(true, |last| last.elements() != joint_frontier.elements()) {
+                    for (bin1, bin2) in states1.bins.iter_mut().zip(states2.bins.iter_mut()).filter(|(b1, b2)| b1.is_some() && b2.is_some()) {
+                        frontier(joint_frontier.elements(), bin1.as_mut().unwrap(), bin2.as_mut().unwrap());
                     }
+                    last_frontier = Some(joint_frontier);
                 }
             }
         });
@@ -426,12 +635,13 @@ impl<G, D1> StatefulOperator<G, D1> for Stream<G, D1>
         stream
     }
 
-    fn distribute<B1>(&self, control: &Stream<G, Control>, key: B1, name: &str) -> Stream<G, (usize, Key, D1)>
+    fn distribute<B1, A>(&self, control: &Stream<G, Control>, config: StatefulConfig<A>, key: B1, name: &str) -> Stream<G, (usize, Key, D1)>
         where
             B1: Fn(&D1)->u64+'static,
+            A: BinAssigner,
     {
         let mut data_vec = vec![];
-        self.stateful_unary_input::<_, (), _, Vec<()>, _, _, _>(control, key, name, move |_state, cap, _time, data, output| {
+        self.stateful_unary_input::<_, (), _, Vec<()>, _, _, _, _>(control, config, key, name, move |_state, cap, _time, data, output| {
             data.swap(&mut data_vec);
             output.session(&cap).give_vec(&mut data_vec);
         }, |_cap, _data, _bin, _output| {})
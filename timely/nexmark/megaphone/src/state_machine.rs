@@ -1,21 +1,28 @@
 //! General purpose state transition operator, implemented with Megaphone.
+use std::cell::RefCell;
 use std::hash::Hash;
+use std::rc::Rc;
 
 use fnv::FnvHashMap as HashMap;
+use fnv::FnvHashSet as HashSet;
 
 use timely::ExchangeData;
 use timely::dataflow::{Stream, Scope};
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+use timely::dataflow::operators::{Broadcast, Inspect, Operator};
 use timely::Data;
 
+use assigner::BinAssigner;
+use notificator::NotifyTimestamp;
 use operator::StatefulOperator;
-use ::Control;
+use ::{Bin, Control, StatefulConfig};
 
 /// Provide a general-purpose state machine operator that can be migrated without changes to the
 /// `fold` implementation.
 pub trait BinnedStateMachine<S, K, V, D>
 where
     S: Scope,
-    S::Timestamp: ::timely::order::TotalOrder,
+    S::Timestamp: NotifyTimestamp,
     K: ExchangeData+Hash+Eq,
     V: ExchangeData+Eq,
     D: ExchangeData + Default + 'static,
@@ -24,7 +31,7 @@ where
     ///
     /// The transition logic `fold` may mutate the state, and produce both output records and
     /// a `bool` indicating that it is appropriate to deregister the state, cleaning up once
-    /// the state is no longer helpful.
+    /// the state is no longer helpful. Keys are assigned to bins as configured by `config`.
     ///
     /// #Examples
     /// ```
@@ -34,13 +41,42 @@ where
         I: IntoIterator<Item=R>,                    // type of output iterator
         F: Fn(&K, V, &mut D)->(bool, I)+'static,    // state update logic
         H: Fn(&K)->u64+'static,                     // "hash" function for keys
-    >(&self, fold: F, hash: H, control: &Stream<S, Control>) -> Stream<S, R> where S::Timestamp : Hash+Eq;
+        A: BinAssigner,                             // assignment of keys to bins
+    >(&self, fold: F, hash: H, control: &Stream<S, Control>, config: StatefulConfig<A>) -> Stream<S, R> where S::Timestamp : Hash+Eq;
+
+    /// Tracks an associative aggregate for each presented key, splitting hot keys over several
+    /// bins.
+    ///
+    /// Hot keys are detected globally, from the rate at which all workers observe them, as
+    /// configured by `split`: each worker counts the keys of its input and sends the counts to the
+    /// worker owning the key, which announces keys turning hot or cold to all workers. Workers
+    /// spread the records of hot keys over up to `split.fanout()` sub-keys, each in a bin
+    /// determined by the key's hash and the sub-key, so a hot key's load is shared by several
+    /// bins and thus workers.
+    ///
+    /// For each batch of input, `fold` adds the values of each sub-key to a partial state starting
+    /// from `D::default()`. The partial states are sent as deltas to a second migratable operator,
+    /// keyed by `hash` alone, which merges them into the key's state with `merge` and presents the
+    /// result to `emit` to produce outputs. Partial states are retired once sent, so only the
+    /// merged state is kept. `merge` must be associative and commutative, with `D::default()` as
+    /// its neutral element. Cold keys take the same path with a single partial state. Outputs are
+    /// produced once per updated key and batch of input rather than per record, and states are
+    /// never removed.
+    fn stateful_split_state_machine<
+        R: Data,                                    // output type
+        I: IntoIterator<Item=R>,                    // type of output iterator
+        F: Fn(&K, V, &mut D)+'static,               // partial state update logic
+        M: Fn(&mut D, &D)+'static,                  // partial state merge logic
+        E: Fn(&K, &D)->I+'static,                   // output logic
+        H: Fn(&K)->u64+'static,                     // "hash" function for keys
+        A: BinAssigner,                             // assignment of keys to bins
+    >(&self, fold: F, merge: M, emit: E, hash: H, control: &Stream<S, Control>, config: StatefulConfig<A>, split: SplitConfig) -> Stream<S, R> where S::Timestamp : Hash+Eq, D: Eq;
 }
 
 impl<S, K, V, D> BinnedStateMachine<S, K, V, D> for Stream<S, (K, V)>
 where
     S: Scope,
-    S::Timestamp: ::timely::order::TotalOrder,
+    S::Timestamp: NotifyTimestamp,
     K: ExchangeData+Hash+Eq,
     V: ExchangeData+Eq,
     D: ExchangeData + Default + 'static,
@@ -50,9 +86,10 @@ where
         I: IntoIterator<Item=R>,                    // type of output iterator
         F: Fn(&K, V, &mut D) -> (bool, I) + 'static,    // state update logic
         H: Fn(&K)->u64+'static,                     // "hash" function for keys
-    >(&self, fold: F, hash: H, control: &Stream<S, Control>) -> Stream<S, R> where S::Timestamp : Hash+Eq {
+        A: BinAssigner,                             // assignment of keys to bins
+    >(&self, fold: F, hash: H, control: &Stream<S, Control>, config: StatefulConfig<A>) -> Stream<S, R> where S::Timestamp : Hash+Eq {
 
-        self.stateful_unary(control, move |(k, _v)| hash(&k), "StateMachine", move |cap, iter, bin, output| {
+        self.stateful_unary(control, config, move |(k, _v)| hash(&k), "StateMachine", move |cap, iter, bin, output| {
             let mut session = output.session(&cap);
             let states: &mut HashMap<_, _> = bin.state();
             for (_time, (key, val)) in iter.drain(..) {
@@ -68,4 +105,215 @@ where
             }
         })
     }
+
+    fn stateful_split_state_machine<
+        R: Data,                                    // output type
+        I: IntoIterator<Item=R>,                    // type of output iterator
+        F: Fn(&K, V, &mut D)+'static,               // partial state update logic
+        M: Fn(&mut D, &D)+'static,                  // partial state merge logic
+        E: Fn(&K, &D)->I+'static,                   // output logic
+        H: Fn(&K)->u64+'static,                     // "hash" function for keys
+        A: BinAssigner,                             // assignment of keys to bins
+    >(&self, fold: F, merge: M, emit: E, hash: H, control: &Stream<S, Control>, config: StatefulConfig<A>, split: SplitConfig) -> Stream<S, R> where S::Timestamp : Hash+Eq, D: Eq {
+
+        let hash = Rc::new(hash);
+        let owner_hash = hash.clone();
+        let sub_hash = hash.clone();
+        let fanout = split.fanout();
+
+        // Count keys per batch, and send the counts to the key's owner
+        let mut buffer = Vec::new();
+        let mut counts = HashMap::default();
+        let key_counts = self.unary(Pipeline, "HotKeyCounts", move |_cap, _info| move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                for (key, _val) in buffer.drain(..) {
+                    *counts.entry(key).or_insert(0) += 1;
+                }
+                output.session(&time).give_iterator(counts.drain());
+            });
+        });
+
+        // Detect hot keys from the global counts, and announce keys turning hot or cold
+        let mut detector = HotKeyDetector::new(split);
+        let mut announced = HashSet::default();
+        let mut buffer = Vec::new();
+        let announcements = key_counts.unary(Exchange::new(move |&(ref key, _): &(K, usize)| owner_hash(key)), "HotKeys", move |_cap, _info| move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                let mut session = output.session(&time);
+                for (key, count) in buffer.drain(..) {
+                    detector.observe(&key, count);
+                    if detector.is_hot(&key) && announced.insert(key.clone()) {
+                        session.give((key, true));
+                    }
+                }
+                // Decay might have cooled down any announced key
+                let cooled: Vec<K> = announced.iter().filter(|key| !detector.is_hot(key)).cloned().collect();
+                for key in cooled {
+                    announced.remove(&key);
+                    session.give((key, false));
+                }
+            });
+        });
+
+        // Every worker maintains the set of hot keys announced so far
+        let hot_keys = Rc::new(RefCell::new(HashSet::default()));
+        let hot_keys_update = hot_keys.clone();
+        announcements.broadcast().inspect(move |&(ref key, hot)| {
+            let mut hot_keys = hot_keys_update.borrow_mut();
+            if hot {
+                hot_keys.insert(key.clone());
+            } else {
+                hot_keys.remove(key);
+            }
+        });
+
+        // Tag each record with its sub-key, rotating through the sub-keys of hot keys
+        let mut rotation = 0u32;
+        let mut buffer = Vec::new();
+        let tagged = self.unary(Pipeline, "SubKeys", move |_cap, _info| move |input, output| {
+            let hot_keys = hot_keys.borrow();
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                let mut session = output.session(&time);
+                for (key, val) in buffer.drain(..) {
+                    let sub_key = if hot_keys.contains(&key) {
+                        rotation = rotation.wrapping_add(1);
+                        rotation % fanout
+                    } else {
+                        0
+                    };
+                    session.give((key, sub_key, val));
+                }
+            });
+        });
+
+        // Fold each batch into partial states per sub-key and send them as deltas
+        let mut deltas: HashMap<(K, u32), D> = HashMap::default();
+        let partials = tagged.stateful_unary(control, config.clone(), move |&(ref k, sub_key, _)| sub_key_hash(sub_hash(k), sub_key), "SplitStateMachine", move |cap, iter, _bin: &mut Bin<_, Vec<()>, _>, output| {
+            for (_time, (key, sub_key, val)) in iter.drain(..) {
+                fold(&key, val, deltas.entry((key, sub_key)).or_insert_with(Default::default));
+            }
+            output.session(&cap).give_iterator(deltas.drain().map(|((key, _sub_key), delta)| (key, delta)));
+        });
+
+        // Merge the deltas into the state of each key
+        partials.stateful_unary(control, config, move |&(ref k, _)| hash(k), "SplitMerge", move |cap, iter, bin, output| {
+            let states: &mut HashMap<K, D> = bin.state();
+            let mut updated = HashSet::default();
+            for (_time, (key, delta)) in iter.drain(..) {
+                merge(states.entry(key.clone()).or_insert_with(Default::default), &delta);
+                updated.insert(key);
+            }
+            let mut session = output.session(&cap);
+            for key in updated.drain() {
+                session.give_iterator(emit(&key, &states[&key]).into_iter());
+            }
+        })
+    }
+}
+
+/// Compute the hash of sub-key `sub_key` of a key with hash `hash`. Sub-key `0` keeps the key's
+/// hash, so cold keys are placed as in `stateful_state_machine`.
+fn sub_key_hash(hash: u64, sub_key: u32) -> u64 {
+    if sub_key == 0 {
+        hash
+    } else {
+        use ::std::hash::Hasher;
+        let mut h: ::fnv::FnvHasher = Default::default();
+        (hash, sub_key).hash(&mut h);
+        h.finish()
+    }
+}
+
+/// Configuration of hot-key splitting for `stateful_split_state_machine`.
+#[derive(Clone, Copy, Debug)]
+pub struct SplitConfig {
+    fanout: u32,
+    threshold: usize,
+    period: usize,
+}
+
+impl SplitConfig {
+    /// Split hot keys into up to `fanout` sub-keys. Panics if `fanout` is zero.
+    pub fn new(fanout: u32) -> Self {
+        assert!(fanout > 0, "split fanout must be positive");
+        Self { fanout, threshold: 1 << 10, period: 1 << 16 }
+    }
+
+    /// Consider a key hot once all workers together observed it `records` times, with counts
+    /// halved every period. Defaults to 1024.
+    pub fn with_threshold(mut self, records: usize) -> Self {
+        self.threshold = records;
+        self
+    }
+
+    /// Halve the counts of the keys a worker owns every `records` records of these keys. Defaults
+    /// to 65536.
+    ///
+    /// Panics if `records` is zero.
+    pub fn with_period(mut self, records: usize) -> Self {
+        assert!(records > 0, "split period must be positive");
+        self.period = records;
+        self
+    }
+
+    /// The maximum number of sub-keys per key.
+    pub fn fanout(&self) -> u32 {
+        self.fanout
+    }
+
+    /// The count at which a key is considered hot.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// The number of records after which counts decay.
+    pub fn period(&self) -> usize {
+        self.period
+    }
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+/// Detects hot keys from the rate at which they are observed.
+///
+/// Counts decay by half every `period` observations, so a key is hot if it was observed about
+/// `threshold` times within the last few periods. `stateful_split_state_machine` feeds each
+/// key's detector with the counts of all workers.
+#[derive(Debug)]
+pub struct HotKeyDetector<K: Hash+Eq> {
+    config: SplitConfig,
+    counts: HashMap<K, usize>,
+    observed: usize,
+}
+
+impl<K: Hash+Eq+Clone> HotKeyDetector<K> {
+    /// Construct a detector without any observations.
+    pub fn new(config: SplitConfig) -> Self {
+        Self { config, counts: Default::default(), observed: 0 }
+    }
+
+    /// Observe `count` records with key `key`.
+    pub fn observe(&mut self, key: &K, count: usize) {
+        *self.counts.entry(key.clone()).or_insert(0) += count;
+        self.observed += count;
+        if self.observed >= self.config.period {
+            self.observed = 0;
+            self.counts.retain(|_, count| {
+                *count /= 2;
+                *count > 0
+            });
+        }
+    }
+
+    /// Whether `key` is currently considered hot.
+    pub fn is_hot(&self, key: &K) -> bool {
+        self.counts.get(key).map_or(false, |count| *count >= self.config.threshold)
+    }
 }
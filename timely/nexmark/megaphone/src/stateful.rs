@@ -10,6 +10,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use std::marker::PhantomData;
+use std::collections::VecDeque;
 
 use fnv::FnvHashMap as HashMap;
 
@@ -20,15 +21,18 @@ use timely::dataflow::operators::Capability;
 use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
 use timely::dataflow::operators::Feedback;
 use timely::dataflow::operators::feedback::Handle as FeedbackHandle;
-use timely::order::TotalOrder;
-use timely::progress::Timestamp;
 use timely::progress::frontier::Antichain;
 
-use ::{BIN_SHIFT, Bin, BinId, Control, ControlSetBuilder, ControlSet, Key, key_to_bin, State};
+use assigner::{BinAssigner, Prefix};
+use checkpoint::{self, BinCheckpoint};
+use controller::LoadCounters;
+use notificator::{Notify, NotifyTimestamp};
+use ::{Bin, BinId, Control, ControlError, ControlErrorPolicy, ControlSetBuilder, ControlSet, Key, State, StatefulConfig};
 
 const BUFFER_CAP: usize = 16;
 
-pub type Notificator<T, D> = ::notificator::TotalOrderFrontierNotificator<T, D>;
+/// The notificator bins use to schedule their notifications, as selected by the timestamp.
+pub type Notificator<T, D> = <T as NotifyTimestamp>::Notificator<D>;
 
 /// Generic state-transition machinery: each key has a state, and receives a sequence of events.
 /// Events are applied in time-order, but no other promises are made. Each state transition can
@@ -51,9 +55,9 @@ pub enum StateProtocol<T, S, D> {
 }
 
 /// A timely `Stream` with an additional state handle and a probe.
-pub struct StateStream<S, V, D, W, M> where
+pub struct StateStream<S, V, D, W, M, A = Prefix> where
     S: Scope, // The containing scope
-    S::Timestamp: TotalOrder,
+    S::Timestamp: NotifyTimestamp,
     V: ExchangeData, // Input data
     D: IntoIterator<Item=W>+Extend<W>+Default+'static,    // per-bin state (data)
     W: ExchangeData,                            // State format on the wire
@@ -68,26 +72,31 @@ pub struct StateStream<S, V, D, W, M> where
     pub stream: Stream<S, (usize, Key, V)>,
     /// Stream of state updates
     pub state_stream: Stream<S, (usize, StateProtocol<S::Timestamp, W, M>)>,
+    /// Malformed configurations rejected under `ControlErrorPolicy::Reject`, at the time of the
+    /// configuration.
+    pub errors: Stream<S, ControlError>,
     /// A handle to the shared state object
-    pub state: Rc<RefCell<State<S::Timestamp, D, M>>>,
+    pub state: Rc<RefCell<State<S::Timestamp, D, M, A>>>,
     /// The probe `stateful` uses to determine completion.
     pub feedback: FeedbackHandle<S, ()>,
     _phantom: PhantomData<*const W>,
 }
 
-impl<S, V, D, W, M> StateStream<S, V, D, W, M>
+impl<S, V, D, W, M, A> StateStream<S, V, D, W, M, A>
     where
         S: Scope, // The containing scope
-        S::Timestamp: TotalOrder,
+        S::Timestamp: NotifyTimestamp,
         V: ExchangeData, // Input data
         D: IntoIterator<Item=W>+Extend<W>+Default,    // per-key state (data)
         W: ExchangeData,
         M: ExchangeData,
 {
-    pub fn new(stream: Stream<S, (usize, Key, V)>, state_stream: Stream<S, (usize, StateProtocol<S::Timestamp, W, M>)>, state: Rc<RefCell<State<S::Timestamp, D, M>>>, feedback: FeedbackHandle<S, ()>) -> Self {
+    /// Construct a new `StateStream` from its parts.
+    pub fn new(stream: Stream<S, (usize, Key, V)>, state_stream: Stream<S, (usize, StateProtocol<S::Timestamp, W, M>)>, errors: Stream<S, ControlError>, state: Rc<RefCell<State<S::Timestamp, D, M, A>>>, feedback: FeedbackHandle<S, ()>) -> Self {
         StateStream {
             stream,
             state_stream,
+            errors,
             state,
             feedback,
             _phantom: PhantomData,
@@ -96,12 +105,14 @@ impl<S, V, D, W, M> StateStream<S, V, D, W, M>
 
 }
 
+/// Apply state updates received on a `state_stream` to the local bins.
 pub fn apply_state_updates<
-    T: Timestamp+TotalOrder, // The containing scope
+    T: NotifyTimestamp, // The containing scope
     D: IntoIterator<Item=W>+Extend<W>+Default,    // per-key state (data)
     W: ExchangeData,
     M: ExchangeData,
-    I: Iterator<Item=(usize, StateProtocol<T, W, M>)>>(states: &mut State<T, D, M>, cap: &Capability<T>, data: I) {
+    A: BinAssigner,
+    I: Iterator<Item=(usize, StateProtocol<T, W, M>)>>(states: &mut State<T, D, M, A>, cap: &Capability<T>, data: I) {
 
     // Apply each state update
     for (_target, state) in data {
@@ -124,6 +135,31 @@ pub fn apply_state_updates<
 
 }
 
+/// Write all bins held by this worker and mark the worker's part of the checkpoint, taken with
+/// `map`, as complete.
+///
+/// Bins are drained to serialize them and rebuilt afterwards, as `D` can only be iterated by value.
+fn write_checkpoint<T, D, W, M, A>(states: &mut State<T, D, M, A>, dir: &::std::path::Path, sequence: u64, operator: &str, index: usize, peers: usize, time: &T, map: &[usize]) -> ::std::io::Result<()>
+    where
+        T: NotifyTimestamp,
+        D: IntoIterator<Item=W>+Extend<W>+Default,
+        W: ExchangeData,
+        M: ExchangeData,
+        A: BinAssigner,
+{
+    for (bin, state) in states.bins.iter_mut().enumerate() {
+        if let Some(state) = state.as_mut() {
+            let data: Vec<W> = ::std::mem::replace(&mut state.data, Default::default()).into_iter().collect();
+            let pending: Vec<(T, M)> = state.notificator.pending_iter().map(|(t, d)| (t.clone(), d.clone())).collect();
+            let checkpoint = (data, pending);
+            let result = checkpoint::write_bin(dir, sequence, operator, bin, &checkpoint);
+            state.data.extend(checkpoint.0.into_iter());
+            result?;
+        }
+    }
+    checkpoint::write_done(dir, sequence, operator, index, peers, time, map)
+}
+
 /// Provides the `stateful` method.
 pub trait Stateful<S: Scope, V: ExchangeData> {
 
@@ -133,13 +169,18 @@ pub trait Stateful<S: Scope, V: ExchangeData> {
     /// stream provides key-to-worker assignments. `stateful` applies the configuration changes such
     /// that a correctly-written downstream operator will still function correctly.
     ///
+    /// The `config` determines the number of bins keys are distributed to. Malformed
+    /// configurations are handled according to its `ControlErrorPolicy`, and rejected ones are
+    /// reported on the `errors` stream of the result.
+    ///
     /// # Parameters
     /// * `W`: State serialization format
     /// * `D`: Data associated with keys
     /// * `B`: Key function
-    fn stateful<W, D, B, M>(&self, key: B, control: &Stream<S, Control>) -> StateStream<S, V, D, W, M>
+    /// * `A`: Assignment of keys to bins
+    fn stateful<W, D, B, M, A>(&self, key: B, control: &Stream<S, Control>, config: StatefulConfig<A>) -> StateStream<S, V, D, W, M, A>
         where
-            S::Timestamp: Hash+Eq+TotalOrder,
+            S::Timestamp: Hash+Eq+NotifyTimestamp,
             // State format on the wire
             W: ExchangeData,
             // per-key state (data)
@@ -147,15 +188,15 @@ pub trait Stateful<S: Scope, V: ExchangeData> {
             // "hash" function for values
             B: Fn(&V)->u64+'static,
             M: ExchangeData,
+            A: BinAssigner,
     ;
 }
 
-#[cfg(not(feature = "fake_stateful"))]
 impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
 
-    fn stateful<W, D, B, M>(&self, key: B, control: &Stream<S, Control>) -> StateStream<S, V, D, W, M>
+    fn stateful<W, D, B, M, A>(&self, key: B, control: &Stream<S, Control>, config: StatefulConfig<A>) -> StateStream<S, V, D, W, M, A>
         where
-            S::Timestamp: Hash+Eq+TotalOrder,
+            S::Timestamp: Hash+Eq+NotifyTimestamp,
             // State format on the wire
             W: ExchangeData,
             // per-key state (data)
@@ -163,22 +204,47 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
             // "hash" function for values
             B: Fn(&V)->u64+'static,
             M: ExchangeData,
+            A: BinAssigner,
     {
         let index = self.scope().index();
         let peers = self.scope().peers();
 
-        let map: Vec<usize> = (0..peers).cycle().take(1 << BIN_SHIFT).collect();
+        let bin_shift = config.bin_shift();
+        let control_error_policy = config.control_error_policy();
+        let state_chunk_size = config.state_chunk_size();
+        let load_tracker = config.load_tracker().cloned();
+        let checkpoint_config = config.checkpoint().cloned();
+        let assigner = config.bin_assigner().clone();
+        let static_partitioning = config.static_partitioning();
+
+        let mut builder = OperatorBuilder::new("StateMachine F".into(), self.scope());
+        // Re-schedule the operator while state transfers are outstanding
+        let activator = self.scope().activator_for(&builder.operator_info().address[..]);
+        // Checkpoints are stored by operator address, which is stable across restarts
+        let operator_name = builder.operator_info().address.iter().map(|a| a.to_string()).collect::<Vec<_>>().join("-");
+        // Metrics recorded locally and published periodically
+        let mut metrics = config.metrics().map(|metrics| metrics.recorder(operator_name.clone()));
+
+        let mut map: Vec<usize> = (0..peers).cycle().take(config.bins()).collect();
+        // Start from the map of the checkpoint to restore if the number of workers is unchanged
+        if let Some(sequence) = checkpoint_config.as_ref().and_then(|c| c.restore()) {
+            let restored = checkpoint::read_map::<S::Timestamp>(checkpoint_config.as_ref().unwrap().dir(), sequence, &operator_name)
+                .unwrap_or_else(|e| panic!("Failed to restore map from checkpoint {}: {}", sequence, e));
+            if let Some((checkpoint_peers, checkpoint_map)) = restored {
+                if checkpoint_peers == peers && checkpoint_map.len() == map.len() {
+                    map = checkpoint_map;
+                }
+            }
+        }
         // worker-local state, maps bins to state
         let default_elements: Vec<Option<_>> = map.iter().map(|i| if *i == index {
             Some(Default::default())
         } else {
             None
         }).collect();
-        let states: Rc<RefCell<State<S::Timestamp, D, M>>> = Rc::new(RefCell::new(State::new(default_elements)));
+        let states: Rc<RefCell<State<S::Timestamp, D, M, A>>> = Rc::new(RefCell::new(State::new(default_elements, bin_shift, config.bin_assigner().clone())));
         let states_f = Rc::clone(&states);
 
-        let mut builder = OperatorBuilder::new("StateMachine F".into(), self.scope());
-
         // The data input
         let mut data_in = builder.new_input(self, Pipeline);
         // The control input
@@ -187,9 +253,11 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
         let (mut data_out, stream) = builder.new_output();
         // State output of the F operator
         let (mut state_out, state) = builder.new_output_connection(vec![Antichain::new(), Antichain::from_elem(Default::default())]);
+        // Rejected configurations, which only depend on the control input
+        let (mut error_out, errors) = builder.new_output_connection(vec![Antichain::new(), Antichain::from_elem(Default::default())]);
 
         let (feedback_handle, feedback_stream) = self.scope().feedback(Default::default());
-        let feedback_in_connection = vec![Antichain::new(); 2];
+        let feedback_in_connection = vec![Antichain::new(); 3];
         let _feedback_in = builder.new_input_connection(&feedback_stream, Pipeline, feedback_in_connection);
 
         // Probe to be attached after the last stateful operator
@@ -197,62 +265,122 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
 //        let probe2 = probe1.clone();
 
         // Construct F operator
-        builder.build(move |_capability| {
+        builder.build(move |mut capabilities| {
 
             // distinct notificators for data and control input
-            let mut data_notificator = Notificator::new();
-            let mut control_notificator = Notificator::new();
+            let mut data_notificator = <Notificator<S::Timestamp, ()> as Notify<_, _>>::new();
+            let mut control_notificator = <Notificator<S::Timestamp, ()> as Notify<_, _>>::new();
 
             // Data input stash, time -> Vec<Vec<V>>
             let mut data_stash: HashMap<_, Vec<Vec<V>>> = Default::default();
 
-            // Active configurations: Vec<(T, ControlInstr)> sorted by increasing T. Note that
-            // we assume the Ts form a total order, i.e. they must dominate each other.
-            let mut pending_configurations: Vec<(Capability<S::Timestamp>, ControlSet<S::Timestamp>)> = Vec::new();
+            // Active configurations: Vec<(T, ControlInstr)> sorted by sequence number, with a
+            // capability to report errors while installing them. Note that each configuration's
+            // frontier must dominate its successors'.
+            let mut pending_configurations: Vec<(Capability<S::Timestamp>, ControlSet<S::Timestamp>, Capability<S::Timestamp>)> = Vec::new();
 
-            let mut pending_configuration_data: HashMap<S::Timestamp, ControlSetBuilder<S::Timestamp>> = Default::default();
+            // Configurations under construction, with a capability to report errors. Malformed
+            // configurations are replaced by their error.
+            let mut pending_configuration_data: HashMap<S::Timestamp, (Capability<S::Timestamp>, Result<ControlSetBuilder<S::Timestamp>, ControlError>)> = Default::default();
 
             // TODO : default configuration may be poorly chosen.
             let mut active_configuration: ControlSet<S::Timestamp> = ControlSet { 
                 sequence: 0,
                 frontier: Antichain::from_elem(Default::default()),
                 map,
+                checkpoint: false,
             };
 
+            // Load counted since the last activation, published to the tracker at its end
+            let mut load = load_tracker.as_ref().map(|_| LoadCounters::default());
+
             // Stash for consumed input buffers
             let mut data_return_buffer = vec![];
 
             let mut control_data_buffer = vec![];
 
+            // Outgoing state transfers: (capability, sequence, target, bin, remaining state). The
+            // capability holds back the state output, and thus the receiving operator, until
+            // the bin's state has been sent completely. Restored state has no sequence number.
+            let mut outgoing_state: VecDeque<(Capability<S::Timestamp>, Option<u64>, usize, BinId, Vec<W>)> = VecDeque::new();
+
+            // Restore owned bins from a checkpoint by sending their state to ourselves
+            // One capability per output in construction order: data, state, errors
+            assert_eq!(capabilities.len(), 3, "expected capabilities for the data, state and error outputs");
+            let state_capability = capabilities.swap_remove(1);
+            let mut restored_pending = Vec::new();
+            if let Some(sequence) = checkpoint_config.as_ref().and_then(|c| c.restore()) {
+                let dir = checkpoint_config.as_ref().unwrap().dir();
+                for bin in (0..active_configuration.map.len()).filter(|bin| active_configuration.map[*bin] == index) {
+                    let restored: Option<BinCheckpoint<S::Timestamp, W, M>> = checkpoint::read_bin(dir, sequence, &operator_name, bin)
+                        .unwrap_or_else(|e| panic!("Failed to restore bin {} from checkpoint {}: {}", bin, sequence, e));
+                    if let Some((data, pending)) = restored {
+                        restored_pending.extend(pending.into_iter().map(|(t, d)| (index, StateProtocol::Pending(BinId(bin), t, d))));
+                        outgoing_state.push_back((state_capability.clone(), None, index, BinId(bin), data));
+                    }
+                }
+            }
+            let mut restored_pending = Some((state_capability, restored_pending));
+
             // Handle input data
             move |frontiers| {
                 let mut data_out = data_out.activate();
                 let mut state_out = state_out.activate();
+                let mut error_out = error_out.activate();
+
+                if let Some((cap, pending)) = restored_pending.take() {
+                    state_out.session(&cap).give_iterator(pending.into_iter());
+                }
 
                 // Read control input
                 control_in.for_each(|time, data| {
                     data.swap(&mut control_data_buffer);
+                    // Static partitioning keeps the initial map forever
+                    if static_partitioning {
+                        control_data_buffer.clear();
+                        return;
+                    }
                     // Append to pending control instructions
-                    let builder = pending_configuration_data.entry(time.time().clone()).or_insert_with(|| {
+                    let (_, ref mut builder) = *pending_configuration_data.entry(time.time().clone()).or_insert_with(|| {
                         let mut builder: ControlSetBuilder<S::Timestamp> = Default::default();
                         // TODO: We don't know the frontier at the time the command was received.
                         builder.frontier(vec![time.time().clone()].into_iter());
-                        builder
+                        (time.retain_for_output(2), Ok(builder))
                     });
                     for update in control_data_buffer.drain(..) {
-                        builder.apply(update);
+                        let result = match *builder {
+                            Ok(ref mut builder) => builder.apply(update),
+                            Err(_) => Ok(()),
+                        };
+                        if let Err(error) = result {
+                            *builder = Err(error);
+                        }
                     }
-                    control_notificator.notify_at(&time.retain_for_output(1));
+                    let cap = time.retain_for_output(1);
+                    control_notificator.notify_at_data(&cap, cap.time().clone(), ());
                 });
 
                 // Analyze control frontier
-                control_notificator.for_each(&[&frontiers[1]], |cap, time, _not| {
+                control_notificator.for_each_data(&[&frontiers[1]], |cap, time, (), _not| {
                     // Check if there are pending control instructions
-                    if let Some(builder) = pending_configuration_data.remove(&time) {
+                    if let Some((error_cap, builder)) = pending_configuration_data.remove(&time) {
                         // Build new configuration
-                        let config = builder.build(pending_configurations.last().map_or(&active_configuration, |pending| &pending.1));
+                        let config = {
+                            let previous = pending_configurations.last().map_or(&active_configuration, |pending| &pending.1);
+                            builder.and_then(|builder| builder.build(previous, bin_shift))
+                        };
+                        let config = match config {
+                            Ok(config) => config,
+                            Err(error) => match control_error_policy {
+                                ControlErrorPolicy::Panic => panic!("Received malformed configuration at {:?}: {}", time, error),
+                                ControlErrorPolicy::Reject => {
+                                    error_out.session(&error_cap).give(error);
+                                    return;
+                                },
+                            },
+                        };
                         // Append to list of compiled configuration
-                        pending_configurations.push((cap.delayed(&time), config));
+                        pending_configurations.push((cap.delayed(&time), config, error_cap));
                         // Sort by provided sequence number
                         pending_configurations.sort_by_key(|d| d.1.sequence);
 
@@ -281,11 +409,27 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
 
                 // If the next configuration to install is no longer at all ahead of the state machine output,
                 // then there can be no more records or state updates for any configuration prior to the next.
-                if pending_configurations.get(0).is_some() {
+                //
+                // Configurations are only installed once all state of the previous one has been
+                // transferred, as bins could otherwise move again before arriving.
+                if pending_configurations.get(0).is_some() && outgoing_state.is_empty() {
                     if pending_configurations[0].1.frontier.elements().iter().all(|t| !frontiers[2].less_than(t)) {
 
                         // We should now install `pending_configurations[0]` into `active_configuration`!
-                        let (time, to_install) = pending_configurations.remove(0);
+                        let (time, to_install, error_cap) = pending_configurations.remove(0);
+
+                        if to_install.checkpoint {
+                            if let Some(ref checkpoint_config) = checkpoint_config {
+                                let mut states = states_f.borrow_mut();
+                                if let Err(e) = write_checkpoint(&mut states, checkpoint_config.dir(), to_install.sequence, &operator_name, index, peers, time.time(), active_configuration.map()) {
+                                    let error = ControlError::CheckpointFailed(to_install.sequence, e.to_string());
+                                    match control_error_policy {
+                                        ControlErrorPolicy::Panic => panic!("Failed to install configuration at {:?}: {}", time.time(), error),
+                                        ControlErrorPolicy::Reject => error_out.session(&error_cap).give(error),
+                                    }
+                                }
+                            }
+                        }
 
                         {   // Scoped to let `old_map` and `new_map` borrows drop.
                             let old_map = active_configuration.map();
@@ -303,10 +447,12 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
                                     let state = states.bins[bin].take().expect("Instructed to move bin but it is None");
                                     let Bin { data, notificator } = state;
                                     session.give((*new, StateProtocol::Prepare(BinId(bin))));
-                                    let chunk: Vec<_> = data.into_iter().collect();
-                                    println!("migration\t{}\t{}\t{}\t{}", bin, old, new, chunk.len());
-                                    session.give((*new, StateProtocol::State(BinId(bin), chunk)));
                                     session.give_iterator(notificator.pending().map(|(t, d)| (*new, StateProtocol::Pending(BinId(bin), t, d))));
+                                    let data: Vec<_> = data.into_iter().collect();
+                                    if let Some(ref mut metrics) = metrics {
+                                        metrics.migration_started(to_install.sequence, bin, data.len());
+                                    }
+                                    outgoing_state.push_back((time.clone(), Some(to_install.sequence), *new, BinId(bin), data));
                                 }
                             }
                         }
@@ -316,7 +462,28 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
                     }
                 }
 
-                data_notificator.for_each(&[&frontiers[0], &frontiers[1]], |cap, time, _not| {
+                // Send at most `state_chunk_size` state records, in chunks of whole bins or less
+                let mut budget = state_chunk_size;
+                while budget > 0 && !outgoing_state.is_empty() {
+                    let (cap, sequence, target, bin, mut data) = outgoing_state.pop_front().unwrap();
+                    let chunk = data.split_off(data.len().saturating_sub(budget));
+                    budget -= chunk.len();
+                    if let (Some(metrics), Some(sequence)) = (metrics.as_mut(), sequence) {
+                        metrics.migrated(sequence, chunk.len(), ::abomonation::measure(&chunk));
+                        if data.is_empty() && !outgoing_state.iter().any(|o| o.1 == Some(sequence)) {
+                            metrics.migration_finished(sequence);
+                        }
+                    }
+                    state_out.session(&cap).give((target, StateProtocol::State(bin, chunk)));
+                    if !data.is_empty() {
+                        outgoing_state.push_front((cap, sequence, target, bin, data));
+                    }
+                }
+                if !outgoing_state.is_empty() {
+                    activator.activate();
+                }
+
+                data_notificator.for_each_data(&[&frontiers[0], &frontiers[1]], |cap, time, (), _not| {
                     // Check for stashed data - now control input has to have advanced
                     if let Some(vec) = data_stash.remove(&time) {
 
@@ -335,7 +502,14 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
                             {
                                 let data_iter = data.drain(..).map(|d| {
                                     let key_id = Key(key(&d));
-                                    (map[key_to_bin(key_id)], key_id, d)
+                                    let bin = assigner.bin(key_id, bin_shift);
+                                    if let Some(ref mut load) = load {
+                                        load.record(bin, key_id);
+                                    }
+                                    if let Some(ref mut metrics) = metrics {
+                                        metrics.record(bin);
+                                    }
+                                    (map[bin], key_id, d)
                                 });
                                 session.give_iterator(data_iter);
                             }
@@ -348,8 +522,9 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
 
                 // Read data from the main data channel
                 data_in.for_each(|time, data| {
-                    // Can we process data? No if the control frontier is <= `time`
-                    if frontiers[1].less_equal(time.time()) {
+                    // Can we process data? No if the control frontier is <= `time`, unless
+                    // configurations are ignored anyway
+                    if !static_partitioning && frontiers[1].less_equal(time.time()) {
                         // No, stash data
                         if !data_stash.contains_key(time.time()) {
                             data_stash.insert(time.time().clone(), Vec::new());
@@ -357,7 +532,8 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
                         let mut data_vec = data_return_buffer.pop().unwrap_or_else(Vec::new);
                         data.swap(&mut data_vec);
                         data_stash.get_mut(time.time()).unwrap().push(data_vec);
-                        data_notificator.notify_at(&time.retain_for_output(0));
+                        let cap = time.retain_for_output(0);
+                        data_notificator.notify_at_data(&cap, cap.time().clone(), ());
                     } else {
                         // Yes, control frontier not <= `time`, process right-away
 
@@ -377,51 +553,33 @@ impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
                         data.swap(&mut data_vec);
                         let data_iter = data_vec.drain(..).map(|d| {
                             let key_id = Key(key(&d));
-                            (map[key_to_bin(key_id)], key_id, d)
+                            let bin = assigner.bin(key_id, bin_shift);
+                            if let Some(ref mut load) = load {
+                                load.record(bin, key_id);
+                            }
+                            if let Some(ref mut metrics) = metrics {
+                                metrics.record(bin);
+                            }
+                            (map[bin], key_id, d)
                         });
                         session.give_iterator(data_iter);
                     }
                 });
 
+                if let (Some(tracker), Some(load)) = (load_tracker.as_ref(), load.as_mut()) {
+                    tracker.publish(load);
+                }
+
+                if let Some(ref mut metrics) = metrics {
+                    if metrics.publish_due(frontiers.iter().all(|f| f.is_empty())) {
+                        let states = states_f.borrow();
+                        metrics.publish(states.bins.iter().map(|state| state.as_ref().map_or(0, |state| state.notificator.pending_len())));
+                    }
+                }
             }
         });
 
         // `stream` is the stateful output stream where data is already correctly partitioned.
-        StateStream::new(stream, state, states, feedback_handle)
-    }
-}
-
-#[cfg(feature = "fake_stateful")]
-impl<S: Scope, V: ExchangeData> Stateful<S, V> for Stream<S, V> {
-    fn stateful<W, D, B, M>(&self, key: B, _control: &Stream<S, Control>) -> StateStream<S, V, D, W, M>
-        where
-            S::Timestamp : Hash+Eq+TotalOrder,
-        // State format on the wire
-            W: ExchangeData,
-        // per-key state (data)
-            D: IntoIterator<Item=W>+Extend<W>+Default,
-        // "hash" function for values
-            B: Fn(&V)->u64+'static,
-            M: ExchangeData,
-    {
-        // construct states, we simply construct all bins on each worker
-        let states: Rc<RefCell<State<S::Timestamp, D, M>>> = Rc::new(RefCell::new(State::new(::std::iter::repeat_with(|| Some(Default::default())).take(1 << BIN_SHIFT).collect())));
-
-        // Feedback handle to be attached after the last stateful operator
-        let (feedback_handle, feedback_stream) = self.scope().feedback(Default::default());
-
-        use timely::dataflow::operators::{Map, Exchange, Filter};
-
-        // `stream` is the stateful output stream where data is already correctly partitioned.
-        let stream = self
-            .map(move |d| {
-                let key = Key(key(&d));
-                (key.0 as usize, key, d)
-            })
-            .exchange(|d| (d.0 ^ d.0.rotate_left(BIN_SHIFT as u32)) as u64);
-        let state_stream = _control
-            .filter(|_| false)
-            .map(|_| (0, StateProtocol::Prepare(BinId(0))));
-        StateStream::new(stream, state_stream, states, feedback_handle)
+        StateStream::new(stream, state, errors, states, feedback_handle)
     }
 }
@@ -0,0 +1,100 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use timely::dataflow::*;
+use timely::dataflow::operators::{Broadcast, Input, Probe, Map, Inspect};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{ControlInst, Control, Key, StatefulConfig};
+use dynamic_scaling_mechanism::assigner::{AnyAssigner, BinAssigner, ConsistentHash, Prefix, Range};
+use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
+
+#[test]
+fn range_assigner() {
+    let range = Range::new(vec![10, 20, 30]);
+    let bins: Vec<_> = [0, 9, 10, 19, 20, 30, 1000].iter().map(|k| range.bin(Key::new(*k), 2)).collect();
+    assert_eq!(bins, vec![0, 0, 1, 1, 2, 3, 3]);
+    // Bounds beyond the number of bins fall into the last bin
+    assert_eq!(range.bin(Key::new(1000), 1), 1);
+
+    let uniform = Range::uniform(0, 16, 2);
+    let bins: Vec<_> = (0..16).map(|k| uniform.bin(Key::new(k), 2)).collect();
+    assert_eq!(bins, vec![0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3]);
+
+    // Ranges partition raw keys, which key functions must not hash
+    assert!(uniform.ordered());
+    assert!(!Prefix.ordered());
+    let any = AnyAssigner::from(uniform);
+    assert!(any.ordered());
+    assert_eq!(any.bin(Key::new(5), 2), 1);
+}
+
+#[test]
+fn consistent_hash_assigner() {
+    let bin_shift = 4;
+    let ring = ConsistentHash::new(bin_shift, 16);
+    let mut counts = vec![0; 1 << bin_shift];
+    for i in 0..100_000u64 {
+        let key = Key::new(i.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        counts[ring.bin(key, bin_shift)] += 1;
+    }
+    assert!(counts.iter().all(|c| *c > 0), "some bins received no keys: {:?}", counts);
+    // Keys past the last virtual node wrap around to the first
+    assert!(ring.bin(Key::new(u64::max_value()), bin_shift) < 1 << bin_shift);
+    assert_eq!(Prefix.bin(Key::new(u64::max_value()), bin_shift), (1 << bin_shift) - 1);
+}
+
+#[test]
+fn range_assigner_migration() {
+    timely::execute(Configuration::Process(2), |worker| {
+
+        let mut result = vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
+                              (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)];
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        // Keys 0 and 1 go to different bins, and thus workers
+        let config = StatefulConfig::new(1).with_bin_assigner(Range::new(vec![1]));
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let input = scope.input_from(&mut input);
+            input
+                .map(|x| (x % 2, x))
+                .stateful_state_machine(
+                    |_key, val, agg| {
+                        *agg += val;
+                        (false, Some((*_key, *agg)))
+                    },
+                    |key| *key as u64,
+                    &control,
+                    config,
+                )
+                .inspect(move |x| {
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                    result.retain(|e| e != x);
+                })
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        if index == 0 {
+            control_input.send(Control::new(1, 1, ControlInst::Map(vec![1, 0])));
+        }
+        control_input.close();
+        for round in 0..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+}
@@ -0,0 +1,214 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use timely::dataflow::*;
+use std::sync::{Arc, Mutex};
+
+use timely::dataflow::operators::{ConnectLoop, Filter, Input, Probe, Map, Inspect};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{Bin, ControlInst, Control, ControlError, ControlErrorPolicy, StatefulConfig};
+use dynamic_scaling_mechanism::checkpoint::{CheckpointConfig, latest_checkpoint};
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
+use dynamic_scaling_mechanism::stateful::Stateful;
+
+fn run(checkpoint: CheckpointConfig, start: usize, expected: Vec<(usize, usize)>) {
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let mut result = expected.clone();
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            input
+                .map(|x| (x % 2, x))
+                .stateful_state_machine(
+                    |_key, val, agg| {
+                        *agg += val;
+                        (false, Some((*_key, *agg)))
+                    },
+                    |key| *key as u64
+                    ,
+                    &control,
+                    StatefulConfig::default().with_checkpoint(checkpoint.clone()),
+                )
+                .inspect(move |x| {
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                    result.retain(|e| e != x);
+                })
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        control_input.send(Control::new(1,  1, ControlInst::Checkpoint));
+        control_input.advance_to(10);
+        input.advance_to(start);
+        // introduce data and watch!
+        for round in start..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+}
+
+#[test]
+fn checkpoint_and_restore() {
+    let dir = ::std::env::temp_dir().join(format!("megaphone-checkpoint-test-{}", ::std::process::id()));
+    let _ = ::std::fs::remove_dir_all(&dir);
+
+    run(CheckpointConfig::new(dir.clone()), 0,
+        vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20), (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)]);
+
+    let checkpoint = latest_checkpoint::<usize>(&dir).unwrap().expect("no complete checkpoint");
+    assert_eq!(checkpoint.sequence, 1);
+    assert_eq!(checkpoint.time, 5);
+
+    // Replay from the checkpoint's time, continuing with the restored sums
+    run(CheckpointConfig::new(dir.clone()).with_restore(checkpoint.sequence), checkpoint.time,
+        vec![(0, 12), (0, 20), (1, 9), (1, 16), (1, 25)]);
+
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Echo `x`, introduced at time `x`, at time `x + 3`, keeping echoed values as bin state. Records
+/// introduced before the checkpoint at time 5 but echoed after it are restored as pending
+/// notifications, and arrive on the state output alongside the bins' data.
+fn run_delayed(checkpoint: CheckpointConfig, start: usize) -> Vec<(usize, usize)> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let results2 = results.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let results = results2.clone();
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            let config = StatefulConfig::default()
+                .with_checkpoint(checkpoint.clone())
+                .with_control_error_policy(ControlErrorPolicy::Reject);
+            input
+                .map(|x: usize| (x % 2, x, false))
+                .stateful_unary(&control, config, |&(key, _, _)| key as u64, "Delay", |cap, data, bin: &mut Bin<_, Vec<usize>, _>, output| {
+                    let mut session = output.session(&cap);
+                    for (time, (key, val, echo)) in data.drain(..) {
+                        if echo {
+                            bin.state().push(val);
+                            session.give(val);
+                        } else {
+                            bin.notificator().notify_at_data(&cap, time + 3, (key, val, true));
+                        }
+                    }
+                })
+                .inspect_time(move |t, x| results.lock().unwrap().push((*t, *x)))
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        control_input.send(Control::new(1,  1, ControlInst::Checkpoint));
+        control_input.advance_to(10);
+        input.advance_to(start);
+        for round in start..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+    let mut results = results.lock().unwrap().clone();
+    results.sort();
+    results
+}
+
+#[test]
+fn checkpoint_restores_pending_notifications() {
+    let dir = ::std::env::temp_dir().join(format!("megaphone-checkpoint-pending-test-{}", ::std::process::id()));
+    let _ = ::std::fs::remove_dir_all(&dir);
+
+    let echoed: Vec<_> = (0..10).map(|x| (x + 3, x)).collect();
+    assert_eq!(run_delayed(CheckpointConfig::new(dir.clone()), 0), echoed);
+
+    let checkpoint = latest_checkpoint::<usize>(&dir).unwrap().expect("no complete checkpoint");
+    assert_eq!(checkpoint.time, 5);
+
+    // Records 2, 3 and 4 were pending at the checkpoint and are echoed from the restored state
+    let restored = run_delayed(CheckpointConfig::new(dir.clone()).with_restore(checkpoint.sequence), checkpoint.time);
+    assert_eq!(restored, echoed[2..].to_vec());
+
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn checkpoint_failure_is_reported() {
+    // a regular file in place of the checkpoint directory makes writing checkpoints fail
+    let dir = ::std::env::temp_dir().join(format!("megaphone-checkpoint-failure-test-{}", ::std::process::id()));
+    ::std::fs::write(&dir, b"").unwrap();
+
+    let failed = Arc::new(Mutex::new(Vec::new()));
+    let reported = failed.clone();
+    let checkpoint = CheckpointConfig::new(dir.join("checkpoints"));
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let failed = reported.clone();
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow::<usize, _, _>(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            let config = StatefulConfig::default()
+                .with_checkpoint(checkpoint.clone())
+                .with_control_error_policy(ControlErrorPolicy::Reject);
+            let stateful = input.stateful::<(), Vec<()>, _, (), _>(|x: &u64| *x, &control, config);
+            stateful.errors
+                .inspect_time(move |t, x| failed.lock().unwrap().push((index, *t, x.clone())))
+                .probe_with(&mut probe);
+            stateful.stream.filter(|_| false).map(|_| ()).connect_loop(stateful.feedback);
+        });
+
+        control_input.advance_to(5);
+        control_input.send(Control::new(1,  1, ControlInst::Checkpoint));
+        control_input.advance_to(10);
+        input.advance_to(10);
+        while probe.less_than(control_input.time()) {
+            worker.step();
+        }
+
+    }).unwrap();
+
+    let mut failed = failed.lock().unwrap().clone();
+    failed.sort_by_key(|&(index, _, _)| index);
+    assert_eq!(failed.len(), 2, "expected a failure per worker, got {:?}", failed);
+    for (index, &(worker, time, ref error)) in failed.iter().enumerate() {
+        assert_eq!((worker, time), (index, 5));
+        match *error {
+            ControlError::CheckpointFailed(1, _) => {},
+            ref other => panic!("expected a failed checkpoint, got {:?}", other),
+        }
+    }
+
+    ::std::fs::remove_file(&dir).unwrap();
+}
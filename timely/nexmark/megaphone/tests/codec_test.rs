@@ -0,0 +1,81 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use std::collections::HashMap;
+
+use timely::dataflow::*;
+use timely::dataflow::operators::{Broadcast, Input, Inspect, Map, Probe};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{Bin, ControlInst, Control, StatefulConfig};
+use dynamic_scaling_mechanism::codec::{AbomonationCodec, Encoded};
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+type CountState = Encoded<HashMap<u64, u64>, AbomonationCodec>;
+
+#[test]
+fn encoded_roundtrip() {
+    let mut state: CountState = Default::default();
+    state.insert(1, 10);
+    state.insert(2, 20);
+    let blobs: Vec<_> = state.clone().into_iter().collect();
+    assert_eq!(blobs.len(), 1);
+
+    let mut restored: CountState = Default::default();
+    restored.extend(blobs);
+    assert_eq!(restored.into_inner(), state.into_inner());
+
+    let empty: CountState = Default::default();
+    assert_eq!(empty.into_iter().count(), 0);
+}
+
+#[test]
+fn encoded_migration() {
+    timely::execute(Configuration::Process(2), |worker| {
+
+        let mut result = vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
+                              (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)];
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let input = scope.input_from(&mut input);
+            input
+                .map(|x: u64| (x % 2, x))
+                .stateful_unary(&control, StatefulConfig::default(), |&(key, _)| key, "Count", |cap, data, bin: &mut Bin<_, CountState, _>, output| {
+                    let mut session = output.session(cap);
+                    for (_time, (key, value)) in data.drain(..) {
+                        let sum = bin.state().entry(key).or_insert(0);
+                        *sum += value;
+                        session.give((key, *sum));
+                    }
+                })
+                .inspect(move |x| {
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                    result.retain(|e| e != x);
+                })
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        if index == 0 {
+            control_input.send(Control::new(1, 1, ControlInst::Map(vec![1; 1 << StatefulConfig::default().bin_shift()])));
+        }
+        control_input.close();
+        for round in 0..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round as usize + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+}
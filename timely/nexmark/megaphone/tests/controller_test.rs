@@ -0,0 +1,98 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use std::sync::{Arc, Mutex};
+
+use timely::dataflow::*;
+use timely::dataflow::operators::{ConnectLoop, Feedback, Input, Probe, Map, Inspect};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::StatefulConfig;
+use dynamic_scaling_mechanism::controller::{BinLoad, Greedy, LoadTracker, MinMigrationCost, Rebalance, RebalancePolicy, Threshold};
+use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
+
+fn loads(records: &[usize], keys: &[usize]) -> Vec<BinLoad> {
+    records.iter().zip(keys.iter()).map(|(&records, &keys)| BinLoad { records, keys }).collect()
+}
+
+#[test]
+fn greedy_balances_records() {
+    let loads = loads(&[10, 10, 10, 10], &[1, 1, 1, 1]);
+    assert_eq!(Greedy.rebalance(&[0, 0, 0, 0], 2, &loads), Some(vec![0, 1, 0, 1]));
+    assert_eq!(Greedy.rebalance(&[0, 1, 0, 1], 2, &loads), None);
+}
+
+#[test]
+fn threshold_tolerates_imbalance() {
+    let loads = loads(&[12, 8, 0, 0], &[1, 1, 1, 1]);
+    assert_eq!(Threshold::new(1.5).rebalance(&[0, 1, 0, 1], 2, &loads), None);
+    assert_eq!(Threshold::new(1.5).rebalance(&[0, 0, 1, 1], 2, &loads), Some(vec![1, 0, 1, 1]));
+}
+
+#[test]
+fn min_migration_cost_moves_small_state() {
+    let loads = loads(&[10, 10, 0, 0], &[100, 1, 1, 1]);
+    assert_eq!(MinMigrationCost::new(1.1).rebalance(&[0, 0, 1, 1], 2, &loads), Some(vec![0, 1, 1, 1]));
+}
+
+#[test]
+fn rebalance_skewed_bins() {
+    let moves = Arc::new(Mutex::new(0));
+    let moves_w = moves.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        // these results happen to be right, but aren't guaranteed.
+        // the system is at liberty to re-order within a timestamp.
+        let mut result = vec![(0, 0), (0, 4), (0, 12), (1, 1), (1, 6), (1, 15),
+                              (2, 2), (2, 8), (2, 18), (3, 3), (3, 10), (3, 21)];
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+        let tracker = LoadTracker::new();
+        let config = StatefulConfig::default().with_load_tracker(tracker.clone());
+        let moves = moves_w.clone();
+
+        worker.dataflow(|scope| {
+            let input = scope.input_from(&mut input);
+            // Controls decided at a time apply from the next time on
+            let (handle, control) = scope.feedback(1);
+            control.inspect(move |_| *moves.lock().unwrap() += 1);
+            let output = input
+                .map(|x| (x % 4, x))
+                .stateful_state_machine(
+                    |_key, val, agg| {
+                        *agg += val;
+                        (false, Some((*_key, *agg)))
+                    },
+                    // All keys map to bins owned by worker 0 initially
+                    |key| (*key as u64) << 62,
+                    &control,
+                    config.clone(),
+                );
+            output.rebalance(&control, tracker, &config, Greedy, 0).connect_loop(handle);
+            output
+                .inspect(move |x| {
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                    result.retain(|e| e != x);
+                })
+                .probe_with(&mut probe);
+        });
+
+        // introduce data and watch!
+        for round in 0..12 {
+            if index == 0 {
+                input.send(round);
+            }
+            if round % 4 == 3 {
+                input.advance_to(round + 1);
+                while probe.less_than(input.time()) {
+                    worker.step();
+                }
+            }
+        }
+
+    }).unwrap();
+    assert!(*moves.lock().unwrap() > 0);
+}
@@ -2,23 +2,42 @@
 extern crate timely;
 extern crate dynamic_scaling_mechanism;
 
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
 use timely::dataflow::*;
-use timely::dataflow::operators::{Input, Probe, Map, Inspect};
+use timely::dataflow::operators::{ConnectLoop, Filter, Input, Probe, Map, Inspect};
 
 use timely::Configuration;
 
-use dynamic_scaling_mechanism::{BIN_SHIFT, ControlInst, Control};
+use dynamic_scaling_mechanism::{ControlInst, Control, ControlError, ControlErrorPolicy, Key, StatefulConfig};
+use dynamic_scaling_mechanism::control::ValidateControl;
 use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
+use dynamic_scaling_mechanism::stateful::{Stateful, StateProtocol};
+
+/// The running sums of even and odd inputs of the tests feeding in `0..10`, shared by all workers.
+fn expected_results() -> Arc<Mutex<Vec<(usize, usize)>>> {
+    Arc::new(Mutex::new(vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
+                             (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)]))
+}
+
+/// Assert that all expected results were produced.
+fn assert_drained<T: ::std::fmt::Debug>(result: &Arc<Mutex<Vec<T>>>) {
+    let result = result.lock().unwrap();
+    assert!(result.is_empty(), "missing results: {:?}", *result);
+}
 
 #[test]
 fn default_configuration() {
-    timely::execute(Configuration::Process(2), |worker| {
-
-        // these results happen to be right, but aren't guaranteed.
-        // the system is at liberty to re-order within a timestamp.
-        let mut result = vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
-                              (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)];
+    // these results happen to be right, but aren't guaranteed.
+    // the system is at liberty to re-order within a timestamp.
+    let result = expected_results();
+    let pending = result.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
 
+        let result = pending.clone();
         let index = worker.index();
         let mut input = InputHandle::new();
         let mut control_input = InputHandle::new();
@@ -36,10 +55,12 @@ fn default_configuration() {
                     },
                     |key| *key as u64
                     ,
-                    &control
+                    &control,
+                    StatefulConfig::default(),
                 )
                 .inspect(move |x| {
-                    assert!(result.contains(x));
+                    let mut result = result.lock().unwrap();
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
                     result.retain(|e| e != x);
                 })
                 .probe_with(&mut probe);
@@ -58,17 +79,18 @@ fn default_configuration() {
         }
 
     }).unwrap();
+    assert_drained(&result);
 }
 
 #[test]
 fn custom_configuration() {
-    timely::execute(Configuration::Process(2), |worker| {
-
-        // these results happen to be right, but aren't guaranteed.
-        // the system is at liberty to re-order within a timestamp.
-        let mut result = vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
-                              (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)];
+    // these results happen to be right, but aren't guaranteed.
+    // the system is at liberty to re-order within a timestamp.
+    let result = expected_results();
+    let pending = result.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
 
+        let result = pending.clone();
         let index = worker.index();
         let mut input = InputHandle::new();
         let mut control_input = InputHandle::new();
@@ -86,18 +108,20 @@ fn custom_configuration() {
                     },
                     |key| *key as u64
                     ,
-                    &control
+                    &control,
+                    StatefulConfig::default(),
                 )
                 .inspect(move |x| {
+                    let mut result = result.lock().unwrap();
                     assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
                     result.retain(|e| e != x);
                 })
                 .probe_with(&mut probe);
         });
 
-        control_input.send(Control::new(0,  1, ControlInst::Map(vec![0; 1 << BIN_SHIFT])));
+        control_input.send(Control::new(0,  1, ControlInst::Map(vec![0; StatefulConfig::default().bins()])));
         control_input.advance_to(5);
-        control_input.send(Control::new(1,  1, ControlInst::Map(vec![1; 1 << BIN_SHIFT])));
+        control_input.send(Control::new(1,  1, ControlInst::Map(vec![1; StatefulConfig::default().bins()])));
         control_input.advance_to(10);
         // introduce data and watch!
         for round in 0..10 {
@@ -111,17 +135,18 @@ fn custom_configuration() {
         }
 
     }).unwrap();
+    assert_drained(&result);
 }
 
 #[test]
 fn adjacent_configuration() {
-    timely::execute(Configuration::Process(2), |worker| {
-
-        // these results happen to be right, but aren't guaranteed.
-        // the system is at liberty to re-order within a timestamp.
-        let mut result = vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
-                              (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)];
+    // these results happen to be right, but aren't guaranteed.
+    // the system is at liberty to re-order within a timestamp.
+    let result = expected_results();
+    let pending = result.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
 
+        let result = pending.clone();
         let index = worker.index();
         let mut input = InputHandle::new();
         let mut control_input = InputHandle::new();
@@ -139,18 +164,20 @@ fn adjacent_configuration() {
                     },
                     |key| *key as u64
                     ,
-                    &control
+                    &control,
+                    StatefulConfig::default(),
                 )
                 .inspect(move |x| {
-                    assert!(result.contains(x));
+                    let mut result = result.lock().unwrap();
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
                     result.retain(|e| e != x);
                 })
                 .probe_with(&mut probe);
         });
 
-        control_input.send(Control::new(0,  1, ControlInst::Map(vec![0; 1 << BIN_SHIFT])));
+        control_input.send(Control::new(0,  1, ControlInst::Map(vec![0; StatefulConfig::default().bins()])));
         control_input.advance_to(1);
-        control_input.send(Control::new(1,  1, ControlInst::Map(vec![1; 1 << BIN_SHIFT])));
+        control_input.send(Control::new(1,  1, ControlInst::Map(vec![1; StatefulConfig::default().bins()])));
         control_input.advance_to(10);
         // introduce data and watch!
         for round in 0..10 {
@@ -164,19 +191,78 @@ fn adjacent_configuration() {
         }
 
     }).unwrap();
+    assert_drained(&result);
 }
 
 
+#[test]
+fn small_bin_shift_configuration() {
+    // these results happen to be right, but aren't guaranteed.
+    // the system is at liberty to re-order within a timestamp.
+    let result = expected_results();
+    let pending = result.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let result = pending.clone();
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        let config = StatefulConfig::new(1);
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            input
+                .map(|x| (x % 2, x))
+                .stateful_state_machine(
+                    |_key, val, agg| {
+                        *agg += val;
+                        (false, Some((*_key, *agg)))
+                    },
+                    |key| (*key as u64) << 63
+                    ,
+                    &control,
+                    config.clone(),
+                )
+                .inspect(move |x| {
+                    let mut result = result.lock().unwrap();
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                    result.retain(|e| e != x);
+                })
+                .probe_with(&mut probe);
+        });
+
+        control_input.send(Control::new(0,  1, ControlInst::Map(vec![1, 0])));
+        control_input.advance_to(5);
+        control_input.send(Control::new(1,  1, ControlInst::Map(vec![0; config.bins()])));
+        control_input.advance_to(10);
+        // introduce data and watch!
+        for round in 0..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+    assert_drained(&result);
+}
+
 #[test]
 #[should_panic]
 fn error_seq_configuration() {
-    timely::execute(Configuration::Process(2), |worker| {
-
-        // these results happen to be right, but aren't guaranteed.
-        // the system is at liberty to re-order within a timestamp.
-        let mut result = vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
-                              (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)];
+    // these results happen to be right, but aren't guaranteed.
+    // the system is at liberty to re-order within a timestamp.
+    let result = expected_results();
+    let pending = result.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
 
+        let result = pending.clone();
         let index = worker.index();
         let mut input = InputHandle::new();
         let mut control_input = InputHandle::new();
@@ -194,20 +280,22 @@ fn error_seq_configuration() {
                     },
                     |key| *key as u64
                     ,
-                    &control
+                    &control,
+                    StatefulConfig::default(),
                 )
                 .inspect(move |x| {
-                    assert!(result.contains(x));
+                    let mut result = result.lock().unwrap();
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
                     result.retain(|e| e != x);
                 })
                 .probe_with(&mut probe);
         });
 
         control_input.advance_to(3);
-        control_input.send(Control::new(10,  1, ControlInst::Map(vec![0; 1 << BIN_SHIFT])));
+        control_input.send(Control::new(10,  1, ControlInst::Map(vec![0; StatefulConfig::default().bins()])));
 //        worker.step();
 //        control_input.advance_to(4);
-        control_input.send(Control::new(9,  1, ControlInst::Map(vec![1; 1 << BIN_SHIFT])));
+        control_input.send(Control::new(9,  1, ControlInst::Map(vec![1; StatefulConfig::default().bins()])));
         worker.step();
         control_input.advance_to(10);
         // introduce data and watch!
@@ -222,4 +310,276 @@ fn error_seq_configuration() {
         }
 
     }).unwrap();
+    assert_drained(&result);
+}
+
+#[test]
+fn reject_error_configuration() {
+    // these results happen to be right, but aren't guaranteed.
+    // the system is at liberty to re-order within a timestamp.
+    let result = expected_results();
+    let pending = result.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let result = pending.clone();
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            input
+                .map(|x| (x % 2, x))
+                .stateful_state_machine(
+                    |_key, val, agg| {
+                        *agg += val;
+                        (false, Some((*_key, *agg)))
+                    },
+                    |key| *key as u64
+                    ,
+                    &control,
+                    StatefulConfig::default().with_control_error_policy(ControlErrorPolicy::Reject),
+                )
+                .inspect(move |x| {
+                    let mut result = result.lock().unwrap();
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                    result.retain(|e| e != x);
+                })
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(3);
+        // a map with the wrong number of bins is rejected
+        control_input.send(Control::new(10,  1, ControlInst::Map(vec![0; 3])));
+        control_input.advance_to(5);
+        control_input.send(Control::new(11,  1, ControlInst::Map(vec![1; StatefulConfig::default().bins()])));
+        control_input.advance_to(10);
+        // introduce data and watch!
+        for round in 0..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+    assert_drained(&result);
+}
+
+#[test]
+fn validate_control_errors() {
+    timely::execute(Configuration::Process(2), |worker| {
+
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+        let config = StatefulConfig::default();
+
+        // each worker validates the controls it sent itself
+        let expected_errors = Rc::new(RefCell::new(vec![
+            (3, ControlError::InconsistentSequence(10, 9)),
+            (5, ControlError::MissingControls(1)),
+        ]));
+        let expected_controls = Rc::new(RefCell::new(vec![(7, 12)]));
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let (control, errors) = control.validate_control(&config);
+            let pending_controls = expected_controls.clone();
+            control
+                .inspect_time(move |t, x| {
+                    let mut expected_controls = pending_controls.borrow_mut();
+                    assert!(expected_controls.contains(&(*t, x.sequence())));
+                    expected_controls.retain(|e| e != &(*t, x.sequence()));
+                })
+                .probe_with(&mut probe);
+            let pending_errors = expected_errors.clone();
+            errors
+                .inspect_time(move |t, x| {
+                    let mut expected_errors = pending_errors.borrow_mut();
+                    assert!(expected_errors.contains(&(*t, x.clone())));
+                    expected_errors.retain(|e| e != &(*t, x.clone()));
+                })
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(3);
+        control_input.send(Control::new(10,  2, ControlInst::Map(vec![0; config.bins()])));
+        control_input.send(Control::new(9,  2, ControlInst::Map(vec![1; config.bins()])));
+        control_input.advance_to(5);
+        control_input.send(Control::new(11,  2, ControlInst::Map(vec![1; config.bins()])));
+        control_input.advance_to(7);
+        control_input.send(Control::new(12,  1, ControlInst::Move(::dynamic_scaling_mechanism::BinId::new(0), 1)));
+        control_input.advance_to(10);
+        while probe.less_than(control_input.time()) {
+            worker.step();
+        }
+        assert!(expected_errors.borrow().is_empty(), "missing errors: {:?}", expected_errors.borrow());
+        assert!(expected_controls.borrow().is_empty(), "missing controls: {:?}", expected_controls.borrow());
+
+    }).unwrap();
+}
+
+#[test]
+fn reject_error_stream() {
+    let errors = Arc::new(Mutex::new(vec![
+        (0, 3, ControlError::InvalidMapLength(StatefulConfig::default().bins(), 3)),
+        (1, 3, ControlError::InvalidMapLength(StatefulConfig::default().bins(), 3)),
+    ]));
+    let pending = errors.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let errors = pending.clone();
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow::<usize, _, _>(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            let stateful = input.stateful::<(), Vec<()>, _, (), _>(|x: &u64| *x, &control, StatefulConfig::default().with_control_error_policy(ControlErrorPolicy::Reject));
+            stateful.errors
+                .inspect_time(move |t, x| {
+                    let mut errors = errors.lock().unwrap();
+                    let error = (index, *t, x.clone());
+                    assert!(errors.contains(&error), "Got {:?}, expected one of {:?}", error, errors);
+                    errors.retain(|e| e != &error);
+                })
+                .probe_with(&mut probe);
+            stateful.stream.filter(|_| false).map(|_| ()).connect_loop(stateful.feedback);
+        });
+
+        control_input.advance_to(3);
+        // a map with the wrong number of bins is rejected
+        control_input.send(Control::new(10,  1, ControlInst::Map(vec![0; 3])));
+        control_input.advance_to(10);
+        input.advance_to(10);
+        while probe.less_than(control_input.time()) {
+            worker.step();
+        }
+
+    }).unwrap();
+    assert_drained(&errors);
+}
+
+#[test]
+fn chunked_state_configuration() {
+    // these results happen to be right, but aren't guaranteed.
+    // the system is at liberty to re-order within a timestamp.
+    let result = Arc::new(Mutex::new(vec![(0, 0), (0, 4), (0, 12), (1, 1), (1, 6), (1, 15),
+                                          (2, 2), (2, 8), (3, 3), (3, 10)]));
+    let pending = result.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let result = pending.clone();
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            input
+                .map(|x| (x % 4, x))
+                .stateful_state_machine(
+                    |_key, val, agg| {
+                        *agg += val;
+                        (false, Some((*_key, *agg)))
+                    },
+                    // keys 0 and 1 share bin 0, keys 2 and 3 share bin 16
+                    |key| (*key as u64 / 2) << 60
+                    ,
+                    &control,
+                    StatefulConfig::default().with_state_chunk_size(1),
+                )
+                .inspect(move |x| {
+                    let mut result = result.lock().unwrap();
+                    assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                    result.retain(|e| e != x);
+                })
+                .probe_with(&mut probe);
+        });
+
+        // move the occupied bins back and forth, one state record per activation
+        control_input.send(Control::new(0,  1, ControlInst::Map(vec![0; StatefulConfig::default().bins()])));
+        control_input.advance_to(5);
+        control_input.send(Control::new(1,  1, ControlInst::Map(vec![1; StatefulConfig::default().bins()])));
+        control_input.advance_to(7);
+        control_input.send(Control::new(2,  1, ControlInst::Map(vec![0; StatefulConfig::default().bins()])));
+        control_input.advance_to(10);
+        // introduce data and watch!
+        for round in 0..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+    assert_drained(&result);
+}
+
+#[test]
+fn chunked_state_bound() {
+    timely::execute(Configuration::Process(2), |worker| {
+
+        let index = worker.index();
+        let config = StatefulConfig::new(2).with_state_chunk_size(3);
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+        // State records sent per worker step
+        let step = Rc::new(Cell::new(0));
+        let per_step = Rc::new(RefCell::new(BTreeMap::new()));
+
+        let state = worker.dataflow::<usize, _, _>(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let input = scope.input_from(&mut input);
+            let stateful = input.stateful::<u64, Vec<u64>, _, (), _>(|x: &u64| *x, &control, config.clone());
+            let step = step.clone();
+            let per_step = per_step.clone();
+            stateful.state_stream
+                .inspect_batch(move |_t, batch| {
+                    let records: usize = batch.iter().map(|&(_, ref update)| match *update {
+                        StateProtocol::State(_, ref chunk) => chunk.len(),
+                        _ => 0,
+                    }).sum();
+                    if records > 0 {
+                        *per_step.borrow_mut().entry(step.get()).or_insert(0) += records;
+                    }
+                })
+                .probe_with(&mut probe);
+            stateful.stream.filter(|_| false).map(|_| ()).connect_loop(stateful.feedback);
+            stateful.state
+        });
+
+        // each worker owns two of the four bins and fills each with five records
+        for bin in (0..config.bins()).filter(|bin| bin % 2 == index) {
+            state.borrow_mut().get(Key::new((bin as u64) << 62)).state().extend(0..5);
+        }
+
+        // swap all bins between the workers
+        control_input.send(Control::new(0,  1, ControlInst::Map(vec![1, 0, 1, 0])));
+        control_input.advance_to(1);
+        input.advance_to(1);
+        while probe.less_than(input.time()) {
+            step.set(step.get() + 1);
+            worker.step();
+        }
+
+        let per_step = per_step.borrow();
+        assert!(per_step.values().all(|records| *records <= config.state_chunk_size()), "step exceeded chunk size: {:?}", *per_step);
+        assert_eq!(per_step.values().sum::<usize>(), 10);
+        assert!(per_step.len() >= 4, "state not spread across steps: {:?}", *per_step);
+
+    }).unwrap();
 }
@@ -0,0 +1,218 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use std::sync::{Arc, Mutex};
+
+use timely::dataflow::*;
+use timely::dataflow::operators::{Broadcast, Filter, Input, Probe, Map, Inspect};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{Bin, ControlInst, Control, StatefulConfig};
+use dynamic_scaling_mechanism::join::{Join, JoinState};
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+/// Join `(x % 2, x)` for `x` in `left` with `(x % 2, 10 * x)` for `x` in `right`, where each
+/// record is introduced at time `x`. All bins move to worker 1 at time 5.
+fn run(mode: &'static str, left: Vec<usize>, right: Vec<usize>) -> Vec<(usize, usize, Option<usize>)> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let results2 = results.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let results = results2.clone();
+
+        let index = worker.index();
+        let mut left_input = InputHandle::new();
+        let mut right_input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let left = scope.input_from(&mut left_input).map(|x: usize| (x % 2, x));
+            let right = scope.input_from(&mut right_input).map(|x: usize| (x % 2, 10 * x));
+            let config = StatefulConfig::default();
+            let joined = match mode {
+                "join" => left.join(&right, &control, config, "Join").map(|(k, v1, v2)| (k, v1, Some(v2))),
+                "left_join" => left.left_join(&right, &control, config, "LeftJoin"),
+                "window_join" => left.window_join(&right, 3, &control, config, "WindowJoin").map(|(k, v1, v2)| (k, v1, Some(v2))),
+                _ => panic!("unknown mode {}", mode),
+            };
+            joined
+                .inspect(move |x| results.lock().unwrap().push(*x))
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        if index == 0 {
+            control_input.send(Control::new(1, 1, ControlInst::Map(vec![1; 1 << StatefulConfig::default().bin_shift()])));
+        }
+        control_input.close();
+        for round in 0..10 {
+            if index == 0 {
+                if left.contains(&round) {
+                    left_input.send(round);
+                }
+                if right.contains(&round) {
+                    right_input.send(round);
+                }
+            }
+            left_input.advance_to(round + 1);
+            right_input.advance_to(round + 1);
+            while probe.less_than(left_input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+    let mut results = results.lock().unwrap().clone();
+    results.sort();
+    results
+}
+
+#[test]
+fn inner_join_keeps_all_values() {
+    let result = run("join", (0..10).collect(), (0..10).collect());
+    let mut expected = Vec::new();
+    for x in 0..10 {
+        for y in 0..10 {
+            if x % 2 == y % 2 {
+                expected.push((x % 2, x, Some(10 * y)));
+            }
+        }
+    }
+    expected.sort();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn left_join_emits_unmatched() {
+    let result = run("left_join", (1..10).collect(), vec![0]);
+    let mut expected: Vec<_> = (1..10)
+        .map(|x| (x % 2, x, if x % 2 == 0 { Some(0) } else { None }))
+        .collect();
+    expected.sort();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn window_join_expires_state() {
+    let result = run("window_join", (0..10).collect(), (0..10).collect());
+    let mut expected = Vec::new();
+    for x in 0..10usize {
+        for y in 0..10usize {
+            if x % 2 == y % 2 && (x as isize - y as isize).abs() < 3 {
+                expected.push((x % 2, x, Some(10 * y)));
+            }
+        }
+    }
+    expected.sort();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn left_join_matches_in_time_order() {
+    // Both inputs advance past all their records before the operator runs once.
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let results2 = results.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let results = results2.clone();
+
+        let index = worker.index();
+        let mut left_input = InputHandle::new();
+        let mut right_input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow::<usize, _, _>(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let left = scope.input_from(&mut left_input);
+            let right = scope.input_from(&mut right_input);
+            left.left_join(&right, &control, StatefulConfig::default(), "LeftJoin")
+                .inspect_batch(move |time, data| results.lock().unwrap().extend(data.iter().map(|x| (*time, *x))))
+                .probe_with(&mut probe);
+        });
+
+        control_input.close();
+        for round in 0..5 {
+            if index == 0 {
+                match round {
+                    1 => { left_input.send((0usize, 1usize)); right_input.send((0usize, 10usize)); }
+                    3 => left_input.send((0, 3)),
+                    4 => right_input.send((0, 40)),
+                    _ => {},
+                }
+            }
+            left_input.advance_to(round + 1);
+            right_input.advance_to(round + 1);
+        }
+        left_input.close();
+        right_input.close();
+        while !probe.done() {
+            worker.step();
+        }
+
+    }).unwrap();
+    let mut results = results.lock().unwrap().clone();
+    results.sort();
+    assert_eq!(results, vec![
+        (1, (0, 1, Some(10))),
+        (3, (0, 3, Some(10))),
+        (4, (0, 1, Some(40))),
+        (4, (0, 3, Some(40))),
+    ]);
+}
+
+#[test]
+fn window_state_expires_without_data() {
+    // `x` for `x` in `0..3` at time `x`, each expiring at time `x + 3`; no records follow.
+    let lengths = Arc::new(Mutex::new(Vec::new()));
+    let lengths2 = lengths.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let lengths = lengths2.clone();
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow::<usize, _, _>(|scope| {
+            let control = scope.input_from(&mut control_input);
+            let left = scope.input_from(&mut input).map(|x: usize| (x % 2, x));
+            let right = left.filter(|_| false);
+            let stream: Stream<_, ()> = left.stateful_binary_frontier(&control, StatefulConfig::default(), &right, |x| x.0 as u64, |x| x.0 as u64, "Expire",
+                |_cap, data, bin1: &mut Bin<_, JoinState<usize, usize, usize>, _>, _bin2: &mut Bin<_, JoinState<usize, usize, usize>, _>, _output| {
+                    for (time, (key, value)) in data.drain(..) {
+                        bin1.state().insert(key, Some(time + 3), value);
+                    }
+                },
+                |_cap, data, _bin1, _bin2, _output| data.clear(),
+                move |frontier, bin1, _bin2| {
+                    bin1.state().expire_frontier(frontier);
+                    lengths.lock().unwrap().push((frontier.to_vec(), bin1.state().len()));
+                });
+            stream.probe_with(&mut probe);
+        });
+
+        control_input.close();
+        for round in 0..10 {
+            if index == 0 && round < 3 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+
+    let lengths = lengths.lock().unwrap();
+    for &(ref frontier, len) in lengths.iter() {
+        let live = (0..3).filter(|x| frontier.iter().all(|f| x + 3 > *f)).count();
+        assert!(len <= live, "{} values held at frontier {:?}, at most {} live", len, frontier, live);
+    }
+    assert!(lengths.iter().any(|&(ref frontier, _)| frontier.iter().all(|f| *f >= 5)), "frontier never passed the last expiry");
+}
@@ -0,0 +1,86 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use std::time::Duration;
+
+use timely::dataflow::*;
+use timely::dataflow::operators::{Broadcast, Input, Probe, Map};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{BinId, ControlInst, Control, StatefulConfig};
+use dynamic_scaling_mechanism::metrics::Metrics;
+use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
+
+#[test]
+fn migration_metrics() {
+    timely::execute(Configuration::Process(2), |worker| {
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        // Publish on every activation
+        let metrics = Metrics::new().with_interval(Duration::from_millis(0));
+        let config = StatefulConfig::new(4).with_metrics(metrics.clone());
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let input = scope.input_from(&mut input);
+            input
+                .map(|x| (x % 2, x))
+                .stateful_state_machine(
+                    |_key, val, agg| {
+                        *agg += val;
+                        (false, Some((*_key, *agg)))
+                    },
+                    |key| *key as u64,
+                    &control,
+                    config,
+                )
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        if index == 0 {
+            // Move all bins to worker 1
+            control_input.send(Control::new(1, 1, ControlInst::Map(vec![1; 16])));
+        }
+        control_input.close();
+        for round in 0..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+        input.close();
+        while worker.step() { }
+
+        // Both keys map to bin 0, and all records are routed by worker 0
+        let bins = metrics.drain_bins();
+        let records: usize = bins.iter().map(|(_operator, _bin, m)| m.records).sum();
+        assert_eq!(records, if index == 0 { 10 } else { 0 });
+        // Records and state lengths are reset by each report, but no notifications are pending
+        assert!(metrics.drain_bins().is_empty());
+
+        let migrations = metrics.drain_migrations();
+        if index == 0 {
+            assert_eq!(migrations.len(), 1);
+            let (ref operator, ref migration) = migrations[0];
+            assert_eq!(migration.sequence, 1);
+            assert_eq!(migration.bins, 8);
+            assert_eq!(migration.records, 2);
+            assert!(migration.bytes > 0);
+            let bin_zero = bins.iter().find(|(_operator, bin, _)| *bin == BinId::new(0)).expect("bin 0 not reported");
+            assert_eq!(&bin_zero.0, operator);
+            assert_eq!(bin_zero.2.state_len, Some(2));
+        } else {
+            assert!(migrations.is_empty());
+        }
+        assert!(metrics.migrations().is_empty());
+    }).unwrap();
+}
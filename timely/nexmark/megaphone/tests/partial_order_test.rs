@@ -0,0 +1,138 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use std::sync::{Arc, Mutex};
+
+use timely::dataflow::*;
+use timely::dataflow::operators::{Broadcast, Concat, ConnectLoop, Enter, Feedback, Filter, Input, Inspect, Leave, Map, Probe};
+use timely::order::Product;
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{ControlInst, Control, StatefulConfig};
+use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
+
+#[test]
+fn iterative_scope_migration() {
+    timely::execute(Configuration::Process(2), |worker| {
+
+        let mut result = vec![(0, 0), (0, 2), (0, 6), (0, 12), (0, 20),
+                              (1, 1), (1, 4), (1, 9), (1, 16), (1, 25)];
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let input = scope.input_from(&mut input);
+            scope.iterative::<u32, _, _>(|inner| {
+                let control = control.enter(inner);
+                input
+                    .enter(inner)
+                    .map(|x| (x % 2, x))
+                    .stateful_state_machine(
+                        |_key, val, agg| {
+                            *agg += val;
+                            (false, Some((*_key, *agg)))
+                        },
+                        |key| *key as u64,
+                        &control,
+                        StatefulConfig::default(),
+                    )
+                    .leave()
+            })
+            .inspect(move |x| {
+                assert!(result.contains(x), "Got {:?}, expected one of {:?}", x, result);
+                result.retain(|e| e != x);
+            })
+            .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        if index == 0 {
+            control_input.send(Control::new(1, 1, ControlInst::Map(vec![1; 1 << StatefulConfig::default().bin_shift()])));
+        }
+        control_input.close();
+        for round in 0..10 {
+            if index == 0 {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+}
+
+#[test]
+fn incomparable_times_migration() {
+    // Each `x` enters at `(x, 0)` and circulates once more at `(x, 1)`, which is incomparable to
+    // `(x + 1, 0)`. All input is presented at once, such that incomparable times are pending
+    // together while bins migrate.
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let results2 = results.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let results = results2.clone();
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let input = scope.input_from(&mut input);
+            scope.iterative::<u32, _, _>(|inner| {
+                let control = control.enter(inner);
+                let (handle, cycle) = inner.feedback(Product::new(0, 1));
+                let values = input.enter(inner).map(|x: usize| (x, 0)).concat(&cycle);
+                values
+                    .filter(|&(_, iteration)| iteration == 0)
+                    .map(|(x, _)| (x, 1))
+                    .connect_loop(handle);
+                values
+                    .map(|(x, iteration)| (x % 2, (x, iteration)))
+                    .stateful_state_machine(
+                        |key, (x, _iteration), agg: &mut usize| {
+                            *agg += x;
+                            (false, Some((*key, *agg)))
+                        },
+                        |key| *key as u64,
+                        &control,
+                        StatefulConfig::default(),
+                    )
+                    .leave()
+            })
+            .inspect(move |x| results.lock().unwrap().push(*x))
+            .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        if index == 0 {
+            control_input.send(Control::new(1, 1, ControlInst::Map(vec![1; 1 << StatefulConfig::default().bin_shift()])));
+            for round in 0..10 {
+                input.advance_to(round);
+                input.send(round);
+            }
+        }
+        control_input.close();
+        input.close();
+        while !probe.done() {
+            worker.step();
+        }
+
+    }).unwrap();
+
+    let results = results.lock().unwrap();
+    assert_eq!(results.len(), 20, "expected a result per record and iteration, got {:?}", results);
+    for key in 0..2 {
+        let sum = results.iter().filter(|&&(k, _)| k == key).map(|&(_, sum)| sum).max();
+        let expected = 2 * (0..10).filter(|x| x % 2 == key).sum::<usize>();
+        assert_eq!(sum, Some(expected), "wrong final sum for key {}", key);
+    }
+}
@@ -0,0 +1,240 @@
+//! Randomized migration tests.
+//!
+//! Each case draws a worker count, bin shift, state chunk size, input and migration plan from a
+//! seeded generator. Outputs of `stateful_state_machine`, `stateful_unary` and `stateful_binary`
+//! under the plan must equal those of a reference execution without migrations. Records are
+//! delayed into the future so that they wait in bins' notificators while bins move, and
+//! `stateful_unary` schedules echoes of its records through the notificator.
+//!
+//! Keys see at most one record per time, such that outputs do not depend on the order in which
+//! records of the same time are processed.
+
+extern crate rand;
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use timely::dataflow::*;
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::{Broadcast, Filter, Input, Inspect, Map, Operator, Probe};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{Bin, BinId, ControlInst, Control, StatefulConfig};
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+use dynamic_scaling_mechanism::state_machine::BinnedStateMachine;
+
+const CASES: u8 = 12;
+const ROUNDS: usize = 12;
+const KEYS: u64 = 16;
+const MAX_DELAY: u64 = 3;
+const MAX_ECHOES: u64 = 2;
+
+/// An input record: key, value, delay and number of echoes.
+type Record = (u64, u64, u64, u64);
+
+/// An output record, tagged with the producing operator and its time.
+type Output = (&'static str, usize, u64, u64, u64);
+
+#[derive(Clone, Debug)]
+struct Plan {
+    peers: usize,
+    bin_shift: usize,
+    state_chunk_size: usize,
+    /// Records per round, each sent by worker `index % peers`
+    rounds: Vec<Vec<Record>>,
+    /// Configurations by time, ascending
+    controls: Vec<(usize, Vec<ControlInst>)>,
+}
+
+impl Plan {
+    fn generate(seed: u8) -> Self {
+        let mut rng = StdRng::from_seed([seed; 32]);
+        let peers = rng.gen_range(1, 5);
+        let bin_shift = rng.gen_range(1, 7);
+        let state_chunk_size = if rng.gen() { rng.gen_range(1, 4) } else { usize::max_value() };
+
+        // Occupied (key, time) pairs, including echoes
+        let mut occupied = HashSet::new();
+        let mut rounds = vec![Vec::new(); ROUNDS];
+        for (round, records) in rounds.iter_mut().enumerate() {
+            for _ in 0..rng.gen_range(0, KEYS as usize) {
+                let key = rng.gen_range(0, KEYS);
+                let delay = rng.gen_range(0, MAX_DELAY + 1);
+                let echoes = rng.gen_range(0, MAX_ECHOES + 1);
+                let times: Vec<_> = (0..echoes + 1).map(|echo| (key, round as u64 + delay + echo)).collect();
+                if times.iter().all(|t| !occupied.contains(t)) {
+                    occupied.extend(times);
+                    records.push((key, rng.gen_range(0, 1000), delay, echoes));
+                }
+            }
+        }
+
+        let bins = 1 << bin_shift;
+        let mut controls = Vec::new();
+        let mut time = 0;
+        loop {
+            time += rng.gen_range(1, 4);
+            if time >= ROUNDS + (MAX_DELAY + MAX_ECHOES) as usize {
+                break;
+            }
+            let instructions = if rng.gen() {
+                vec![ControlInst::Map((0..bins).map(|_| rng.gen_range(0, peers)).collect())]
+            } else {
+                (0..rng.gen_range(1, bins + 1)).map(|_| ControlInst::Move(BinId::new(rng.gen_range(0, bins)), rng.gen_range(0, peers))).collect()
+            };
+            controls.push((time, instructions));
+        }
+
+        Plan { peers, bin_shift, state_chunk_size, rounds, controls }
+    }
+}
+
+/// Send each record at its time plus its delay.
+fn delay<G: Scope<Timestamp=usize>>(stream: &Stream<G, Record>) -> Stream<G, (u64, u64, u64)> {
+    stream.unary(Pipeline, "Delay", |_cap, _info| {
+        let mut buffer = Vec::new();
+        move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                let cap = time.retain();
+                for (key, val, delay, echoes) in buffer.drain(..) {
+                    let delayed = cap.delayed(&(*cap.time() + delay as usize));
+                    output.session(&delayed).give((key, val, echoes));
+                }
+            });
+        }
+    })
+}
+
+fn hash(key: u64) -> u64 {
+    key.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// Run all operators under `plan`, optionally applying its migrations, and collect their outputs.
+fn run(plan: &Plan, migrate: bool) -> Vec<Output> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let shared = results.clone();
+    let plan = plan.clone();
+    timely::execute(Configuration::Process(plan.peers), move |worker| {
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        let config = StatefulConfig::new(plan.bin_shift).with_state_chunk_size(plan.state_chunk_size);
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let records = delay(&scope.input_from(&mut input));
+
+            let results = shared.clone();
+            records
+                .map(|(key, val, _echoes)| (key, val))
+                .stateful_state_machine(
+                    |key, val, agg: &mut u64| {
+                        *agg += val;
+                        (false, Some((*key, val, *agg)))
+                    },
+                    |key| hash(*key),
+                    &control,
+                    config.clone(),
+                )
+                .inspect_batch(move |t, xs| {
+                    results.lock().unwrap().extend(xs.iter().map(|&(key, val, agg)| ("machine", *t, key, val, agg)));
+                })
+                .probe_with(&mut probe);
+
+            let results = shared.clone();
+            records
+                .stateful_unary(&control, config.clone(), |&(key, _, _)| hash(key), "Echo", |cap, data, bin: &mut Bin<_, HashMap<u64, u64>, _>, output| {
+                    let mut session = output.session(&cap);
+                    for (time, (key, val, echoes)) in data.drain(..) {
+                        let count = {
+                            let count = bin.state().entry(key).or_insert(0);
+                            *count += 1;
+                            *count
+                        };
+                        session.give((key, val, count));
+                        if echoes > 0 {
+                            bin.notificator().notify_at_data(&cap, time + 1, (key, val, echoes - 1));
+                        }
+                    }
+                })
+                .inspect_batch(move |t, xs| {
+                    results.lock().unwrap().extend(xs.iter().map(|&(key, val, count)| ("unary", *t, key, val, count)));
+                })
+                .probe_with(&mut probe);
+
+            let results = shared.clone();
+            let left = records.filter(|&(_, val, _)| val % 2 == 0).map(|(key, val, _)| (key, val));
+            let right = records.filter(|&(_, val, _)| val % 2 == 1).map(|(key, val, _)| (key, val));
+            left
+                .stateful_binary(&control, config.clone(), &right, |&(key, _)| hash(key), |&(key, _)| hash(key), "Join", |cap, data, bin1: &mut Bin<_, HashMap<u64, Vec<u64>>, _>, bin2: &mut Bin<_, HashMap<u64, Vec<u64>>, _>, output| {
+                    let mut session = output.session(&cap);
+                    for (_time, (key, val)) in data.drain(..) {
+                        for other in bin2.state().get(&key).into_iter().flat_map(|vals| vals.iter()) {
+                            session.give((key, val, *other));
+                        }
+                        bin1.state().entry(key).or_insert_with(Vec::new).push(val);
+                    }
+                }, |cap, data, bin1, bin2, output| {
+                    let mut session = output.session(&cap);
+                    for (_time, (key, val)) in data.drain(..) {
+                        for other in bin1.state().get(&key).into_iter().flat_map(|vals| vals.iter()) {
+                            session.give((key, *other, val));
+                        }
+                        bin2.state().entry(key).or_insert_with(Vec::new).push(val);
+                    }
+                })
+                .inspect_batch(move |t, xs| {
+                    results.lock().unwrap().extend(xs.iter().map(|&(key, left, right)| ("binary", *t, key, left, right)));
+                })
+                .probe_with(&mut probe);
+        });
+
+        if migrate && index == 0 {
+            for (sequence, &(time, ref instructions)) in plan.controls.iter().enumerate() {
+                control_input.advance_to(time);
+                for inst in instructions {
+                    control_input.send(Control::new(sequence as u64 + 1, instructions.len(), inst.clone()));
+                }
+            }
+        }
+        control_input.close();
+
+        for (round, records) in plan.rounds.iter().enumerate() {
+            for (i, record) in records.iter().enumerate() {
+                if i % plan.peers == index {
+                    input.send(*record);
+                }
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+
+    let mut results = ::std::mem::replace(&mut *results.lock().unwrap(), Vec::new());
+    results.sort();
+    results
+}
+
+#[test]
+fn random_migrations_match_reference() {
+    for seed in 0..CASES {
+        let plan = Plan::generate(seed);
+        let reference = run(&plan, false);
+        let migrated = run(&plan, true);
+        assert!(!reference.is_empty(), "seed {} produced no output: {:?}", seed, plan);
+        assert_eq!(migrated, reference, "seed {} diverged under plan {:?}", seed, plan);
+    }
+}
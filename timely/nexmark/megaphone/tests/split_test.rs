@@ -0,0 +1,90 @@
+extern crate timely;
+extern crate dynamic_scaling_mechanism;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use timely::dataflow::*;
+use timely::dataflow::operators::{Broadcast, Input, Probe, Inspect};
+
+use timely::Configuration;
+
+use dynamic_scaling_mechanism::{ControlInst, Control, StatefulConfig};
+use dynamic_scaling_mechanism::state_machine::{BinnedStateMachine, HotKeyDetector, SplitConfig};
+
+#[test]
+fn hot_key_detection() {
+    let mut detector = HotKeyDetector::new(SplitConfig::new(2).with_threshold(3).with_period(8));
+    // Counts of several workers add up
+    detector.observe(&"hot", 2);
+    assert!(!detector.is_hot(&"hot"));
+    detector.observe(&"hot", 2);
+    assert!(detector.is_hot(&"hot"));
+    detector.observe(&"cold", 1);
+    assert!(!detector.is_hot(&"cold"));
+    // The eighth observation halves all counts
+    detector.observe(&"cold", 3);
+    assert!(!detector.is_hot(&"hot"));
+    assert!(!detector.is_hot(&"cold"));
+}
+
+#[test]
+fn split_state_machine() {
+    let results = Arc::new(Mutex::new(HashMap::new()));
+    let shared = results.clone();
+    timely::execute(Configuration::Process(2), move |worker| {
+
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        let config = StatefulConfig::new(4);
+        let results = shared.clone();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let input = scope.input_from(&mut input);
+            input
+                .stateful_split_state_machine(
+                    |_key, val, sum: &mut u64| *sum += val,
+                    |sum, partial| *sum += *partial,
+                    |key, sum| Some((*key, *sum)),
+                    |key| *key as u64,
+                    &control,
+                    config.clone(),
+                    SplitConfig::new(4).with_threshold(2),
+                )
+                .inspect(move |&(key, sum)| {
+                    let mut results = results.lock().unwrap();
+                    let max = results.entry(key).or_insert(0);
+                    assert!(sum >= *max, "Sum of key {} decreased from {} to {}", key, max, sum);
+                    *max = sum;
+                })
+                .probe_with(&mut probe);
+        });
+
+        control_input.advance_to(5);
+        if index == 0 {
+            control_input.send(Control::new(1, 1, ControlInst::Map(vec![1; config.bins()])));
+        }
+        control_input.close();
+        for round in 0..10 {
+            if index == 0 {
+                for _ in 0..10 {
+                    input.send((0u64, 1u64));
+                }
+                input.send((1, round));
+            }
+            input.advance_to(round as usize + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+
+    }).unwrap();
+
+    let results = results.lock().unwrap();
+    assert_eq!(results.get(&0), Some(&100));
+    assert_eq!(results.get(&1), Some(&45));
+}
@@ -10,13 +10,17 @@ extern crate rand;
 extern crate streaming_harness;
 extern crate dynamic_scaling_mechanism;
 extern crate fnv;
+extern crate clap;
+extern crate nexmark_generator;
 
 pub mod config;
-pub mod event;
 pub mod tools;
-pub mod utils;
+
+pub use nexmark_generator::{event, event_log, query, tcp, utils, EventGenerator, worker_share};
 
 pub mod queries;
+pub mod source;
+pub mod window;
 
 
 use std::hash::Hash;
@@ -27,3 +31,38 @@ pub fn calculate_hash<T: Hash>(t: &T) -> u64 {
     t.hash(&mut h);
     h.finish()
 }
+
+/// Keys of migratable operators. Ids keep their order under bin assigners that partition ordered
+/// keys, such as `assigner::Range`, while other keys are hashed under all assigners.
+pub trait PartitionKey: Hash {
+    /// The position of the key in an ordered key domain, if it has one.
+    fn ordered_key(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl PartitionKey for usize {
+    fn ordered_key(&self) -> Option<u64> {
+        Some(*self as u64)
+    }
+}
+
+impl PartitionKey for () {}
+
+impl PartitionKey for String {}
+
+/// Pairs are ordered by their first component, such that the pairs of an id share a range.
+impl<A: PartitionKey, B: Hash> PartitionKey for (A, B) {
+    fn ordered_key(&self) -> Option<u64> {
+        self.0.ordered_key()
+    }
+}
+
+/// The key a `stateful` operator routes `key` by: the raw ordered key if the operator's bin
+/// assigner is `ordered`, and its hash otherwise.
+pub fn partition_key<K: PartitionKey>(key: &K, ordered: bool) -> u64 {
+    match key.ordered_key() {
+        Some(ordered_key) if ordered => ordered_key,
+        _ => calculate_hash(key),
+    }
+}
@@ -1,20 +1,59 @@
+use ::std::cell::RefCell;
+use ::std::collections::HashMap;
+use ::std::fmt::Debug;
 use ::std::rc::Rc;
+use timely::Data;
 use timely::dataflow::{Scope, Stream};
 use timely::dataflow::operators::capture::event::link::EventLink;
-use timely::dataflow::operators::capture::Replay;
+use timely::dataflow::operators::capture::{Capture, Replay};
+use timely::dataflow::operators::Map;
 
-use dynamic_scaling_mechanism::Control;
+use dynamic_scaling_mechanism::{Control, StatefulConfig};
+use dynamic_scaling_mechanism::assigner::{AnyAssigner, BinAssigner};
 use event::{Bid, Auction, Person, Date};
+use window::{LatePolicy, Window, Windows};
 
 mod q1;
+mod q1_flex;
 mod q2;
+mod q2_flex;
 mod q3;
+mod q3_flex;
 mod q4_q6_common;
+mod q4_q6_common_flex;
 mod q4;
+mod q4_flex;
 mod q5;
+mod q5_flex;
 mod q6;
+mod q6_flex;
 mod q7;
+mod q7_flex;
 mod q8;
+mod q8_flex;
+mod q9;
+mod q9_flex;
+mod q10;
+mod q11;
+mod q11_flex;
+mod q12;
+mod q12_flex;
+mod q13;
+mod q14;
+mod q15;
+mod q15_flex;
+mod q16;
+mod q16_flex;
+mod q17;
+mod q17_flex;
+mod q18;
+mod q18_flex;
+mod q19;
+mod q19_flex;
+mod q20;
+mod q20_flex;
+mod q21;
+mod q22;
 
 pub use self::q1::q1;
 pub use self::q1_flex::q1_flex;
@@ -34,6 +73,38 @@ pub use self::q7::q7;
 pub use self::q7_flex::q7_flex;
 pub use self::q8::q8;
 pub use self::q8_flex::q8_flex;
+pub use self::q9::q9;
+pub use self::q9_flex::q9_flex;
+pub use self::q10::q10;
+pub use self::q11::q11;
+pub use self::q11_flex::q11_flex;
+pub use self::q12::q12;
+pub use self::q12_flex::q12_flex;
+pub use self::q13::q13;
+pub use self::q14::q14;
+pub use self::q15::q15;
+pub use self::q15_flex::q15_flex;
+pub use self::q16::q16;
+pub use self::q16_flex::q16_flex;
+pub use self::q17::q17;
+pub use self::q17_flex::q17_flex;
+pub use self::q18::q18;
+pub use self::q18_flex::q18_flex;
+pub use self::q19::q19;
+pub use self::q19_flex::q19_flex;
+pub use self::q20::q20;
+pub use self::q20_flex::q20_flex;
+pub use self::q21::q21;
+pub use self::q22::q22;
+
+pub use query::{Lateness, QueryConfig};
+
+/// A record a query discarded as late: its event time, in the dataflow's timestamps, and the
+/// record.
+pub type LateRecord = (usize, String);
+
+/// The side outputs of late records per query.
+pub type LateEvents = Rc<RefCell<HashMap<String, Rc<EventLink<usize, LateRecord>>>>>;
 
 pub struct NexmarkInput<'a> {
     pub control: &'a Rc<EventLink<usize, Control>>,
@@ -42,6 +113,9 @@ pub struct NexmarkInput<'a> {
     pub people: &'a Rc<EventLink<usize, Person>>,
     pub closed_auctions: &'a Rc<EventLink<usize, (Auction, Bid)>>,
     pub closed_auctions_flex: &'a Rc<EventLink<usize, (Auction, Bid)>>,
+    pub stateful_config: StatefulConfig<AnyAssigner>,
+    pub config: QueryConfig,
+    pub late_events: &'a LateEvents,
 }
 
 impl<'a> NexmarkInput<'a> {
@@ -68,6 +142,56 @@ impl<'a> NexmarkInput<'a> {
     pub fn closed_auctions_flex<S: Scope<Timestamp=usize>>(&self, scope: &mut S) -> Stream<S, (Auction, Bid)> {
         Some(self.closed_auctions_flex.clone()).replay_into(scope)
     }
+
+    /// Whether keys of migratable operators should be raw ordered keys rather than hashes, as
+    /// for `partition_key`.
+    pub fn ordered_keys(&self) -> bool {
+        self.stateful_config.bin_assigner().ordered()
+    }
+
+    /// The window policy that implements the lateness policy.
+    pub fn late_policy(&self, nt: NexmarkTimer) -> LatePolicy {
+        match self.config.lateness {
+            Lateness::Drop | Lateness::SideOutput => LatePolicy::Drop,
+            Lateness::Allowed(lateness_ns) => LatePolicy::Update(lateness_ns / nt.time_dilation),
+        }
+    }
+
+    /// Window records under the late policy that implements the lateness policy, and produce
+    /// the results. `window` applies the policy it receives, and the records it discards are the
+    /// side output of `query`.
+    pub fn windowed<S, K, V, O, F>(&self, query: &str, nt: NexmarkTimer, window: F) -> Stream<S, (K, Window, O)>
+        where
+            S: Scope<Timestamp=usize>,
+            K: Data+Debug,
+            V: Data+Debug,
+            O: Data,
+            F: FnOnce(LatePolicy) -> Windows<S, K, V, O>,
+    {
+        let windows = window(self.late_policy(nt));
+        self.side_output(query, &windows.late.map(|(key, event_time, value)| (event_time, format!("{:?}", (key, value)))));
+        windows.results
+    }
+
+    /// Make `late` the side output of `query` if late events are a side output.
+    pub fn side_output<S: Scope<Timestamp=usize>>(&self, query: &str, late: &Stream<S, LateRecord>) {
+        if self.config.lateness == Lateness::SideOutput {
+            let link = Rc::new(EventLink::new());
+            late.capture_into(link.clone());
+            let previous = self.late_events.borrow_mut().insert(query.to_string(), link);
+            assert!(previous.is_none(), "{} has a side output already", query);
+        }
+    }
+
+    /// The side outputs of the queries built so far, by query.
+    pub fn side_outputs<S: Scope<Timestamp=usize>>(&self, scope: &mut S) -> Vec<(String, Stream<S, LateRecord>)> {
+        let mut side_outputs = self.late_events.borrow().iter()
+            .map(|(query, link)| (query.clone(), Some(link.clone()).replay_into(scope)))
+            .collect::<Vec<_>>();
+        // All workers must build the same dataflow
+        side_outputs.sort_by(|a, b| a.0.cmp(&b.0));
+        side_outputs
+    }
 }
 
 
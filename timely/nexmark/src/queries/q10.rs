@@ -0,0 +1,52 @@
+use ::std::fs::File;
+use ::std::io::{BufWriter, Write};
+use ::std::path::PathBuf;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use ::event::Bid;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Aggregate, Windowed, WindowSpec};
+
+/// Collects the bids of a window as JSON lines.
+#[derive(Clone, Default)]
+struct Log(Vec<String>);
+
+impl Aggregate<Bid> for Log {
+    type Output = Vec<String>;
+
+    fn add(&mut self, bid: &Bid) {
+        self.0.push(::serde_json::to_string(bid).expect("couldn't serialize bid"));
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    fn result(&self) -> Vec<String> {
+        self.0.clone()
+    }
+}
+
+/// Writes each worker's bids of each window to a file in `dir`, and reports the files' names and
+/// number of bids.
+pub fn q10<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, dir: PathBuf, window_size_ns: usize) -> Stream<S, (String, usize)>
+{
+    let index = scope.index();
+    ::std::fs::create_dir_all(&dir).expect("couldn't create log directory");
+
+    let spec = WindowSpec::Tumbling(window_size_ns / nt.time_dilation);
+    let bids = input.bids(scope)
+        .map(move |b| ((), nt.from_nexmark_time(b.date_time), b));
+
+    input.windowed("q10", nt, |late| bids.local_window::<Log>(spec, late, "Q10 Log"))
+        .map(move |((), window, lines)| {
+            let path = dir.join(format!("{}-{}-{}.json", window.start, window.end, index));
+            let mut file = BufWriter::new(File::create(&path).expect("couldn't create log file"));
+            for line in lines.iter() {
+                writeln!(file, "{}", line).expect("couldn't write log file");
+            }
+            (path.to_string_lossy().into_owned(), lines.len())
+        })
+}
@@ -0,0 +1,15 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Count, Windowed, WindowSpec};
+
+pub fn q11<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, session_gap_ns: usize) -> Stream<S, (usize, usize)>
+{
+    let spec = WindowSpec::Session(session_gap_ns / nt.time_dilation);
+    let bids = input.bids(scope)
+        .map(move |b| (b.bidder, nt.from_nexmark_time(b.date_time), ()));
+
+    input.windowed("q11", nt, |late| bids.window::<Count>(spec, late, "Q11 Sessions"))
+        .map(|(bidder, _window, count)| (bidder, count))
+}
@@ -0,0 +1,17 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Count, Windowed, WindowSpec};
+
+pub fn q11_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, session_gap_ns: usize) -> Stream<S, (usize, usize)>
+{
+    let control = input.control(scope);
+
+    let spec = WindowSpec::Session(session_gap_ns / nt.time_dilation);
+    let bids = input.bids(scope)
+        .map(move |b| (b.bidder, nt.from_nexmark_time(b.date_time), ()));
+
+    input.windowed("q11-flex", nt, |late| bids.window_flex::<Count, _>(spec, late, &control, input.stateful_config.clone(), "Q11 Sessions"))
+        .map(|(bidder, _window, count)| (bidder, count))
+}
@@ -0,0 +1,43 @@
+use ::std::time::Instant;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::{Map, Operator};
+
+use ::event::Bid;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Count, LatePolicy, Windowed, WindowSpec};
+
+/// Keys bids by bidder and stamps them with their processing time, the wall-clock time at which
+/// this operator saw them. The clock counts nanoseconds from the timestamp of the first bid, and
+/// a bid's processing time is never before its timestamp, such that its window is still open.
+pub fn processing_time<S: Scope<Timestamp=usize>>(bids: &Stream<S, Bid>) -> Stream<S, (usize, usize, ())> {
+    let mut bids_buffer = vec![];
+    // The timestamp of the first bid and when it was seen
+    let mut clock: Option<(usize, Instant)> = None;
+
+    bids.unary(Pipeline, "Q12 Processing time", |_cap, _info| {
+        move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut bids_buffer);
+                let (start, started) = *clock.get_or_insert_with(|| (*time.time(), Instant::now()));
+                let now = start + started.elapsed().as_nanos() as usize;
+                let processing_time = ::std::cmp::max(now, *time.time());
+                output.session(&time).give_iterator(bids_buffer.drain(..).map(|b| (b.bidder, processing_time, ())));
+            });
+        }
+    })
+}
+
+/// Counts each bidder's bids in tumbling windows of processing time. Processing time passes at
+/// the rate of the dataflow's timestamps, so windows span `window_size_ns` of event time like the
+/// windows of the other queries.
+pub fn q12<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, (usize, usize)>
+{
+    let spec = WindowSpec::Tumbling(window_size_ns / nt.time_dilation);
+
+    processing_time(&input.bids(scope))
+        .window::<Count>(spec, LatePolicy::Drop, "Q12 Windows")
+        .results
+        .map(|(bidder, _window, count)| (bidder, count))
+}
@@ -0,0 +1,18 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q12::processing_time;
+use window::{Count, LatePolicy, Windowed, WindowSpec};
+
+/// Counts each bidder's bids in tumbling windows of processing time, as `q12`.
+pub fn q12_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, (usize, usize)>
+{
+    let control = input.control(scope);
+    let spec = WindowSpec::Tumbling(window_size_ns / nt.time_dilation);
+
+    processing_time(&input.bids(scope))
+        .window_flex::<Count, _>(spec, LatePolicy::Drop, &control, input.stateful_config.clone(), "Q12 Windows")
+        .results
+        .map(|(bidder, _window, count)| (bidder, count))
+}
@@ -0,0 +1,18 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use ::event::Date;
+use ::query::{side_input, side_input_key};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+pub fn q13<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize, usize, Date, String)>
+{
+    let side_input = side_input();
+
+    input.bids(scope)
+        .map(move |b| {
+            let value = side_input[&side_input_key(b.auction)].clone();
+            (b.auction, b.bidder, b.price, b.date_time, value)
+        })
+}
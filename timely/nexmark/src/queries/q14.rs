@@ -0,0 +1,14 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::{Filter, Map};
+
+use ::event::Date;
+use ::query::bid_time_type;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+pub fn q14<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize, f32, &'static str, Date, usize)>
+{
+    input.bids(scope)
+        .map(|b| (b.auction, b.bidder, b.price as f32 * 0.908, bid_time_type(b.date_time), b.date_time, b.extra.matches('c').count()))
+        .filter(|&(_, _, price, _, _, _)| 1_000_000. < price && price < 50_000_000.)
+}
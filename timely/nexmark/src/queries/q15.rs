@@ -0,0 +1,81 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use ::calculate_hash;
+use ::query::price_rank;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Aggregate, Windowed, WindowSpec};
+
+/// Bits of a hash that select the register of a `DistinctCount`.
+const REGISTER_BITS: u32 = 10;
+
+/// Estimates the number of distinct values with a HyperLogLog sketch of `1 << REGISTER_BITS`
+/// registers, with a standard error of about 3%. Sketches merge by taking the maximum of each
+/// register, so estimates do not depend on the order in which values are added.
+#[derive(Abomonation, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DistinctCount {
+    /// Per register, the maximal position of the first set bit in the hashes it saw, or empty
+    /// before the first value
+    registers: Vec<u8>,
+}
+
+impl DistinctCount {
+    /// Add a value.
+    pub fn insert(&mut self, value: usize) {
+        if self.registers.is_empty() {
+            self.registers = vec![0; 1 << REGISTER_BITS];
+        }
+        // Mix the hash such that all of its bits depend on all bits of the value
+        let mut hash = calculate_hash(&value);
+        hash = (hash ^ (hash >> 33)).wrapping_mul(0xff51afd7ed558ccd);
+        hash = (hash ^ (hash >> 33)).wrapping_mul(0xc4ceb9fe1a85ec53);
+        hash ^= hash >> 33;
+        let register = (hash >> (64 - REGISTER_BITS)) as usize;
+        let rank = ((hash << REGISTER_BITS) | (1 << (REGISTER_BITS - 1))).leading_zeros() as u8 + 1;
+        self.registers[register] = ::std::cmp::max(self.registers[register], rank);
+    }
+
+    /// Absorb the values of another sketch.
+    pub fn merge(&mut self, other: &Self) {
+        if self.registers.is_empty() {
+            self.registers = other.registers.clone();
+        } else {
+            for (register, &other) in self.registers.iter_mut().zip(other.registers.iter()) {
+                *register = ::std::cmp::max(*register, other);
+            }
+        }
+    }
+
+    /// The estimated number of distinct values.
+    pub fn estimate(&self) -> usize {
+        if self.registers.is_empty() {
+            return 0;
+        }
+        let m = self.registers.len() as f64;
+        let sum: f64 = self.registers.iter().map(|&rank| 2f64.powi(-(rank as i32))).sum();
+        let estimate = 0.7213 / (1. + 1.079 / m) * m * m / sum;
+        let empty = self.registers.iter().filter(|&&rank| rank == 0).count();
+        if estimate <= 2.5 * m && empty > 0 {
+            // Small cardinalities are estimated more accurately from the empty registers
+            (m * (m / empty as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+pub fn q15<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, (usize, [usize; 4], [usize; 4], [usize; 4])>
+{
+    let window_size = window_size_ns / nt.time_dilation;
+    let spec = WindowSpec::Tumbling(window_size);
+    // Bids are keyed by their window, i.e., their day, such that windows spread across workers
+    let bids = input.bids(scope)
+        .map(move |b| {
+            let event_time = nt.from_nexmark_time(b.date_time);
+            (event_time / window_size, event_time, (b.price, b.bidder, b.auction))
+        });
+
+    input.windowed("q15", nt, |late| bids.window::<BidStatistics>(spec, late, "Q15 Statistics"))
+        .map(|(_day, window, (bids, bidders, auctions))| (window.start, bids, bidders, auctions))
+}
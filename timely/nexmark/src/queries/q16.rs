@@ -0,0 +1,21 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q15::BidStatistics;
+use window::{Windowed, WindowSpec};
+
+pub fn q16<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, (String, usize, [usize; 4], [usize; 4], [usize; 4])>
+{
+    let window_size = window_size_ns / nt.time_dilation;
+    let spec = WindowSpec::Tumbling(window_size);
+    // Bids are keyed by channel and day, such that the windows of a channel spread across workers
+    let bids = input.bids(scope)
+        .map(move |b| {
+            let event_time = nt.from_nexmark_time(b.date_time);
+            ((b.channel, event_time / window_size), event_time, (b.price, b.bidder, b.auction))
+        });
+
+    input.windowed("q16", nt, |late| bids.window::<BidStatistics>(spec, late, "Q16 Statistics"))
+        .map(|((channel, _day), window, (bids, bidders, auctions))| (channel, window.start, bids, bidders, auctions))
+}
@@ -0,0 +1,53 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use ::query::price_rank;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Aggregate, Windowed, WindowSpec};
+
+/// Counts bids in total and per price rank, and summarizes their prices.
+#[derive(Abomonation, Clone, Debug, Default, Eq, PartialEq)]
+pub struct AuctionStatistics {
+    bids: [usize; 4],
+    min: Option<usize>,
+    max: Option<usize>,
+    sum: usize,
+}
+
+impl Aggregate<usize> for AuctionStatistics {
+    /// Bids, minimal, maximal, average and total price
+    type Output = ([usize; 4], usize, usize, usize, usize);
+
+    fn add(&mut self, &price: &usize) {
+        self.bids[0] += 1;
+        self.bids[price_rank(price)] += 1;
+        self.min = Some(self.min.map_or(price, |min| ::std::cmp::min(min, price)));
+        self.max = Some(self.max.map_or(price, |max| ::std::cmp::max(max, price)));
+        self.sum += price;
+    }
+
+    fn merge(&mut self, other: Self) {
+        for index in 0..4 {
+            self.bids[index] += other.bids[index];
+        }
+        self.min = self.min.into_iter().chain(other.min).min();
+        self.max = self.max.into_iter().chain(other.max).max();
+        self.sum += other.sum;
+    }
+
+    fn result(&self) -> Self::Output {
+        let average = if self.bids[0] > 0 { self.sum / self.bids[0] } else { 0 };
+        (self.bids, self.min.unwrap_or(0), self.max.unwrap_or(0), average, self.sum)
+    }
+}
+
+pub fn q17<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, (usize, usize, [usize; 4], usize, usize, usize, usize)>
+{
+    let spec = WindowSpec::Tumbling(window_size_ns / nt.time_dilation);
+    let bids = input.bids(scope)
+        .map(move |b| (b.auction, nt.from_nexmark_time(b.date_time), b.price));
+
+    input.windowed("q17", nt, |late| bids.window::<AuctionStatistics>(spec, late, "Q17 Statistics"))
+        .map(|(auction, window, (bids, min, max, average, sum))| (auction, window.start, bids, min, max, average, sum))
+}
@@ -0,0 +1,18 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q17::AuctionStatistics;
+use window::{Windowed, WindowSpec};
+
+pub fn q17_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, (usize, usize, [usize; 4], usize, usize, usize, usize)>
+{
+    let control = input.control(scope);
+
+    let spec = WindowSpec::Tumbling(window_size_ns / nt.time_dilation);
+    let bids = input.bids(scope)
+        .map(move |b| (b.auction, nt.from_nexmark_time(b.date_time), b.price));
+
+    input.windowed("q17-flex", nt, |late| bids.window_flex::<AuctionStatistics, _>(spec, late, &control, input.stateful_config.clone(), "Q17 Statistics"))
+        .map(|(auction, window, (bids, min, max, average, sum))| (auction, window.start, bids, min, max, average, sum))
+}
@@ -0,0 +1,68 @@
+use ::std::collections::HashMap;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{CapabilitySet, Operator};
+
+use ::calculate_hash;
+use ::event::{Bid, Date};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+/// Updates the last bid of a bidder on an auction, given the `(date_time, price)` of the last bid
+/// so far, and returns whether `bid` is the new last bid. Bids with equal times are ranked by
+/// price. Callers process the bids of a time in sorted order, such that the results do not depend
+/// on the order in which bids arrive.
+pub fn update_last(last: &mut HashMap<(usize, usize), (Date, usize)>, bid: &Bid) -> bool {
+    let rank = (bid.date_time, bid.price);
+    let key = (bid.bidder, bid.auction);
+    if last.get(&key).map_or(true, |last| *last < rank) {
+        last.insert(key, rank);
+        true
+    } else {
+        false
+    }
+}
+
+pub fn q18<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, Bid>
+{
+    let mut bids_buffer = vec![];
+
+    input.bids(scope)
+        .unary_frontier(Exchange::new(|b: &Bid| calculate_hash(&(b.bidder, b.auction))), "Q18 Last bid",
+               |capability, _info| {
+                   let mut cap_set = CapabilitySet::new();
+                   cap_set.insert(capability);
+
+                   // Stores (bidder, auction) -> (time, price) of the last bid
+                   let mut state = HashMap::new();
+                   // Stores time -> bids of the time, until the time is complete
+                   let mut pending = HashMap::new();
+
+                   move |input, output| {
+                       input.for_each(|time, data| {
+                           data.swap(&mut bids_buffer);
+                           pending.entry(*time.time()).or_insert_with(Vec::new).extend(bids_buffer.drain(..));
+                       });
+
+                       let mut times = pending
+                           .keys()
+                           .filter(|t| !input.frontier.less_equal(t))
+                           .cloned()
+                           .collect::<Vec<_>>();
+                       times.sort();
+
+                       for time in times.drain(..) {
+                           let mut bids: Vec<Bid> = pending.remove(&time).unwrap();
+                           bids.sort();
+                           let time = cap_set.delayed(&time);
+                           let mut session = output.session(&time);
+                           for bid in bids {
+                               if update_last(&mut state, &bid) {
+                                   session.give(bid);
+                               }
+                           }
+                       }
+                       cap_set.downgrade(&input.frontier.frontier());
+                   }
+               })
+}
@@ -0,0 +1,30 @@
+use ::std::collections::HashMap;
+use ::timely::dataflow::{Scope, Stream};
+
+use dynamic_scaling_mechanism::Bin;
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use ::partition_key;
+use ::event::{Bid, Date};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q18::update_last;
+
+pub fn q18_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, Bid>
+{
+    let control = input.control(scope);
+    let ordered = input.ordered_keys();
+
+    input.bids(scope)
+        .stateful_unary(&control, input.stateful_config.clone(), move |b: &Bid| partition_key(&(b.bidder, b.auction), ordered), "Q18 Last bid",
+                        |cap, data, bin: &mut Bin<_, HashMap<(usize, usize), (Date, usize)>, _>, output| {
+                            // Records of a time arrive in any order, which must not affect the results
+                            data.sort();
+                            let mut session = output.session(&cap);
+                            for (_time, bid) in data.drain(..) {
+                                if update_last(bin.state(), &bid) {
+                                    session.give(bid);
+                                }
+                            }
+                        })
+}
@@ -0,0 +1,68 @@
+use ::std::collections::HashMap;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{CapabilitySet, Map, Operator};
+
+use ::query::TOP_BIDS;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+/// Inserts `price` into the descending `top` prices if it ranks among the top bids, and returns its
+/// rank starting at 1. A price ranks below equal prices inserted before it, so callers process the
+/// bids of a time in sorted order, such that ranks do not depend on the order in which bids arrive.
+pub fn insert_top(top: &mut Vec<usize>, price: usize) -> Option<usize> {
+    let position = top.iter().position(|&other| other < price).unwrap_or(top.len());
+    if position < TOP_BIDS {
+        top.insert(position, price);
+        top.truncate(TOP_BIDS);
+        Some(position + 1)
+    } else {
+        None
+    }
+}
+
+pub fn q19<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize, usize, usize)>
+{
+    let mut bids_buffer = vec![];
+
+    input.bids(scope)
+        .map(|b| (b.auction, b.bidder, b.price))
+        .unary_frontier(Exchange::new(|x: &(usize, usize, usize)| x.0 as u64), "Q19 Top bids",
+               |capability, _info| {
+                   let mut cap_set = CapabilitySet::new();
+                   cap_set.insert(capability);
+
+                   // Stores auction -> descending top prices
+                   let mut state = HashMap::new();
+                   // Stores time -> bids of the time, until the time is complete
+                   let mut pending = HashMap::new();
+
+                   move |input, output| {
+                       input.for_each(|time, data| {
+                           data.swap(&mut bids_buffer);
+                           pending.entry(*time.time()).or_insert_with(Vec::new).extend(bids_buffer.drain(..));
+                       });
+
+                       let mut times = pending
+                           .keys()
+                           .filter(|t| !input.frontier.less_equal(t))
+                           .cloned()
+                           .collect::<Vec<_>>();
+                       times.sort();
+
+                       for time in times.drain(..) {
+                           let mut bids: Vec<(usize, usize, usize)> = pending.remove(&time).unwrap();
+                           bids.sort();
+                           let time = cap_set.delayed(&time);
+                           let mut session = output.session(&time);
+                           for (auction, bidder, price) in bids {
+                               let top = state.entry(auction).or_insert_with(Vec::new);
+                               if let Some(rank) = insert_top(top, price) {
+                                   session.give((auction, bidder, price, rank));
+                               }
+                           }
+                       }
+                       cap_set.downgrade(&input.frontier.frontier());
+                   }
+               })
+}
@@ -0,0 +1,32 @@
+use ::std::collections::HashMap;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use dynamic_scaling_mechanism::Bin;
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use ::partition_key;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q19::insert_top;
+
+pub fn q19_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize, usize, usize)>
+{
+    let control = input.control(scope);
+    let ordered = input.ordered_keys();
+
+    input.bids(scope)
+        .map(|b| (b.auction, b.bidder, b.price))
+        .stateful_unary(&control, input.stateful_config.clone(), move |x: &(usize, usize, usize)| partition_key(&x.0, ordered), "Q19 Top bids",
+                        |cap, data, bin: &mut Bin<_, HashMap<usize, Vec<usize>>, _>, output| {
+                            // Records of a time arrive in any order, which must not affect the ranks
+                            data.sort();
+                            let mut session = output.session(&cap);
+                            for (_time, (auction, bidder, price)) in data.drain(..) {
+                                let top = bin.state().entry(auction).or_insert_with(Vec::new);
+                                if let Some(rank) = insert_top(top, price) {
+                                    session.give((auction, bidder, price, rank));
+                                }
+                            }
+                        })
+}
@@ -0,0 +1,13 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::{Map};
+
+use ::event::Bid;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+/// Q1 keeps no state, so the flex variant is the same as `q1`.
+pub fn q1_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, Bid>
+{
+    input.bids(scope)
+        .map_in_place(|b| b.price = (b.price as f32 * 0.908) as usize)
+}
@@ -5,8 +5,8 @@ use {queries::NexmarkInput, queries::NexmarkTimer};
 
 pub fn q2<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize)>
 {
-    let auction_skip = 123;
+    let config = input.config;
     input.bids(scope)
-        .filter(move |b| b.auction % auction_skip == 0)
+        .filter(move |b| config.q2_selects(b.auction))
         .map(|b| (b.auction, b.price))
 }
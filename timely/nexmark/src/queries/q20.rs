@@ -0,0 +1,79 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::Operator;
+
+use dynamic_scaling_mechanism::join::JoinState;
+
+use ::event::{Auction, Bid, Date};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+/// The category of auctions to join bids with.
+pub const CATEGORY: usize = 10;
+
+/// Joins bids with their auctions. Auctions are kept until their expiry is the allowed lateness
+/// behind the frontier, and bids that arrived before their auction until their own time is.
+pub fn q20<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S) -> Stream<S, (Bid, Auction)>
+{
+    let bids = input.bids(scope);
+    let auctions = input.auctions(scope);
+    let allowed = Date::new(input.config.lateness.allowed_ns());
+
+    let mut bids_buffer = vec![];
+    let mut auctions_buffer = vec![];
+
+    bids
+        .binary_frontier(
+            &auctions,
+            Exchange::new(|b: &Bid| b.auction as u64),
+            Exchange::new(|a: &Auction| a.id as u64),
+            "Q20 Join",
+            |_capability, _info| {
+                // Stores auction id -> auction, or `None` for auctions of other categories
+                let mut auctions_state = JoinState::<usize, usize, Option<Auction>>::default();
+                // Stores auction id -> bids that arrived before their auction
+                let mut pending = JoinState::<usize, usize, Bid>::default();
+
+                move |input1, input2, output| {
+
+                    // Process each input bid.
+                    input1.for_each(|time, data| {
+                        data.swap(&mut bids_buffer);
+                        let mut session = output.session(&time);
+                        for bid in bids_buffer.drain(..) {
+                            match auctions_state.get(&bid.auction).next() {
+                                Some(&Some(ref auction)) => session.give((bid, auction.clone())),
+                                Some(&None) => {},
+                                None => {
+                                    let expires = nt.from_nexmark_time(bid.date_time + allowed);
+                                    pending.insert(bid.auction, Some(expires), bid);
+                                },
+                            }
+                        }
+                    });
+
+                    // Process each input auction.
+                    input2.for_each(|time, data| {
+                        data.swap(&mut auctions_buffer);
+                        let mut session = output.session(&time);
+                        for auction in auctions_buffer.drain(..) {
+                            let expires = nt.from_nexmark_time(auction.expires + allowed);
+                            if auction.category == CATEGORY {
+                                for bid in pending.get(&auction.id) {
+                                    session.give((bid.clone(), auction.clone()));
+                                }
+                                auctions_state.insert(auction.id, Some(expires), Some(auction));
+                            } else {
+                                auctions_state.insert(auction.id, Some(expires), None);
+                            }
+                        }
+                    });
+
+                    // Drop the state that no future bid or auction can join with.
+                    let frontier = input1.frontier.frontier().iter().chain(input2.frontier.frontier().iter()).cloned().collect::<Vec<_>>();
+                    auctions_state.expire_frontier(&frontier);
+                    pending.expire_frontier(&frontier);
+                }
+            }
+        )
+}
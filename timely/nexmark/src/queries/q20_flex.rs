@@ -0,0 +1,57 @@
+use ::timely::dataflow::{Scope, Stream};
+
+use dynamic_scaling_mechanism::Bin;
+use dynamic_scaling_mechanism::join::JoinState;
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use ::partition_key;
+use ::event::{Auction, Bid, Date};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q20::CATEGORY;
+
+/// Joins bids with their auctions, expiring state as `q20`.
+pub fn q20_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S) -> Stream<S, (Bid, Auction)>
+{
+    let control = input.control(scope);
+    let ordered = input.ordered_keys();
+    let bids = input.bids(scope);
+    let auctions = input.auctions(scope);
+    let allowed = Date::new(input.config.lateness.allowed_ns());
+
+    bids.stateful_binary_frontier(&control, input.stateful_config.clone(), &auctions, move |b: &Bid| partition_key(&b.auction, ordered), move |a: &Auction| partition_key(&a.id, ordered), "Q20 Join",
+        // Bins of the first input store bids that arrived before their auction, bins of the second
+        // input store auctions, or `None` for auctions of other categories.
+        move |cap, data, pending: &mut Bin<_, JoinState<usize, usize, Bid>, _>, auctions: &mut Bin<_, JoinState<usize, usize, Option<Auction>>, _>, output| {
+            let mut session = output.session(&cap);
+            for (_time, bid) in data.drain(..) {
+                match auctions.state().get(&bid.auction).next() {
+                    Some(&Some(ref auction)) => session.give((bid, auction.clone())),
+                    Some(&None) => {},
+                    None => {
+                        let expires = nt.from_nexmark_time(bid.date_time + allowed);
+                        pending.state().insert(bid.auction, Some(expires), bid);
+                    },
+                }
+            }
+        },
+        move |cap, data, pending, auctions, output| {
+            let mut session = output.session(&cap);
+            for (_time, auction) in data.drain(..) {
+                let expires = nt.from_nexmark_time(auction.expires + allowed);
+                if auction.category == CATEGORY {
+                    for bid in pending.state().get(&auction.id) {
+                        session.give((bid.clone(), auction.clone()));
+                    }
+                    auctions.state().insert(auction.id, Some(expires), Some(auction));
+                } else {
+                    auctions.state().insert(auction.id, Some(expires), None);
+                }
+            }
+        },
+        // Drop the state that no future bid or auction can join with.
+        |frontier, pending, auctions| {
+            pending.state().expire_frontier(frontier);
+            auctions.state().expire_frontier(frontier);
+        })
+}
@@ -0,0 +1,12 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use ::query::channel_id;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+pub fn q21<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize, usize, String, String)>
+{
+    input.bids(scope)
+        .flat_map(|b| channel_id(&b.channel, &b.url).map(|id| (b.auction, b.bidder, b.price, b.channel, id)))
+}
@@ -0,0 +1,23 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+/// Extracts the first three directories of a URL, empty if absent.
+pub fn url_directories(url: &str) -> (String, String, String) {
+    let mut parts = url.split('/').skip(3).map(String::from);
+    let mut next = || parts.next().unwrap_or_default();
+    let dir1 = next();
+    let dir2 = next();
+    let dir3 = next();
+    (dir1, dir2, dir3)
+}
+
+pub fn q22<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize, usize, String, String, String, String)>
+{
+    input.bids(scope)
+        .map(|b| {
+            let (dir1, dir2, dir3) = url_directories(&b.url);
+            (b.auction, b.bidder, b.price, b.channel, dir1, dir2, dir3)
+        })
+}
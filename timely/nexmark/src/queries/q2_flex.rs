@@ -0,0 +1,13 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::{Filter, Map};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+/// Q2 keeps no state, so the flex variant is the same as `q2`.
+pub fn q2_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, usize)>
+{
+    let config = input.config;
+    input.bids(scope)
+        .filter(move |b| config.q2_selects(b.auction))
+        .map(|b| (b.auction, b.price))
+}
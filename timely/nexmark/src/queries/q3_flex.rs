@@ -0,0 +1,46 @@
+use ::std::collections::HashMap;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Filter;
+
+use dynamic_scaling_mechanism::Bin;
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use ::partition_key;
+use ::event::{Auction, Person};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+pub fn q3_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (String, String, String, usize)>
+{
+    let control = input.control(scope);
+    let ordered = input.ordered_keys();
+
+    let auctions = input.auctions(scope)
+        .filter(|a| a.category == 10);
+
+    let people = input.people(scope)
+        .filter(|p| p.state == "OR" || p.state == "ID" || p.state == "CA");
+
+    auctions.stateful_binary(&control, input.stateful_config.clone(), &people, move |a: &Auction| partition_key(&a.seller, ordered), move |p: &Person| partition_key(&p.id, ordered), "Q3 Join",
+        // Bins of the first input store auctions by seller, bins of the second input store people.
+        |cap, data, auctions: &mut Bin<_, HashMap<usize, Vec<Auction>>, _>, people: &mut Bin<_, HashMap<usize, Person>, _>, output| {
+            let mut session = output.session(&cap);
+            for (_time, auction) in data.drain(..) {
+                if let Some(person) = people.state().get(&auction.seller) {
+                    session.give((person.name.clone(), person.city.clone(), person.state.clone(), auction.id));
+                }
+                auctions.state().entry(auction.seller).or_insert_with(Vec::new).push(auction);
+            }
+        },
+        |cap, data, auctions, people, output| {
+            let mut session = output.session(&cap);
+            for (_time, person) in data.drain(..) {
+                if let Some(auctions) = auctions.state().get(&person.id) {
+                    for auction in auctions.iter() {
+                        session.give((person.name.clone(), person.city.clone(), person.state.clone(), auction.id));
+                    }
+                }
+                people.state().insert(person.id, person);
+            }
+        })
+}
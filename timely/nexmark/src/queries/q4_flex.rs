@@ -0,0 +1,32 @@
+use ::std::collections::HashMap;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use dynamic_scaling_mechanism::Bin;
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use ::partition_key;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+pub fn q4_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, f64)>
+{
+    let control = input.control(scope);
+    let ordered = input.ordered_keys();
+
+    input.closed_auctions_flex(scope)
+        .map(|(a, b)| (a.category, b.price))
+        .stateful_unary(&control, input.stateful_config.clone(), move |x: &(usize, usize)| partition_key(&x.0, ordered), "Q4 Average",
+                        // Stores category -> (total, count)
+                        |cap, data, bin: &mut Bin<_, HashMap<usize, (f64, f64)>, _>, output| {
+                            // Records of a time arrive in any order, which must not affect the running averages
+                            data.sort();
+                            let mut session = output.session(&cap);
+                            for (_time, (category, price)) in data.drain(..) {
+                                let entry = bin.state().entry(category).or_insert((0., 0.));
+                                entry.0 += price as f64;
+                                entry.1 += 1 as f64;
+                                session.give((category, entry.0 / entry.1));
+                            }
+                        })
+}
@@ -2,9 +2,9 @@
 use ::std::collections::HashMap;
 use ::timely::dataflow::{Scope, Stream};
 use timely::dataflow::channels::pact::Exchange;
-use timely::dataflow::operators::{Capability, Operator};
+use timely::dataflow::operators::{Capability, Map, Operator};
 
-use ::event::{Auction, Bid};
+use ::event::{Auction, Bid, Date, Event};
 
 use {queries::NexmarkInput, queries::NexmarkTimer};
 
@@ -12,8 +12,11 @@ pub fn q4_q6_common<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: Nexmark
 {
     let bids = input.bids(scope);
     let auctions = input.auctions(scope);
+    // Auctions close once their expiry is the allowed lateness behind the frontier. Bids and
+    // auctions arriving later are late, and produced as errors.
+    let allowed = Date::new(input.config.lateness.allowed_ns());
 
-    bids.binary_frontier(
+    let closed = bids.binary_frontier(
         &auctions,
         Exchange::new(|b: &Bid| b.auction as u64),
         Exchange::new(|a: &Auction| a.id as u64),
@@ -35,8 +38,15 @@ pub fn q4_q6_common<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: Nexmark
                 // Record each bid.
                 // NB: We don't summarize as the max, because we don't know which are valid.
                 input1.for_each(|time, data| {
+                    let mut late = Vec::new();
                     for bid in data.iter().cloned() {
 //                                        eprintln!("[{:?}] bid: {:?}", time.time().inner, bid);
+                        let retain_until = bid.date_time + allowed;
+                        if nt.from_nexmark_time(retain_until) < *time.time() && !state.get(&bid.auction).map_or(false, |entry| entry.0.is_some()) {
+                            // The bid's auction closed already, or the bid is invalid
+                            late.push(Err(Event::Bid(bid)));
+                            continue;
+                        }
                         let entry = state.entry(bid.auction).or_insert((None, Vec::new()));
                         if let Some(ref auction) = entry.0 {
                             debug_assert!(entry.1.len() <= 1);
@@ -51,23 +61,30 @@ pub fn q4_q6_common<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: Nexmark
                                 }
                             }
                         } else {
-                            opens.push((Reverse(bid.date_time), bid.auction));
-                            if capability.as_ref().map(|c| nt.to_nexmark_time(*c.time()) <= bid.date_time) != Some(true) {
-                                capability = Some(time.delayed(&nt.from_nexmark_time(bid.date_time)));
+                            opens.push((Reverse(retain_until), bid.auction));
+                            if capability.as_ref().map(|c| nt.to_nexmark_time(*c.time()) <= retain_until) != Some(true) {
+                                capability = Some(time.delayed(&nt.from_nexmark_time(retain_until)));
                             }
                             entry.1.push(bid);
                         }
                     }
+                    output.session(&time).give_iterator(late.drain(..));
                 });
 
                 // Record each auction.
                 input2.for_each(|time, data| {
+                    let mut late = Vec::new();
                     for auction in data.iter().cloned() {
 //                                        eprintln!("[{:?}] auction: {:?}", time.time().inner, auction);
-                        if capability.as_ref().map(|c| nt.to_nexmark_time(*c.time()) <= auction.expires) != Some(true) {
-                            capability = Some(time.delayed(&nt.from_nexmark_time(auction.expires)));
+                        let close = auction.expires + allowed;
+                        if nt.from_nexmark_time(close) < *time.time() {
+                            late.push(Err(Event::Auction(auction)));
+                            continue;
+                        }
+                        if capability.as_ref().map(|c| nt.to_nexmark_time(*c.time()) <= close) != Some(true) {
+                            capability = Some(time.delayed(&nt.from_nexmark_time(close)));
                         }
-                        opens.push((Reverse(auction.expires), auction.id));
+                        opens.push((Reverse(close), auction.id));
                         let mut entry = state.entry(auction.id).or_insert((None, Vec::new()));
                         debug_assert!(entry.0.is_none());
                         entry.1.retain(|bid| is_valid_bid(&bid, &auction));
@@ -77,6 +94,7 @@ pub fn q4_q6_common<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: Nexmark
                         }
                         entry.0 = Some(auction);
                     }
+                    output.session(&time).give_iterator(late.drain(..));
                 });
 
                 // Use frontiers to determine which auctions to close.
@@ -95,17 +113,17 @@ pub fn q4_q6_common<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: Nexmark
                             let delete = {
                                 let auction_bids = entry.get_mut();
                                 if let Some(ref auction) = auction_bids.0 {
-                                    if time == auction.expires {
+                                    if time == auction.expires + allowed {
                                         // Auction expired, clean up state
                                         if let Some(winner) = auction_bids.1.pop() {
-                                            session.give((auction.clone(), winner));
+                                            session.give(Ok((auction.clone(), winner)));
                                         }
                                         true
                                     } else {
                                         false
                                     }
                                 } else {
-                                    auction_bids.1.retain(|bid| bid.date_time > time);
+                                    auction_bids.1.retain(|bid| bid.date_time + allowed > time);
                                     auction_bids.1.is_empty()
                                 }
                             };
@@ -124,5 +142,8 @@ pub fn q4_q6_common<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: Nexmark
                 }
             }
         }
-    )
+    );
+
+    input.side_output("q4-q6-common", &closed.flat_map(move |closed| closed.err().map(|event| (nt.from_nexmark_time(event.time()), format!("{:?}", event)))));
+    closed.flat_map(|closed| closed.ok())
 }
@@ -0,0 +1,103 @@
+use ::std::cmp::Reverse;
+use ::std::collections::HashMap;
+use ::std::collections::hash_map::Entry;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use dynamic_scaling_mechanism::Bin;
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use ::partition_key;
+use ::event::{Auction, Bid, Date, Event};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+fn is_valid_bid(bid: &Bid, auction: &Auction) -> bool {
+    bid.price >= auction.reserve && auction.date_time <= bid.date_time && bid.date_time < auction.expires
+}
+
+/// Orders bids by price, preferring earlier bids and then lower bidders on ties, such that the
+/// winner does not depend on the order in which bids arrive.
+fn rank(bid: &Bid) -> (usize, Reverse<Date>, Reverse<usize>) {
+    (bid.price, Reverse(bid.date_time), Reverse(bid.bidder))
+}
+
+pub fn q4_q6_common_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S) -> Stream<S, (Auction, Bid)>
+{
+    let control = input.control(scope);
+    let ordered = input.ordered_keys();
+    // Auctions close once their expiry is the allowed lateness behind the frontier. Bids and
+    // auctions arriving later are late, and produced as errors.
+    let allowed = Date::new(input.config.lateness.allowed_ns());
+
+    // Bids are tagged with whether they are to be recorded or, for bids still waiting for their
+    // auction, forgotten.
+    let bids = input.bids(scope).map(|b| (b, false));
+    let auctions = input.auctions(scope);
+
+    let closed = bids.stateful_binary(&control, input.stateful_config.clone(), &auctions, move |&(ref b, _): &(Bid, bool)| partition_key(&b.auction, ordered), move |a: &Auction| partition_key(&a.id, ordered), "Q4 Auction close",
+        // Bins of the first input store the bids of each auction, only the best valid one once the
+        // auction arrived. Bins of the second input store open auctions.
+        move |cap, data, bids: &mut Bin<_, HashMap<usize, Vec<Bid>>, _>, auctions: &mut Bin<_, HashMap<usize, Auction>, _>, output| {
+            let mut session = output.session(&cap);
+            for (time, (bid, forget)) in data.drain(..) {
+                if let Some(auction) = auctions.state().get(&bid.auction) {
+                    if !forget && is_valid_bid(&bid, auction) {
+                        let best = bids.state().entry(bid.auction).or_insert_with(Vec::new);
+                        if best.get(0).map_or(true, |existing| rank(existing) < rank(&bid)) {
+                            best.clear();
+                            best.push(bid);
+                        }
+                    }
+                    continue;
+                }
+                let retain_until = nt.from_nexmark_time(bid.date_time + allowed);
+                if forget {
+                    // The auction did not arrive in time
+                    if let Entry::Occupied(mut pending) = bids.state().entry(bid.auction) {
+                        pending.get_mut().retain(|pending| *pending != bid);
+                        if pending.get().is_empty() {
+                            pending.remove();
+                        }
+                    }
+                } else if retain_until < time {
+                    // The bid's auction closed already, or the bid is invalid
+                    session.give(Err(Event::Bid(bid)));
+                } else {
+                    bids.notificator().notify_at_data(cap, retain_until, (bid.clone(), true));
+                    bids.state().entry(bid.auction).or_insert_with(Vec::new).push(bid);
+                }
+            }
+        },
+        move |cap, data, bids, auctions, output| {
+            let mut session = output.session(&cap);
+            for (time, auction) in data.drain(..) {
+                let close = nt.from_nexmark_time(auction.expires + allowed);
+                match auctions.state().entry(auction.id) {
+                    Entry::Vacant(entry) => {
+                        if close < time {
+                            session.give(Err(Event::Auction(auction)));
+                            continue;
+                        }
+                        // Keep the best valid bid that arrived before the auction
+                        if let Some(pending) = bids.state().get_mut(&auction.id) {
+                            let best = pending.drain(..).filter(|bid| is_valid_bid(bid, &auction)).max_by_key(rank);
+                            pending.extend(best);
+                        }
+                        // The auction is delivered again at its close
+                        auctions.notificator().notify_at_data(cap, close, auction.clone());
+                        entry.insert(auction);
+                    },
+                    Entry::Occupied(entry) => {
+                        let auction = entry.remove();
+                        if let Some(winner) = bids.state().remove(&auction.id).and_then(|mut best| best.pop()) {
+                            session.give(Ok((auction, winner)));
+                        }
+                    },
+                }
+            }
+        });
+
+    input.side_output("q4-q6-common-flex", &closed.flat_map(move |closed| closed.err().map(|event| (nt.from_nexmark_time(event.time()), format!("{:?}", event)))));
+    closed.flat_map(|closed| closed.ok())
+}
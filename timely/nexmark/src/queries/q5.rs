@@ -1,124 +1,70 @@
-
 use ::std::collections::HashMap;
 use ::timely::dataflow::{Scope, Stream};
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::channels::pact::{Exchange, ParallelizationContract, Pipeline};
 use timely::dataflow::operators::{Map, Operator, CapabilitySet};
 
-use ::event::Date;
-
 use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Count, Windowed, WindowSpec};
 
 pub fn q5<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_slice_count: usize, window_slide_ns: usize) -> Stream<S, usize>
 {
-    input.bids(scope)
-        .map(move |b| (b.auction, Date::new(((*b.date_time / window_slide_ns) + 1) * window_slide_ns)))
-        // TODO: Could pre-aggregate pre-exchange, if there was reason to do so.
-        .unary_frontier(Exchange::new(|b: &(usize, _)| b.0 as u64), "Q5 Accumulate",
-                        |capability, _info| {
-                            let mut cap_set = CapabilitySet::new();
-                            cap_set.insert(capability);
+    let slide = window_slide_ns / nt.time_dilation;
+    let spec = WindowSpec::Sliding { size: window_slice_count * slide, slide };
+    let bids = input.bids(scope)
+        .map(move |b| (b.auction, nt.from_nexmark_time(b.date_time), ()));
+
+    let counts = input.windowed("q5", nt, |late| bids.window::<Count>(spec, late, "Q5 Accumulate"))
+        .map(|(auction, _window, count)| (auction, count));
+    let local = max_count(&counts, Pipeline, "Q5 Local Accumulate");
+    max_count(&local, Exchange::new(|_| 0), "Q5 Final Accumulate")
+        .map(|(auction, _count)| auction)
+}
+
+/// Reduces `(auction, count)` pairs to the pair with the largest count per time.
+pub fn max_count<S, P>(stream: &Stream<S, (usize, usize)>, pact: P, name: &str) -> Stream<S, (usize, usize)>
+    where
+        S: Scope<Timestamp=usize>,
+        P: ParallelizationContract<usize, (usize, usize)>,
+{
+    stream.unary_frontier(pact, name, |capability, _info| {
+        let mut cap_set = CapabilitySet::new();
+        cap_set.insert(capability);
+
+        let mut acc = HashMap::new();
 
-                            let mut additions = HashMap::new();
-                            let mut deletions = HashMap::new();
-                            let mut accumulations = HashMap::new();
+        let mut in_buffer = vec![];
 
-                            let mut bids_buffer = vec![];
+        move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut in_buffer);
 
-                            move |input, output| {
-                                input.for_each(|_time, data| {
-                                    data.swap(&mut bids_buffer);
+                for (auction, count) in in_buffer.drain(..) {
+                    let (max_id, max_count) = acc
+                        .entry(*time)
+                        .or_insert_with(|| (auction, count));
 
-                                    for (auction, a_time) in bids_buffer.drain(..) {
-                                        additions
-                                            .entry(nt.from_nexmark_time(a_time))
-                                            .or_insert_with(Vec::new)
-                                            .push(auction);
-                                    }
-                                });
+                    if (count, auction) > (*max_count, *max_id) {
+                        *max_id = auction;
+                        *max_count = count;
+                    }
+                }
+            });
 
-                                // Extract and order times we can now process.
-                                let mut times = {
-                                    let add_times = additions.keys().filter(|t| !input.frontier.less_equal(t)).cloned();
-                                    let del_times = deletions.keys().filter(|t| !input.frontier.less_equal(t)).cloned();
-                                    add_times.chain(del_times).collect::<Vec<_>>()
-                                };
-                                times.sort();
-                                times.dedup();
+            let mut times = acc
+                .keys()
+                .filter(|t| !input.frontier.less_equal(t))
+                .cloned()
+                .collect::<Vec<_>>();
+            times.sort();
+            times.dedup();
 
-                                for time in times.drain(..) {
-                                    if let Some(additions) = additions.remove(&time) {
-                                        for &auction in additions.iter() {
-                                            *accumulations.entry(auction).or_insert(0) += 1;
-                                        }
-                                        let new_time = time + (window_slice_count * window_slide_ns);
-                                        deletions.insert(new_time, additions);
-                                    }
-                                    if let Some(deletions) = deletions.remove(&time) {
-                                        for auction in deletions.into_iter() {
-                                            use std::collections::hash_map::Entry;
-                                            match accumulations.entry(auction) {
-                                                Entry::Occupied(mut entry) => {
-                                                    *entry.get_mut() -= 1;
-                                                    if *entry.get_mut() == 0 {
-                                                        entry.remove();
-                                                    }
-                                                },
-                                                _ => panic!("entry has to exist"),
-                                            }
-                                        }
-                                    }
-                                    let time = cap_set.delayed(&time);
-                                    if let Some((count, auction)) = accumulations.iter().map(|(&a, &c)| (c, a)).max() {
-                                        output.session(&time).give((auction, count));
-                                    }
-                                }
-                                cap_set.downgrade(&input.frontier.frontier());
-                            }
-                        })
-                        .unary_frontier(
-                            Exchange::new(|_| 0),
-                            "Q5 Final Accumulate",
-                            |capability, _info| {
-                                let mut cap_set = CapabilitySet::new();
-                                cap_set.insert(capability);
-                
-                                let mut acc = HashMap::new();
-                
-                                let mut in_buffer = vec![];
-                
-                                move |input, output| {
-                                    input.for_each(|time, data| {
-                                        data.swap(&mut in_buffer);
-                
-                                        for (auction, count) in in_buffer.drain(..) {
-                                            let (max_id, max_count) = acc
-                                                .entry(*time)
-                                                .or_insert_with(|| (auction, count));
-                
-                                            if count > *max_count {
-                                                *max_id = auction;
-                                                *max_count = count;
-                                            }
-                                        }
-                                    });
-                
-                                    let mut times = acc
-                                        .keys()
-                                        .filter(|t| !input.frontier.less_equal(t))
-                                        .cloned()
-                                        .collect::<Vec<_>>();
-                                    times.sort();
-                                    times.dedup();
-                
-                                    for time in times.drain(..) {
-                                        let time = cap_set.delayed(&time);
-                                        if let Some((auction, _count)) = acc.remove(&time)
-                                        {
-                                            output.session(&time).give(auction);
-                                        }
-                                    }
-                                    cap_set.downgrade(&input.frontier.frontier());
-                                }
-                            },
-                        )
+            for time in times.drain(..) {
+                let time = cap_set.delayed(&time);
+                if let Some(max) = acc.remove(time.time()) {
+                    output.session(&time).give(max);
+                }
+            }
+            cap_set.downgrade(&input.frontier.frontier());
+        }
+    })
 }
@@ -0,0 +1,24 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q5::max_count;
+use window::{Count, Windowed, WindowSpec};
+
+pub fn q5_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_slice_count: usize, window_slide_ns: usize) -> Stream<S, usize>
+{
+    let control = input.control(scope);
+
+    let slide = window_slide_ns / nt.time_dilation;
+    let spec = WindowSpec::Sliding { size: window_slice_count * slide, slide };
+    let bids = input.bids(scope)
+        .map(move |b| (b.auction, nt.from_nexmark_time(b.date_time), ()));
+
+    let counts = input.windowed("q5-flex", nt, |late| bids.window_flex::<Count, _>(spec, late, &control, input.stateful_config.clone(), "Q5 Accumulate"))
+        .map(|(auction, _window, count)| (auction, count));
+    // The maximum per time is small, so it is reduced by a single worker without migration.
+    let local = max_count(&counts, Pipeline, "Q5 Local Accumulate");
+    max_count(&local, Exchange::new(|_| 0), "Q5 Final Accumulate")
+        .map(|(auction, _count)| auction)
+}
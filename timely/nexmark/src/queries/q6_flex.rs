@@ -0,0 +1,33 @@
+use ::std::collections::HashMap;
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use dynamic_scaling_mechanism::Bin;
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use ::partition_key;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+pub fn q6_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (usize, f32)>
+{
+    let control = input.control(scope);
+    let ordered = input.ordered_keys();
+
+    input.closed_auctions_flex(scope)
+        .map(|(_a, b)| (b.bidder, b.price))
+        .stateful_unary(&control, input.stateful_config.clone(), move |x: &(usize, usize)| partition_key(&x.0, ordered), "Q6 Average",
+                        // Stores bidder -> [prices; 10]
+                        |cap, data, bin: &mut Bin<_, HashMap<usize, Vec<usize>>, _>, output| {
+                            // Records of a time arrive in any order, which must not affect the averages
+                            data.sort();
+                            let mut session = output.session(&cap);
+                            for (_time, (bidder, price)) in data.drain(..) {
+                                let entry = bin.state().entry(bidder).or_insert_with(Vec::new);
+                                entry.truncate(9);
+                                entry.insert(0, price);
+                                let sum: usize = entry.iter().sum();
+                                session.give((bidder, sum as f32 / entry.len() as f32));
+                            }
+                        })
+}
@@ -0,0 +1,23 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Map;
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use window::{Max, Windowed, WindowSpec};
+
+pub fn q7_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, usize>
+{
+    let control = input.control(scope);
+
+    let spec = WindowSpec::Tumbling(window_size_ns / nt.time_dilation);
+    let bids = input.bids(scope)
+        .map(move |b| ((), nt.from_nexmark_time(b.date_time), b.price));
+
+    // Tracks the worker-local maximal bid for each window.
+    input.windowed("q7-flex", nt, |late| bids.local_window::<Max<usize>>(spec, late, "Q7 Pre-reduce"))
+        // Tracks the global maximal bid for each window in migratable bins keyed by window.
+        // Partial results arrive within their window's allowed lateness, so none of them is late.
+        .flat_map(|((), window, price)| price.map(|price| ((window.end, window.start), window.start, price)))
+        .window_flex::<Max<usize>, _>(spec, input.late_policy(nt), &control, input.stateful_config.clone(), "Q7 All-reduce")
+        .results
+        .flat_map(|(_window, _start, price)| price)
+}
@@ -0,0 +1,23 @@
+use ::timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::{Concat, Map};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+use queries::q8::NewSeller;
+use window::{Windowed, WindowSpec};
+
+pub fn q8_flex<S: Scope<Timestamp=usize>>(input: &NexmarkInput, nt: NexmarkTimer, scope: &mut S, window_size_ns: usize) -> Stream<S, usize>
+{
+    let control = input.control(scope);
+
+    let auctions = input.auctions(scope)
+        .map(move |a| (a.seller, nt.from_nexmark_time(a.date_time), false));
+
+    let people = input.people(scope)
+        .map(move |p| (p.id, nt.from_nexmark_time(p.date_time), true));
+
+    let spec = WindowSpec::Tumbling(window_size_ns / nt.time_dilation);
+    let records = people.concat(&auctions);
+
+    input.windowed("q8-flex", nt, |late| records.window_flex::<NewSeller, _>(spec, late, &control, input.stateful_config.clone(), "Q8 join"))
+        .flat_map(|(person, _window, new_seller)| if new_seller { Some(person) } else { None })
+}
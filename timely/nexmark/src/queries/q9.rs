@@ -0,0 +1,11 @@
+use ::timely::dataflow::{Scope, Stream};
+
+use ::event::{Auction, Bid};
+
+use {queries::NexmarkInput, queries::NexmarkTimer};
+
+pub fn q9<S: Scope<Timestamp=usize>>(input: &NexmarkInput, _nt: NexmarkTimer, scope: &mut S) -> Stream<S, (Auction, Bid)>
+{
+    // Closing auctions determines their winning bids.
+    input.closed_auctions(scope)
+}
@@ -0,0 +1,84 @@
+//! The events each worker feeds into its input, and the streams of bids, auctions and people the
+//! queries read from them.
+
+use std::path::PathBuf;
+
+use timely::dataflow::{Scope, Stream};
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
+
+use config::NEXMarkConfig;
+use event::{Auction, Bid, Event, Person};
+use event_log::{partition_path, EventLogReader, Format};
+use tcp::TcpSource;
+use EventGenerator;
+
+/// Where the workers' events come from.
+#[derive(Clone)]
+pub enum EventSource {
+    /// Generate events, where worker `index` generates the events of the single generator's
+    /// events numbered `index` modulo the number of workers.
+    Generator(NEXMarkConfig),
+    /// Replay an event log written by `nexmark-log` with as many partitions as workers, moving
+    /// event times from the log's base time to `base_time_ns`.
+    Log { dir: PathBuf, format: Format, base_time_ns: usize },
+    /// Receive events from a `nexmark-producer`, with one connection per worker, moving event
+    /// times from the producer's base time to `base_time_ns`.
+    Tcp { addr: String, base_time_ns: usize },
+}
+
+impl EventSource {
+    /// The events of worker `index` out of `peers`, in the order the worker sends them.
+    pub fn worker_events(&self, index: usize, peers: usize) -> Box<dyn Iterator<Item=Event>> {
+        match *self {
+            EventSource::Generator(ref config) => Box::new(EventGenerator::new(config.clone(), index, peers)),
+            EventSource::Log { ref dir, format, base_time_ns } => {
+                let path = partition_path(dir, index, peers, format);
+                let reader = EventLogReader::open(&path, format).unwrap_or_else(|e| panic!("couldn't open event log {}: {}", path.display(), e));
+                Box::new(reader.rebased(base_time_ns))
+            }
+            EventSource::Tcp { ref addr, base_time_ns } => {
+                let source = TcpSource::connect(addr.as_str(), index, peers).unwrap_or_else(|e| panic!("couldn't connect to producer {}: {}", addr, e));
+                Box::new(source.rebased(base_time_ns))
+            }
+        }
+    }
+}
+
+/// Split events into bids, auctions and people.
+pub fn demux<S: Scope>(events: &Stream<S, Event>) -> (Stream<S, Bid>, Stream<S, Auction>, Stream<S, Person>) {
+    let mut demux = OperatorBuilder::new("NEXMark demux".to_string(), events.scope());
+
+    let mut input = demux.new_input(events, Pipeline);
+
+    let (mut b_out, bids_stream) = demux.new_output();
+    let (mut a_out, auctions_stream) = demux.new_output();
+    let (mut p_out, people_stream) = demux.new_output();
+
+    let mut demux_buffer = Vec::new();
+
+    demux.build(move |_capability| {
+        move |_frontiers| {
+            let mut b_out = b_out.activate();
+            let mut a_out = a_out.activate();
+            let mut p_out = p_out.activate();
+
+            input.for_each(|time, data| {
+                data.swap(&mut demux_buffer);
+                let mut b_session = b_out.session(&time);
+                let mut a_session = a_out.session(&time);
+                let mut p_session = p_out.session(&time);
+
+                for datum in demux_buffer.drain(..) {
+                    match datum {
+                        Event::Bid(b) => { b_session.give(b) },
+                        Event::Auction(a) => { a_session.give(a) },
+                        Event::Person(p) => { p_session.give(p) },
+                    }
+                }
+            });
+        }
+    });
+
+    (bids_stream, auctions_stream, people_stream)
+}
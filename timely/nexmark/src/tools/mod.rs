@@ -0,0 +1,316 @@
+use streaming_harness::util::ToNanos;
+use dynamic_scaling_mechanism::{BinId, ControlInst};
+
+pub mod patterns;
+pub mod plan;
+
+use self::patterns::PatternPlan;
+use self::plan::MigrationPlan;
+
+#[derive(Clone, Debug)]
+pub enum ParseError {
+    InvalidScalePlan(String),
+    InvalidPatternPlan(String),
+}
+
+/// How bins are moved from the initial to the final set of workers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleStrategy {
+    /// Install the final map at once.
+    Sudden,
+    /// Move one bin per configuration.
+    Fluid,
+    /// Move bins in batches where each worker sends and receives at most one bin.
+    Batched,
+}
+
+/// Migrate from `from` active workers to `to` active workers. Workers not active hold no bins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScalePlan {
+    pub from: usize,
+    pub to: usize,
+    pub strategy: ScaleStrategy,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExperimentMapMode {
+    None,
+    Sudden,
+    //    OneByOne,
+    Fluid,
+    ScaleOut(ScalePlan),
+    ScaleIn(ScalePlan),
+    Pattern(PatternPlan),
+    File(String),
+}
+
+impl ScalePlan {
+    /// Parses `<strategy>:<from>:<to>`, for example `fluid:2:4`.
+    fn parse(s: &str) -> Result<ScalePlan, ParseError> {
+        let error = || ParseError::InvalidScalePlan(s.to_string());
+        let parts: Vec<_> = s.split(':').collect();
+        if parts.len() != 3 {
+            return Err(error());
+        }
+        let strategy = match parts[0] {
+            "sudden" => ScaleStrategy::Sudden,
+            "fluid" => ScaleStrategy::Fluid,
+            "batched" => ScaleStrategy::Batched,
+            _ => return Err(error()),
+        };
+        let from = parts[1].parse().map_err(|_| error())?;
+        let to = parts[2].parse().map_err(|_| error())?;
+        if from == 0 || to == 0 {
+            return Err(error());
+        }
+        Ok(ScalePlan { from, to, strategy })
+    }
+
+    /// The map assigning bins round-robin to the first `workers` workers.
+    fn map(workers: usize, bin_shift: usize) -> Vec<usize> {
+        (0..1 << bin_shift).map(|bin| bin % workers).collect()
+    }
+
+    /// Instructions to install the initial map at `duration_ns/3` and to migrate to the final map
+    /// at `2*duration_ns/3`.
+    pub fn instructions(&self, peers: usize, duration_ns: u64, bin_shift: usize) -> Result<Vec<(u64, Vec<ControlInst>)>, String> {
+        if self.from > peers || self.to > peers {
+            return Err(format!("cannot scale from {} to {} workers with only {} peers", self.from, self.to, peers));
+        }
+        let initial_map = Self::map(self.from, bin_shift);
+        let final_map = Self::map(self.to, bin_shift);
+        let mut configurations = vec![(duration_ns / 3, vec![ControlInst::Map(initial_map.clone())])];
+        let moves: Vec<_> = initial_map.iter().zip(final_map.iter()).enumerate()
+            .filter(|&(_, (src, dst))| src != dst)
+            .map(|(bin, (src, dst))| (bin, *src, *dst))
+            .collect();
+        match self.strategy {
+            ScaleStrategy::Sudden => configurations.push((2 * duration_ns / 3, vec![ControlInst::Map(final_map)])),
+            ScaleStrategy::Fluid => {
+                for (bin, _src, dst) in moves {
+                    configurations.push((2 * duration_ns / 3, vec![ControlInst::Move(BinId::new(bin), dst)]));
+                }
+            },
+            ScaleStrategy::Batched => {
+                for batch in patterns::matched_steps(moves) {
+                    let batch = batch.into_iter().map(|(bin, _src, dst)| ControlInst::Move(BinId::new(bin), dst)).collect();
+                    configurations.push((2 * duration_ns / 3, batch));
+                }
+            },
+        }
+        Ok(configurations)
+    }
+}
+
+impl ::std::str::FromStr for ExperimentMapMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<ExperimentMapMode, Self::Err> {
+        let map_mode = match s {
+            "none" => ExperimentMapMode::None,
+            "sudden" => ExperimentMapMode::Sudden,
+//            "one-by-one" => ExperimentMapMode::OneByOne,
+            "fluid" => ExperimentMapMode::Fluid,
+            scale if scale.starts_with("scale-out:") => {
+                let plan = ScalePlan::parse(&scale["scale-out:".len()..])?;
+                if plan.from >= plan.to {
+                    return Err(ParseError::InvalidScalePlan(scale.to_string()));
+                }
+                ExperimentMapMode::ScaleOut(plan)
+            },
+            pattern if pattern.starts_with("pattern:") => {
+                let plan = PatternPlan::parse(&pattern["pattern:".len()..]);
+                ExperimentMapMode::Pattern(plan.ok_or_else(|| ParseError::InvalidPatternPlan(pattern.to_string()))?)
+            },
+            scale if scale.starts_with("scale-in:") => {
+                let plan = ScalePlan::parse(&scale["scale-in:".len()..])?;
+                if plan.from <= plan.to {
+                    return Err(ParseError::InvalidScalePlan(scale.to_string()));
+                }
+                ExperimentMapMode::ScaleIn(plan)
+            },
+            file_name => ExperimentMapMode::File(file_name.to_string()),
+        };
+        Ok(map_mode)
+    }
+}
+
+impl ExperimentMapMode {
+    /// Install the `half` map at `duration_ns/3` and migrate to the `uniform` target map with
+    /// `strategy` at `2*duration_ns/3`.
+    fn half_to_uniform(strategy: patterns::MigrationStrategy, peers: usize, duration_ns: u64, bin_shift: usize) -> Vec<(u64, Vec<ControlInst>)> {
+        let initial_map = patterns::InitialPattern::Half.generate(bin_shift, peers);
+        let target_map = patterns::InitialPattern::Uniform.generate(bin_shift, peers);
+        let mut configurations = vec![(duration_ns / 3, vec![ControlInst::Map(initial_map.clone())])];
+        configurations.extend(strategy.steps(&initial_map, &target_map).into_iter().map(|step| (2 * duration_ns / 3, step)));
+        configurations
+    }
+
+    pub fn instructions(&self, peers: usize, duration_ns: u64, bin_shift: usize) -> Result<Vec<(u64, Vec<ControlInst>)>, String> {
+        match self {
+            ExperimentMapMode::None => {
+                let mut map = vec![0; 1 << bin_shift];
+                for (i, element) in map.iter_mut().enumerate() {
+                    *element = i % peers;
+                };
+                Ok(vec![(0, vec![ControlInst::Map(map)])])
+            }
+            ExperimentMapMode::Sudden => Ok(Self::half_to_uniform(patterns::MigrationStrategy::Sudden, peers, duration_ns, bin_shift)),
+            ExperimentMapMode::Fluid => Ok(Self::half_to_uniform(patterns::MigrationStrategy::Fluid, peers, duration_ns, bin_shift)),
+            ExperimentMapMode::ScaleOut(plan) | ExperimentMapMode::ScaleIn(plan) => plan.instructions(peers, duration_ns, bin_shift),
+            ExperimentMapMode::Pattern(plan) => plan.instructions(peers, duration_ns, bin_shift),
+            ExperimentMapMode::File(migrations_file) => {
+                let plan = MigrationPlan::read(migrations_file).map_err(|e| e.to_string())?;
+                plan.validate(peers, bin_shift).map_err(|e| format!("{}: {}", migrations_file, e))?;
+                Ok(plan.instructions())
+            },
+//            _ => panic!("unsupported map mode"),
+        }
+    }
+}
+
+/// The `validate-plan` subcommand, checking a migration plan file before a run.
+pub fn validate_plan_subcommand<'a, 'b>() -> ::clap::App<'a, 'b> {
+    ::clap::SubCommand::with_name("validate-plan")
+        .about("Checks a migration plan file against the number of peers and bins")
+        .arg(::clap::Arg::with_name("plan").required(true))
+        .arg(::clap::Arg::with_name("peers").long("peers").takes_value(true).required(true))
+        .arg(::clap::Arg::with_name("bin_shift").long("bin_shift").takes_value(true))
+}
+
+/// Runs the `validate-plan` subcommand, returning the process exit code.
+pub fn validate_plan(matches: &::clap::ArgMatches) -> i32 {
+    let file = matches.value_of("plan").expect("plan absent");
+    let peers: usize = matches.value_of("peers").expect("peers absent").parse().expect("couldn't parse peers");
+    let bin_shift = matches.value_of("bin_shift").map_or(::dynamic_scaling_mechanism::DEFAULT_BIN_SHIFT, |arg| arg.parse().expect("couldn't parse bin_shift"));
+    match MigrationPlan::read(file).and_then(|plan| plan.validate(peers, bin_shift).map(|()| plan)) {
+        Ok(plan) => {
+            println!("{}: {} configurations, valid for {} peers and {} bins", file, plan.steps.len(), peers, 1 << bin_shift);
+            0
+        },
+        Err(error) => {
+            eprintln!("{}: {}", file, error);
+            1
+        },
+    }
+}
+
+pub fn statm_reporter() -> ::std::sync::Arc<::std::sync::atomic::AtomicBool> {
+
+    // Read and report RSS every 100ms
+    let statm_reporter_running = ::std::sync::Arc::new(::std::sync::atomic::AtomicBool::new(true));
+    {
+        let statm_reporter_running = statm_reporter_running.clone();
+        ::std::thread::spawn(move || {
+            use std::io::Read;
+            let timer = ::std::time::Instant::now();
+            let mut iteration = 0;
+            while statm_reporter_running.load(::std::sync::atomic::Ordering::SeqCst) {
+                let mut stat_s = String::new();
+                let mut statm_f = ::std::fs::File::open("/proc/self/statm").expect("can't open /proc/self/statm");
+                statm_f.read_to_string(&mut stat_s).expect("can't read /proc/self/statm");
+                let pages: u64 = stat_s.split_whitespace().nth(1).expect("wooo").parse().expect("not a number");
+                let rss = pages * 4096;
+
+                let elapsed_ns = timer.elapsed().to_nanos();
+                println!("statm_RSS\t{}\t{}", elapsed_ns, rss);
+                #[allow(deprecated)]
+                    ::std::thread::sleep_ms(500 - (elapsed_ns / 1_000_000 - iteration * 500) as u32);
+                iteration += 1;
+            }
+        });
+    }
+    statm_reporter_running
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn final_map(instructions: &[(u64, Vec<ControlInst>)]) -> Vec<usize> {
+        let mut map = Vec::new();
+        for (_, insts) in instructions {
+            for inst in insts {
+                match inst {
+                    ControlInst::Map(new_map) => map = new_map.clone(),
+                    ControlInst::Move(bin, target) => map[**bin] = *target,
+                    ControlInst::Checkpoint | ControlInst::None => {},
+                }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn test_sudden_and_fluid() {
+        for mode in &[ExperimentMapMode::Sudden, ExperimentMapMode::Fluid] {
+            let instructions = mode.instructions(4, 3, 4).unwrap();
+            assert_eq!(final_map(&instructions[..1]), patterns::InitialPattern::Half.generate(4, 4));
+            assert_eq!(final_map(&instructions), (0..16).map(|bin| bin % 4).collect::<Vec<_>>());
+        }
+        // Fluid only moves bins whose owner changes
+        let instructions = ExperimentMapMode::Fluid.instructions(4, 3, 4).unwrap();
+        assert_eq!(instructions.len(), 1 + 8);
+    }
+
+    #[test]
+    fn test_scale_plans() {
+        assert!("scale-out:fluid:4:2".parse::<ExperimentMapMode>().is_err());
+        assert!("scale-in:sudden:2:4".parse::<ExperimentMapMode>().is_err());
+        assert!("scale-out:other:2:4".parse::<ExperimentMapMode>().is_err());
+        for strategy in &["sudden", "fluid", "batched"] {
+            let mode: ExperimentMapMode = format!("scale-out:{}:2:3", strategy).parse().unwrap();
+            let instructions = mode.instructions(4, 3, 4).unwrap();
+            assert_eq!(final_map(&instructions), (0..16).map(|bin| bin % 3).collect::<Vec<_>>());
+            let mode: ExperimentMapMode = format!("scale-in:{}:3:1", strategy).parse().unwrap();
+            let instructions = mode.instructions(4, 3, 4).unwrap();
+            assert_eq!(final_map(&instructions), vec![0; 16]);
+        }
+        let mode: ExperimentMapMode = "scale-out:sudden:2:8".parse().unwrap();
+        assert!(mode.instructions(4, 3, 4).is_err());
+    }
+
+    #[test]
+    fn test_pattern_plans() {
+        assert!("pattern:other:uniform:half".parse::<ExperimentMapMode>().is_err());
+        assert!("pattern:batched-0:uniform:half".parse::<ExperimentMapMode>().is_err());
+        let initial = patterns::InitialPattern::Uniform.generate(4, 4);
+        for strategy in &["sudden", "fluid", "optimized", "batched-3"] {
+            let mode: ExperimentMapMode = format!("pattern:{}:uniform:uniform_skew:10", strategy).parse().unwrap();
+            let instructions = mode.instructions(4, 3_000_000_000, 4).unwrap();
+            assert!(instructions.windows(2).all(|w| w[0].0 <= w[1].0));
+            assert_eq!(final_map(&instructions), initial);
+        }
+        // Eight fluid steps 200ms apart span 1.4s, more than a third of 3s
+        let mode: ExperimentMapMode = "pattern:fluid:uniform:half:200".parse().unwrap();
+        assert!(mode.instructions(4, 3_000_000_000, 4).is_err());
+        assert!(mode.instructions(4, 6_000_000_000, 4).is_ok());
+    }
+
+    #[test]
+    fn test_matched_steps() {
+        // Worker 0 sends three bins, worker 1 one bin: at least three steps are needed
+        let steps = patterns::matched_steps(vec![(0, 0, 1), (1, 0, 2), (2, 0, 3), (3, 1, 2)]);
+        assert_eq!(steps.len(), 3);
+        for step in &steps {
+            let mut sources: Vec<_> = step.iter().map(|m| m.1).collect();
+            let mut targets: Vec<_> = step.iter().map(|m| m.2).collect();
+            sources.sort();
+            sources.dedup();
+            targets.sort();
+            targets.dedup();
+            assert_eq!(sources.len(), step.len());
+            assert_eq!(targets.len(), step.len());
+        }
+        assert_eq!(steps.iter().map(|s| s.len()).sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_scale_batched() {
+        let plan = ScalePlan { from: 1, to: 4, strategy: ScaleStrategy::Batched };
+        let instructions = plan.instructions(4, 3, 4).unwrap();
+        // The single source sends one bin per batch
+        assert!(instructions[1..].iter().all(|(_, batch)| batch.len() == 1));
+        assert_eq!(instructions.len(), 1 + 12);
+    }
+}
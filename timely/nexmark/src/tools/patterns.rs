@@ -0,0 +1,226 @@
+//! Generators for bin-to-worker maps and migrations between them.
+//!
+//! This is a port of the experiment scripts' `patterns.py`, allowing the benchmarks to generate
+//! migration plans without an external script.
+
+use dynamic_scaling_mechanism::{BinId, ControlInst};
+
+/// A map assigning bins to workers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitialPattern {
+    /// Assign bins round-robin to workers.
+    Uniform,
+    /// `Half` for the first half of the bins, `Uniform` for the second half.
+    UniformSkew,
+    /// Assign bin pairs to a worker and the worker `workers/2` later.
+    Half,
+}
+
+impl InitialPattern {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "uniform" => Some(InitialPattern::Uniform),
+            "uniform_skew" => Some(InitialPattern::UniformSkew),
+            "half" => Some(InitialPattern::Half),
+            _ => None,
+        }
+    }
+
+    /// Generate a map of `1 << bin_shift` bins to `workers` workers.
+    pub fn generate(&self, bin_shift: usize, workers: usize) -> Vec<usize> {
+        let bins = 1 << bin_shift;
+        match self {
+            InitialPattern::Uniform => (0..bins).map(|i| i % workers).collect(),
+            InitialPattern::UniformSkew => {
+                let mut map = InitialPattern::Half.generate(bin_shift, workers);
+                map.truncate(bins / 2);
+                map.extend((bins / 2..bins).map(|i| i % workers));
+                map
+            },
+            InitialPattern::Half => (0..bins).map(|i| ((i / 2) * 2 + (i % 2) * workers / 2) % workers).collect(),
+        }
+    }
+}
+
+/// A strategy to migrate from one map to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationStrategy {
+    /// Install the target map in one step.
+    Sudden,
+    /// Move one bin per step.
+    Fluid,
+    /// Move as many bins per step as possible such that each worker sends and receives at most
+    /// one bin, minimizing the number of steps.
+    Optimized,
+    /// Move up to the given number of bins per step.
+    Batched(usize),
+}
+
+impl MigrationStrategy {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "sudden" => Some(MigrationStrategy::Sudden),
+            "fluid" => Some(MigrationStrategy::Fluid),
+            "optimized" => Some(MigrationStrategy::Optimized),
+            batched if batched.starts_with("batched-") => {
+                batched["batched-".len()..].parse().ok().filter(|n| *n > 0).map(MigrationStrategy::Batched)
+            },
+            _ => None,
+        }
+    }
+
+    /// The steps to migrate from `current` to `target`. Each step forms one configuration.
+    pub fn steps(&self, current: &[usize], target: &[usize]) -> Vec<Vec<ControlInst>> {
+        assert_eq!(current.len(), target.len(), "maps must have the same number of bins");
+        let moves: Vec<_> = current.iter().zip(target.iter()).enumerate()
+            .filter(|&(_, (src, dst))| src != dst)
+            .map(|(bin, (src, dst))| (bin, *src, *dst))
+            .collect();
+        let to_inst = |(bin, _src, dst): (usize, usize, usize)| ControlInst::Move(BinId::new(bin), dst);
+        match self {
+            MigrationStrategy::Sudden => vec![vec![ControlInst::Map(target.to_vec())]],
+            MigrationStrategy::Fluid => moves.into_iter().map(|m| vec![to_inst(m)]).collect(),
+            MigrationStrategy::Optimized => matched_steps(moves).into_iter().map(|step| step.into_iter().map(to_inst).collect()).collect(),
+            MigrationStrategy::Batched(bins) => moves.chunks(*bins).map(|step| step.iter().cloned().map(to_inst).collect()).collect(),
+        }
+    }
+}
+
+/// Split `(bin, src, dst)` moves into steps where each worker sends and receives at most one bin,
+/// using a maximum matching of sources to destinations per step.
+pub fn matched_steps(mut moves: Vec<(usize, usize, usize)>) -> Vec<Vec<(usize, usize, usize)>> {
+    let workers = moves.iter().map(|&(_, src, dst)| ::std::cmp::max(src, dst) + 1).max().unwrap_or(0);
+    let mut steps = Vec::new();
+    while !moves.is_empty() {
+        let mut adjacency = vec![Vec::new(); workers];
+        for &(_, src, dst) in &moves {
+            if !adjacency[src].contains(&dst) {
+                adjacency[src].push(dst);
+            }
+        }
+        let matching = hopcroft_karp(&adjacency, workers);
+        let mut step = Vec::new();
+        for (src, dst) in matching.into_iter().enumerate().filter_map(|(src, dst)| dst.map(|dst| (src, dst))) {
+            let position = moves.iter().position(|&(_, s, d)| s == src && d == dst).expect("matched edge without move");
+            step.push(moves.remove(position));
+        }
+        step.sort();
+        steps.push(step);
+    }
+    steps
+}
+
+/// Compute a maximum matching of a bipartite graph given as adjacency lists from left to right
+/// vertices, with `right` vertices on the right side. Returns the matched right vertex for each
+/// left vertex.
+pub fn hopcroft_karp(adjacency: &[Vec<usize>], right: usize) -> Vec<Option<usize>> {
+    const INFINITY: usize = ::std::usize::MAX;
+
+    let mut pair_left: Vec<Option<usize>> = vec![None; adjacency.len()];
+    let mut pair_right: Vec<Option<usize>> = vec![None; right];
+    let mut distance = vec![INFINITY; adjacency.len()];
+
+    // Layer free left vertices by the length of alternating paths. Returns whether a free right
+    // vertex is reachable.
+    fn bfs(adjacency: &[Vec<usize>], pair_left: &[Option<usize>], pair_right: &[Option<usize>], distance: &mut [usize]) -> bool {
+        let mut queue = ::std::collections::VecDeque::new();
+        for (left, pair) in pair_left.iter().enumerate() {
+            if pair.is_none() {
+                distance[left] = 0;
+                queue.push_back(left);
+            } else {
+                distance[left] = INFINITY;
+            }
+        }
+        let mut found = false;
+        while let Some(left) = queue.pop_front() {
+            for &right in &adjacency[left] {
+                match pair_right[right] {
+                    None => found = true,
+                    Some(next) => if distance[next] == INFINITY {
+                        distance[next] = distance[left] + 1;
+                        queue.push_back(next);
+                    },
+                }
+            }
+        }
+        found
+    }
+
+    // Find an augmenting path from `left` along the layers and flip it.
+    fn dfs(left: usize, adjacency: &[Vec<usize>], pair_left: &mut [Option<usize>], pair_right: &mut [Option<usize>], distance: &mut [usize]) -> bool {
+        for &right in &adjacency[left] {
+            let augment = match pair_right[right] {
+                None => true,
+                Some(next) => distance[next] == distance[left] + 1 && dfs(next, adjacency, pair_left, pair_right, distance),
+            };
+            if augment {
+                pair_left[left] = Some(right);
+                pair_right[right] = Some(left);
+                return true;
+            }
+        }
+        distance[left] = INFINITY;
+        false
+    }
+
+    while bfs(adjacency, &pair_left, &pair_right, &mut distance) {
+        for left in 0..adjacency.len() {
+            if pair_left[left].is_none() {
+                dfs(left, adjacency, &mut pair_left, &mut pair_right, &mut distance);
+            }
+        }
+    }
+    pair_left
+}
+
+/// A migration plan from an initial to a final map and back, as run by the experiment scripts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatternPlan {
+    pub strategy: MigrationStrategy,
+    pub initial: InitialPattern,
+    pub target: InitialPattern,
+    /// Time between consecutive steps of a migration.
+    pub interval_ns: u64,
+}
+
+impl PatternPlan {
+    /// Parses `<strategy>:<initial>:<final>[:<interval_ms>]`, for example
+    /// `batched-16:uniform:uniform_skew:100`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let parts: Vec<_> = s.split(':').collect();
+        if parts.len() != 3 && parts.len() != 4 {
+            return None;
+        }
+        let interval_ns = match parts.get(3) {
+            Some(interval_ms) => interval_ms.parse::<u64>().ok()? * 1_000_000,
+            None => 0,
+        };
+        Some(PatternPlan {
+            strategy: MigrationStrategy::parse(parts[0])?,
+            initial: InitialPattern::parse(parts[1])?,
+            target: InitialPattern::parse(parts[2])?,
+            interval_ns,
+        })
+    }
+
+    /// Install the initial map at time 0, migrate to the final map at `duration_ns/3` and back to
+    /// the initial map at `2*duration_ns/3`. Fails if the steps of a migration, `interval_ns`
+    /// apart, do not complete before the next migration or the end of the run.
+    pub fn instructions(&self, peers: usize, duration_ns: u64, bin_shift: usize) -> Result<Vec<(u64, Vec<ControlInst>)>, String> {
+        let initial = self.initial.generate(bin_shift, peers);
+        let target = self.target.generate(bin_shift, peers);
+        let mut instructions = vec![(0, vec![ControlInst::Map(initial.clone())])];
+        for &(start, end, ref from, ref to) in &[(duration_ns / 3, 2 * duration_ns / 3, &initial, &target), (2 * duration_ns / 3, duration_ns, &target, &initial)] {
+            let steps = self.strategy.steps(from, to);
+            let last = start + steps.len().saturating_sub(1) as u64 * self.interval_ns;
+            if !steps.is_empty() && last >= end {
+                return Err(format!("{} migration steps {}ns apart starting at {}ns do not complete before {}ns", steps.len(), self.interval_ns, start, end));
+            }
+            for (index, step) in steps.into_iter().enumerate() {
+                instructions.push((start + index as u64 * self.interval_ns, step));
+            }
+        }
+        Ok(instructions)
+    }
+}
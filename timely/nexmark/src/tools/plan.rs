@@ -0,0 +1,313 @@
+//! Migration plan files.
+//!
+//! Plans are read either from the legacy whitespace format, one configuration per line,
+//!
+//! ```text
+//! M <time> <worker of bin 0> <worker of bin 1> ...
+//! D <time> <bin> <worker> <bin> <worker> ...
+//! ```
+//!
+//! or from a versioned JSON document:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "bin_shift": 2,
+//!   "peers": 2,
+//!   "steps": [
+//!     { "time": 0, "map": [0, 1, 0, 1] },
+//!     { "time": 1000000000, "moves": [[0, 1], [3, 0]] }
+//!   ]
+//! }
+//! ```
+//!
+//! `bin_shift` and `peers` are optional and checked against the actual run if present.
+
+use std::fmt;
+use std::io::{BufRead, Read};
+
+use dynamic_scaling_mechanism::{BinId, ControlInst};
+
+/// The version of the JSON plan format written by this crate.
+pub const PLAN_VERSION: u32 = 1;
+
+/// A single configuration of a plan: either a full map or a set of moves.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanStep {
+    /// Time in nanoseconds at which to apply the configuration.
+    pub time: u64,
+    /// A worker for each bin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map: Option<Vec<usize>>,
+    /// `(bin, worker)` pairs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moves: Vec<(usize, usize)>,
+    /// Line the step was read from, for error reporting. Steps read from JSON record the line of
+    /// their opening brace.
+    #[serde(skip)]
+    pub line: Option<usize>,
+}
+
+/// A sequence of configurations to apply during an experiment.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationPlan {
+    /// Format version, 0 for the legacy format.
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_shift: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peers: Option<usize>,
+    pub steps: Vec<PlanStep>,
+}
+
+/// An error reading or validating a plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlanError {
+    /// The line of the plan the error refers to, if known.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl PlanError {
+    fn new<S: Into<String>>(line: Option<usize>, message: S) -> Self {
+        PlanError { line, message: message.into() }
+    }
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl ::std::error::Error for PlanError {}
+
+impl MigrationPlan {
+
+    /// Read a plan from a file, in JSON format if the file starts with `{`, else in the legacy
+    /// format.
+    pub fn read(path: &str) -> Result<Self, PlanError> {
+        let mut contents = String::new();
+        ::std::fs::File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| PlanError::new(None, format!("{}: {}", path, e)))?;
+        if contents.trim_start().starts_with('{') {
+            Self::from_json(contents.as_bytes())
+        } else {
+            Self::from_legacy(contents.as_bytes())
+        }
+    }
+
+    /// Parse a plan in the JSON format.
+    pub fn from_json<R: Read>(mut reader: R) -> Result<Self, PlanError> {
+        let mut json = String::new();
+        reader.read_to_string(&mut json).map_err(|e| PlanError::new(None, e.to_string()))?;
+        let mut plan: MigrationPlan = ::serde_json::from_str(&json)
+            .map_err(|e| PlanError::new(Some(e.line()), e.to_string()))?;
+        if plan.version != PLAN_VERSION {
+            return Err(PlanError::new(None, format!("unsupported plan version {}, expected {}", plan.version, PLAN_VERSION)));
+        }
+        for (step, line) in plan.steps.iter_mut().zip(json_step_lines(&json)) {
+            step.line = Some(line);
+        }
+        Ok(plan)
+    }
+
+    /// Parse a plan in the legacy whitespace-separated format.
+    pub fn from_legacy<R: BufRead>(reader: R) -> Result<Self, PlanError> {
+        let mut steps = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line_number = Some(index + 1);
+            let error = |message: &str| PlanError::new(line_number, message);
+            let line = line.map_err(|e| PlanError::new(line_number, e.to_string()))?;
+            let mut parts = line.split_whitespace();
+            let indicator = match parts.next() {
+                Some(indicator) => indicator,
+                None => continue,
+            };
+            let time = parts.next().ok_or_else(|| error("missing time stamp"))?
+                .parse().map_err(|_| error("failed to parse time stamp"))?;
+            let values = parts.map(|x| x.parse()).collect::<Result<Vec<usize>, _>>()
+                .map_err(|_| error("failed to parse bin or worker"))?;
+            let step = match indicator {
+                "M" => PlanStep { time, map: Some(values), moves: Vec::new(), line: line_number },
+                "D" => {
+                    if values.len() % 2 != 0 {
+                        return Err(error("moves must be pairs of bin and worker"));
+                    }
+                    let moves = values.chunks(2).map(|x| (x[0], x[1])).collect();
+                    PlanStep { time, map: None, moves, line: line_number }
+                },
+                other => return Err(error(&format!("expected `M` or `D`, found `{}`", other))),
+            };
+            steps.push(step);
+        }
+        Ok(MigrationPlan { version: 0, bin_shift: None, peers: None, steps })
+    }
+
+    /// Check the plan against the number of bins and peers of a run. Time stamps must not
+    /// decrease, the first step must assign every bin and all bins and workers must exist.
+    pub fn validate(&self, peers: usize, bin_shift: usize) -> Result<(), PlanError> {
+        if let Some(plan_bin_shift) = self.bin_shift {
+            if plan_bin_shift != bin_shift {
+                return Err(PlanError::new(None, format!("plan is for bin shift {}, running with {}", plan_bin_shift, bin_shift)));
+            }
+        }
+        if let Some(plan_peers) = self.peers {
+            if plan_peers != peers {
+                return Err(PlanError::new(None, format!("plan is for {} peers, running with {}", plan_peers, peers)));
+            }
+        }
+        let bins = 1 << bin_shift;
+        let mut last_time = 0;
+        for (index, step) in self.steps.iter().enumerate() {
+            let error = |message: String| PlanError::new(step.line, format!("step {}: {}", index, message));
+            if step.time < last_time {
+                return Err(error(format!("time {} precedes previous time {}", step.time, last_time)));
+            }
+            last_time = step.time;
+            match step.map {
+                Some(ref map) => {
+                    if !step.moves.is_empty() {
+                        return Err(error("step has both a map and moves".to_string()));
+                    }
+                    if map.len() != bins {
+                        return Err(error(format!("map assigns {} bins, expected {}", map.len(), bins)));
+                    }
+                    if let Some(worker) = map.iter().find(|&&worker| worker >= peers) {
+                        return Err(error(format!("worker {} out of range, only {} peers", worker, peers)));
+                    }
+                },
+                None => {
+                    if index == 0 {
+                        return Err(error("first step must assign every bin with a map".to_string()));
+                    }
+                    if step.moves.is_empty() {
+                        return Err(error("step has neither a map nor moves".to_string()));
+                    }
+                    for &(bin, worker) in &step.moves {
+                        if bin >= bins {
+                            return Err(error(format!("bin {} out of range, only {} bins", bin, bins)));
+                        }
+                        if worker >= peers {
+                            return Err(error(format!("worker {} out of range, only {} peers", worker, peers)));
+                        }
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert the plan to timestamped control instructions.
+    pub fn instructions(&self) -> Vec<(u64, Vec<ControlInst>)> {
+        self.steps.iter().map(|step| {
+            let inst = match step.map {
+                Some(ref map) => vec![ControlInst::Map(map.clone())],
+                None => step.moves.iter().map(|&(bin, worker)| ControlInst::Move(BinId::new(bin), worker)).collect(),
+            };
+            (step.time, inst)
+        }).collect()
+    }
+}
+
+/// The lines of the objects in the top-level `steps` array of a well-formed JSON plan, in order.
+fn json_step_lines(json: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut line = 1;
+    let mut depth = 0;
+    // The last string at depth 1, which is the key of the value that follows it
+    let mut key = String::new();
+    let mut string = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut in_steps = false;
+    for c in json.chars() {
+        if c == '\n' {
+            line += 1;
+        }
+        if in_string {
+            if escaped {
+                escaped = false;
+                string.push(c);
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+                if depth == 1 {
+                    key = ::std::mem::replace(&mut string, String::new());
+                }
+            } else {
+                string.push(c);
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                string.clear();
+            },
+            '[' if depth == 1 && key == "steps" => {
+                in_steps = true;
+                depth += 1;
+            },
+            '{' if depth == 2 && in_steps => {
+                lines.push(line);
+                depth += 1;
+            },
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 1 {
+                    in_steps = false;
+                }
+            },
+            _ => {},
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_plan() {
+        let plan = MigrationPlan::from_legacy("M 0 0 1 0 1\n\nD 10 0 1 3 0\n".as_bytes()).unwrap();
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.validate(2, 2), Ok(()));
+        assert_eq!(plan.validate(2, 3).unwrap_err().line, Some(1));
+        assert_eq!(plan.validate(1, 2).unwrap_err().line, Some(1));
+
+        let error = MigrationPlan::from_legacy("M 0 0 1 0 1\nD x 0 1\n".as_bytes()).unwrap_err();
+        assert_eq!(error.line, Some(2));
+        let plan = MigrationPlan::from_legacy("M 10 0 1 0 1\nD 5 0 1\n".as_bytes()).unwrap();
+        assert_eq!(plan.validate(2, 2).unwrap_err().line, Some(2));
+        let plan = MigrationPlan::from_legacy("D 0 0 1\n".as_bytes()).unwrap();
+        assert!(plan.validate(2, 2).is_err());
+    }
+
+    #[test]
+    fn test_json_plan() {
+        let json = r#"{"version": 1, "bin_shift": 2, "steps": [{"time": 0, "map": [0, 1, 0, 1]}, {"time": 10, "moves": [[0, 1]]}]}"#;
+        let plan = MigrationPlan::from_json(json.as_bytes()).unwrap();
+        assert_eq!(plan.validate(2, 2), Ok(()));
+        assert!(plan.validate(2, 3).is_err());
+        let legacy = MigrationPlan::from_legacy("M 0 0 1 0 1\nD 10 0 1\n".as_bytes()).unwrap();
+        assert_eq!(plan.instructions().len(), legacy.instructions().len());
+
+        assert!(MigrationPlan::from_json(r#"{"version": 2, "steps": []}"#.as_bytes()).is_err());
+        let error = MigrationPlan::from_json("{\"version\": 1,\n\"steps\": [}".as_bytes()).unwrap_err();
+        assert_eq!(error.line, Some(2));
+
+        // Validation errors refer to the line of the offending step
+        let json = "{\"version\": 1, \"steps\": [\n  {\"time\": 0, \"map\": [0, 1, 0, 1]},\n  {\"time\": 10, \"moves\": [[4, 1]]}\n]}";
+        let plan = MigrationPlan::from_json(json.as_bytes()).unwrap();
+        assert_eq!(plan.steps.iter().map(|step| step.line).collect::<Vec<_>>(), vec![Some(2), Some(3)]);
+        assert_eq!(plan.validate(2, 2).unwrap_err().line, Some(3));
+    }
+}
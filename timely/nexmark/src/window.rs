@@ -0,0 +1,468 @@
+//! Windowed aggregation.
+//!
+//! [`Windowed`] aggregates streams of `(key, event_time, value)` triples per key and window. Event
+//! times are in the unit of the dataflow's timestamps. A [`WindowSpec`] determines the windows of
+//! each record, an [`Aggregate`] folds their values, and a [`LatePolicy`] decides what happens to
+//! records that arrive after their window's result was due.
+//!
+//! Records are processed in timestamp order once their time is complete. A window's result is
+//! produced at its end time, after all records with timestamps up to the end have been added. A
+//! record is late if its timestamp is beyond the end of one of its windows.
+//!
+//! `window` exchanges records by key, `local_window` aggregates on each worker, for example to
+//! pre-aggregate before a `window`, and `window_flex` keeps windows in Megaphone bins, such that
+//! they migrate with them. Each produces [`Windows`]: the results, and the records it discarded as
+//! late.
+//!
+//! [`Windowed`]: trait.Windowed.html
+//! [`WindowSpec`]: enum.WindowSpec.html
+//! [`Aggregate`]: trait.Aggregate.html
+//! [`LatePolicy`]: enum.LatePolicy.html
+//! [`Windows`]: struct.Windows.html
+
+use std::hash::Hash;
+
+use fnv::FnvHashMap as HashMap;
+
+use timely::{Data, ExchangeData};
+use timely::dataflow::{Scope, Stream};
+use timely::dataflow::channels::pact::{Exchange, ParallelizationContract, Pipeline};
+use timely::dataflow::channels::pushers::Tee;
+use timely::dataflow::operators::{Capability, Map, Operator};
+use timely::dataflow::operators::generic::OutputHandle;
+
+use dynamic_scaling_mechanism::{Bin, Control, StatefulConfig};
+use dynamic_scaling_mechanism::assigner::BinAssigner;
+use dynamic_scaling_mechanism::notificator::{Notify, TotalOrderFrontierNotificator};
+use dynamic_scaling_mechanism::operator::StatefulOperator;
+
+use {calculate_hash, partition_key, PartitionKey};
+
+/// Incrementally aggregates the values of a window.
+pub trait Aggregate<V>: Clone+Default+'static {
+    /// The result of the aggregation.
+    type Output: Data;
+    /// Add a value.
+    fn add(&mut self, value: &V);
+    /// Absorb another aggregate, as needed to merge session windows.
+    fn merge(&mut self, other: Self);
+    /// The result for the values added so far.
+    fn result(&self) -> Self::Output;
+}
+
+/// Counts values.
+#[derive(Abomonation, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Count(pub usize);
+
+impl<V> Aggregate<V> for Count {
+    type Output = usize;
+
+    fn add(&mut self, _value: &V) {
+        self.0 += 1;
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+
+    fn result(&self) -> usize {
+        self.0
+    }
+}
+
+/// Tracks the maximal value.
+#[derive(Abomonation, Clone, Debug, Eq, PartialEq)]
+pub struct Max<V>(pub Option<V>);
+
+impl<V> Default for Max<V> {
+    fn default() -> Self {
+        Max(None)
+    }
+}
+
+impl<V: Data+Ord> Aggregate<V> for Max<V> {
+    type Output = Option<V>;
+
+    fn add(&mut self, value: &V) {
+        if self.0.as_ref().map_or(true, |max| value > max) {
+            self.0 = Some(value.clone());
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        if let Some(value) = other.0 {
+            self.add(&value);
+        }
+    }
+
+    fn result(&self) -> Option<V> {
+        self.0.clone()
+    }
+}
+
+/// The bounds `start..end` of a window.
+#[derive(Abomonation, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Window {
+    /// The first time in the window.
+    pub start: usize,
+    /// The first time after the window, at which its result is produced.
+    pub end: usize,
+}
+
+/// Describes the windows a record belongs to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WindowSpec {
+    /// Consecutive windows of the given size.
+    Tumbling(usize),
+    /// Windows of `size` starting every `slide`. Windows of times before `size` are cut off at
+    /// zero.
+    Sliding {
+        /// The size of each window
+        size: usize,
+        /// The distance between window starts
+        slide: usize,
+    },
+    /// Windows per key of records less than the gap apart. A session ends a gap after its last
+    /// record.
+    Session(usize),
+}
+
+impl WindowSpec {
+    fn assert_valid(&self) {
+        match *self {
+            WindowSpec::Tumbling(size) => assert!(size > 0, "tumbling windows must not be empty"),
+            WindowSpec::Sliding { size, slide } => assert!(size > 0 && slide > 0, "sliding windows must not be empty and must advance"),
+            WindowSpec::Session(gap) => assert!(gap > 0, "session gap must be positive"),
+        }
+    }
+}
+
+/// Determines how to treat records that arrive after their window's result was produced.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LatePolicy {
+    /// Discard late records.
+    Drop,
+    /// Retain windows for the given time after their end. Late records within this time update
+    /// their window, and its updated result is produced again at the record's time. Later records
+    /// are discarded.
+    Update(usize),
+}
+
+impl LatePolicy {
+    fn allowed_lateness(&self) -> usize {
+        match *self {
+            LatePolicy::Drop => 0,
+            LatePolicy::Update(lateness) => lateness,
+        }
+    }
+}
+
+/// A window of a key and its aggregate.
+#[derive(Abomonation, Clone, Debug, Eq, PartialEq)]
+pub struct Pane<A> {
+    window: Window,
+    aggregate: A,
+    /// The time at which the window's result is due next, if any
+    fire_at: Option<usize>,
+}
+
+/// The windows of a set of keys.
+#[derive(Clone)]
+pub struct WindowState<K: Hash+Eq, A> {
+    panes: HashMap<K, Vec<Pane<A>>>,
+}
+
+impl<K: Hash+Eq, A> Default for WindowState<K, A> {
+    fn default() -> Self {
+        Self { panes: Default::default() }
+    }
+}
+
+impl<K: Hash+Eq+Clone, A> IntoIterator for WindowState<K, A> {
+    type Item = (K, Pane<A>);
+    type IntoIter = ::std::vec::IntoIter<(K, Pane<A>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut panes = Vec::new();
+        for (key, key_panes) in self.panes {
+            panes.extend(key_panes.into_iter().map(|pane| (key.clone(), pane)));
+        }
+        panes.into_iter()
+    }
+}
+
+impl<K: Hash+Eq, A> Extend<(K, Pane<A>)> for WindowState<K, A> {
+    fn extend<I: IntoIterator<Item=(K, Pane<A>)>>(&mut self, iter: I) {
+        for (key, pane) in iter {
+            self.panes.entry(key).or_insert_with(Vec::new).push(pane);
+        }
+    }
+}
+
+/// A record `(key, event_time, Some(value))`, or a request `(key, end, None)` to produce the result
+/// of the key's window ending at `end`.
+type Message<K, V> = (K, usize, Option<V>);
+
+/// A result `(key, window, result)`, or a record `(key, event_time, value)` discarded as late.
+type Emitted<K, V, O> = Result<(K, Window, O), (K, usize, V)>;
+
+impl<K: Hash+Eq+Clone, A> WindowState<K, A> {
+    /// Apply a message at `time`. `schedule(at, key, end)` requests the window of `key` ending at
+    /// `end` to be notified at `at`, `emit` receives results and late records.
+    fn apply<V, F, E>(&mut self, spec: WindowSpec, late: LatePolicy, time: usize, message: Message<K, V>, schedule: F, mut emit: E)
+        where
+            A: Aggregate<V>,
+            F: FnMut(usize, K, usize),
+            E: FnMut(Emitted<K, V, A::Output>),
+    {
+        match message {
+            (key, event_time, Some(value)) => {
+                if !self.insert(spec, late, time, key.clone(), event_time, &value, schedule) {
+                    emit(Err((key, event_time, value)));
+                }
+            },
+            (key, end, None) => self.notify::<V, _>(late, time, key, end, |key, window, result| emit(Ok((key, window, result)))),
+        }
+    }
+
+    /// Add a record to its windows, and return whether any window accepted it rather than all
+    /// having passed their allowed lateness.
+    fn insert<V, F>(&mut self, spec: WindowSpec, late: LatePolicy, time: usize, key: K, event_time: usize, value: &V, mut schedule: F) -> bool
+        where
+            A: Aggregate<V>,
+            F: FnMut(usize, K, usize),
+    {
+        let allowed_lateness = late.allowed_lateness();
+        let panes = self.panes.entry(key.clone()).or_insert_with(Vec::new);
+
+        let windows = match spec {
+            WindowSpec::Tumbling(size) => {
+                let start = event_time / size * size;
+                vec![Window { start, end: start + size }]
+            },
+            WindowSpec::Sliding { size, slide } => {
+                let mut end = (event_time / slide + 1) * slide;
+                let mut windows = Vec::new();
+                while end <= event_time + size {
+                    windows.push(Window { start: end.saturating_sub(size), end });
+                    end += slide;
+                }
+                windows
+            },
+            WindowSpec::Session(gap) => {
+                // Merge with all sessions that still accept updates and are less than `gap` apart.
+                // Sessions that produced their result are retained for the allowed lateness, and
+                // produce the merged result again.
+                let mut window = Window { start: event_time, end: event_time + gap };
+                let mut aggregate = A::default();
+                aggregate.add(value);
+                let mut index = 0;
+                while index < panes.len() {
+                    let merge = {
+                        let other = &panes[index].window;
+                        other.end + allowed_lateness >= time && other.start < window.end && window.start < other.end
+                    };
+                    if merge {
+                        let pane = panes.swap_remove(index);
+                        window.start = ::std::cmp::min(window.start, pane.window.start);
+                        window.end = ::std::cmp::max(window.end, pane.window.end);
+                        aggregate.merge(pane.aggregate);
+                    } else {
+                        index += 1;
+                    }
+                }
+                // The record is late only if its session merged with no session that still accepts updates
+                if time > window.end + allowed_lateness {
+                    return false;
+                }
+                let fire_at = ::std::cmp::max(window.end, time);
+                schedule(fire_at, key.clone(), window.end);
+                if fire_at < window.end + allowed_lateness {
+                    schedule(window.end + allowed_lateness, key, window.end);
+                }
+                panes.push(Pane { window, aggregate, fire_at: Some(fire_at) });
+                return true;
+            },
+        };
+
+        let mut accepted = false;
+        for window in windows {
+            if time > window.end + allowed_lateness {
+                continue;
+            }
+            accepted = true;
+            let position = match panes.iter().position(|pane| pane.window == window) {
+                Some(position) => position,
+                None => {
+                    if time < window.end + allowed_lateness && allowed_lateness > 0 {
+                        schedule(window.end + allowed_lateness, key.clone(), window.end);
+                    }
+                    panes.push(Pane { window, aggregate: A::default(), fire_at: None });
+                    panes.len() - 1
+                },
+            };
+            let pane = &mut panes[position];
+            pane.aggregate.add(value);
+            if pane.fire_at.is_none() {
+                let fire_at = ::std::cmp::max(window.end, time);
+                pane.fire_at = Some(fire_at);
+                schedule(fire_at, key.clone(), window.end);
+            }
+        }
+        accepted
+    }
+
+    fn notify<V, E>(&mut self, late: LatePolicy, time: usize, key: K, end: usize, mut emit: E)
+        where
+            A: Aggregate<V>,
+            E: FnMut(K, Window, A::Output),
+    {
+        let allowed_lateness = late.allowed_lateness();
+        let empty = match self.panes.get_mut(&key) {
+            Some(panes) => {
+                if let Some(pane) = panes.iter_mut().find(|pane| pane.window.end == end && pane.fire_at == Some(time)) {
+                    pane.fire_at = None;
+                    emit(key.clone(), pane.window, pane.aggregate.result());
+                }
+                panes.retain(|pane| pane.window.end != end || pane.fire_at.is_some() || time < end + allowed_lateness);
+                panes.is_empty()
+            },
+            None => false,
+        };
+        if empty {
+            self.panes.remove(&key);
+        }
+    }
+}
+
+/// Process a batch of messages available at `cap`, in time order with records before requests
+/// at the same time, and produce results and late records.
+fn process<K, V, A, F>(
+    state: &mut WindowState<K, A>,
+    spec: WindowSpec,
+    late: LatePolicy,
+    cap: &Capability<usize>,
+    messages: &mut Vec<(usize, Message<K, V>)>,
+    mut schedule: F,
+    output: &mut OutputHandle<usize, Emitted<K, V, A::Output>, Tee<usize, Emitted<K, V, A::Output>>>)
+    where
+        K: Data+Hash+Eq,
+        V: Data,
+        A: Aggregate<V>,
+        F: FnMut(usize, Message<K, V>),
+{
+    messages.sort_by_key(|&(time, ref message)| (time, message.2.is_none()));
+    let mut results = Vec::new();
+    for (time, message) in messages.drain(..) {
+        state.apply(spec, late, time, message, |at, key, end| schedule(at, (key, end, None)), |emitted| results.push((time, emitted)));
+    }
+    let mut results = results.into_iter().peekable();
+    while let Some((time, result)) = results.next() {
+        let delayed = cap.delayed(&time);
+        let mut session = output.session(&delayed);
+        session.give(result);
+        while results.peek().map_or(false, |&(next, _)| next == time) {
+            session.give(results.next().unwrap().1);
+        }
+    }
+}
+
+/// The results and late records of a window operator.
+pub struct Windows<S: Scope, K: Data, V: Data, O: Data> {
+    /// `(key, window, result)` at the end of each window, and again at each update by a late
+    /// record.
+    pub results: Stream<S, (K, Window, O)>,
+    /// The records `(key, event_time, value)` that arrived after the allowed lateness of all their
+    /// windows, at their arrival time.
+    pub late: Stream<S, (K, usize, V)>,
+}
+
+impl<S: Scope, K: Data, V: Data, O: Data> Windows<S, K, V, O> {
+    fn split(emitted: &Stream<S, Emitted<K, V, O>>) -> Self {
+        Windows {
+            results: emitted.flat_map(|emitted| emitted.ok()),
+            late: emitted.flat_map(|emitted| emitted.err()),
+        }
+    }
+}
+
+fn window_core<S, K, V, A, P>(stream: &Stream<S, (K, usize, V)>, pact: P, spec: WindowSpec, late: LatePolicy, name: &str) -> Windows<S, K, V, A::Output>
+    where
+        S: Scope<Timestamp=usize>,
+        K: Data+Hash+Eq,
+        V: Data,
+        A: Aggregate<V>,
+        P: ParallelizationContract<usize, (K, usize, V)>,
+{
+    spec.assert_valid();
+    let emitted = stream.unary_frontier(pact, name, move |_cap, _info| {
+        let mut notificator = TotalOrderFrontierNotificator::new();
+        let mut state: WindowState<K, A> = Default::default();
+        let mut input_buffer = Vec::new();
+        let mut messages = Vec::new();
+        let mut requests = Vec::new();
+        move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut input_buffer);
+                let cap = time.retain();
+                for (key, event_time, value) in input_buffer.drain(..) {
+                    notificator.notify_at_data(&cap, *cap.time(), (key, event_time, Some(value)));
+                }
+            });
+            while let Some(cap) = notificator.drain(&[input.frontier()], &mut messages) {
+                process(&mut state, spec, late, &cap, &mut messages, |at, request| requests.push((at, request)), output);
+                for (at, request) in requests.drain(..) {
+                    notificator.notify_at_data(&cap, at, request);
+                }
+            }
+        }
+    });
+    Windows::split(&emitted)
+}
+
+/// Aggregates keyed streams of `(key, event_time, value)` per window.
+pub trait Windowed<S: Scope<Timestamp=usize>, K: Data, V: Data> {
+    /// Aggregate each key's windows, exchanging records by key. Produces `(key, window, result)`
+    /// at the end of each window.
+    fn window<A: Aggregate<V>>(&self, spec: WindowSpec, late: LatePolicy, name: &str) -> Windows<S, K, V, A::Output>;
+
+    /// Aggregate each key's windows on the local worker, without exchanging records.
+    fn local_window<A: Aggregate<V>>(&self, spec: WindowSpec, late: LatePolicy, name: &str) -> Windows<S, K, V, A::Output>;
+
+    /// Aggregate each key's windows in Megaphone bins, which migrate as instructed by `control`.
+    /// Keys are assigned to bins by `config`'s bin assigner.
+    fn window_flex<A: Aggregate<V>+ExchangeData, B: BinAssigner>(&self, spec: WindowSpec, late: LatePolicy, control: &Stream<S, Control>, config: StatefulConfig<B>, name: &str) -> Windows<S, K, V, A::Output>
+        where K: PartitionKey;
+}
+
+impl<S, K, V> Windowed<S, K, V> for Stream<S, (K, usize, V)>
+    where
+        S: Scope<Timestamp=usize>,
+        K: ExchangeData+Hash+Eq,
+        V: ExchangeData+Eq,
+{
+    fn window<A: Aggregate<V>>(&self, spec: WindowSpec, late: LatePolicy, name: &str) -> Windows<S, K, V, A::Output> {
+        window_core::<_, _, _, A, _>(self, Exchange::new(|record: &(K, usize, V)| calculate_hash(&record.0)), spec, late, name)
+    }
+
+    fn local_window<A: Aggregate<V>>(&self, spec: WindowSpec, late: LatePolicy, name: &str) -> Windows<S, K, V, A::Output> {
+        window_core::<_, _, _, A, _>(self, Pipeline, spec, late, name)
+    }
+
+    fn window_flex<A: Aggregate<V>+ExchangeData, B: BinAssigner>(&self, spec: WindowSpec, late: LatePolicy, control: &Stream<S, Control>, config: StatefulConfig<B>, name: &str) -> Windows<S, K, V, A::Output>
+        where K: PartitionKey
+    {
+        spec.assert_valid();
+        let ordered = config.bin_assigner().ordered();
+        let emitted = self
+            .map(|(key, event_time, value)| (key, event_time, Some(value)))
+            .stateful_unary(control, config, move |message: &Message<K, V>| partition_key(&message.0, ordered), name, move |cap, messages, bin: &mut Bin<_, WindowState<K, A>, _>, output| {
+                let mut requests = Vec::new();
+                process(bin.state(), spec, late, cap, messages, |at, request| requests.push((at, request)), output);
+                for (at, request) in requests {
+                    bin.notificator().notify_at_data(cap, at, request);
+                }
+            });
+        Windows::split(&emitted)
+    }
+}
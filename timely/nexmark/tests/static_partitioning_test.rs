@@ -0,0 +1,160 @@
+//! Checks that the flex queries produce the same results whether Megaphone migrates bins or keeps
+//! the initial partitioning, such that static partitioning is a faithful baseline.
+
+extern crate timely;
+extern crate nexmark;
+extern crate dynamic_scaling_mechanism;
+
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use timely::Configuration;
+use timely::dataflow::{InputHandle, ProbeHandle};
+use timely::dataflow::operators::{Broadcast, Capture, Inspect, Probe};
+use timely::dataflow::operators::capture::event::link::EventLink;
+
+use dynamic_scaling_mechanism::{ControlInst, Control, StatefulConfig};
+use dynamic_scaling_mechanism::assigner::AnyAssigner;
+
+use nexmark::worker_share;
+use nexmark::source::EventSource;
+use nexmark::queries::{LateEvents, NexmarkInput, NexmarkTimer, QueryConfig};
+
+const EVENTS: usize = 20_000;
+const EVENTS_PER_SECOND: usize = 1_000;
+const BIN_SHIFT: usize = 4;
+
+/// Run `query` on a fixed input while migrating all bins to worker 1 and back, and return its
+/// outputs with their times, formatted and sorted.
+fn run(query: &'static str, static_partitioning: bool) -> Vec<(usize, String)> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let shared = results.clone();
+
+    timely::execute(Configuration::Process(2), move |worker| {
+        let index = worker.index();
+        let peers = worker.peers();
+
+        let stateful_config = StatefulConfig::new(BIN_SHIFT)
+            .with_static_partitioning(static_partitioning)
+            .with_bin_assigner(AnyAssigner::default());
+
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        let control = Rc::new(EventLink::new());
+        let bids = Rc::new(EventLink::new());
+        let auctions = Rc::new(EventLink::new());
+        let people = Rc::new(EventLink::new());
+        let closed_auctions = Rc::new(EventLink::new());
+        let closed_auctions_flex = Rc::new(EventLink::new());
+        let late_events = LateEvents::default();
+
+        let nexmark_input = NexmarkInput {
+            control: &control,
+            bids: &bids,
+            auctions: &auctions,
+            people: &people,
+            closed_auctions: &closed_auctions,
+            closed_auctions_flex: &closed_auctions_flex,
+            stateful_config,
+            config: QueryConfig::default(),
+            late_events: &late_events,
+        };
+        let nexmark_timer = NexmarkTimer { time_dilation: 1 };
+
+        worker.dataflow(|scope| {
+            let (bids_stream, auctions_stream, people_stream) = ::nexmark::source::demux(&input.to_stream(scope));
+            bids_stream.capture_into(bids.clone());
+            auctions_stream.capture_into(auctions.clone());
+            people_stream.capture_into(people.clone());
+            control_input.to_stream(scope).broadcast().capture_into(control.clone());
+        });
+
+        if query == "q4-flex" || query == "q6-flex" || query == "q9-flex" {
+            worker.dataflow(|scope| {
+                ::nexmark::queries::q4_q6_common_flex(&nexmark_input, nexmark_timer, scope)
+                    .capture_into(nexmark_input.closed_auctions_flex.clone());
+            });
+        }
+
+        worker.dataflow(|scope| {
+            let results = shared.clone();
+            macro_rules! collect {
+                ($stream:expr) => {
+                    $stream
+                        .inspect_batch(move |t, xs| results.lock().unwrap().extend(xs.iter().map(|x| (*t, format!("{:?}", x)))))
+                        .probe_with(&mut probe)
+                }
+            }
+            match query {
+                "q1-flex" => { collect!(::nexmark::queries::q1_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q2-flex" => { collect!(::nexmark::queries::q2_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q3-flex" => { collect!(::nexmark::queries::q3_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q4-flex" => { collect!(::nexmark::queries::q4_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q5-flex" => { collect!(::nexmark::queries::q5_flex(&nexmark_input, nexmark_timer, scope, 5, 2_000_000_000)); },
+                "q6-flex" => { collect!(::nexmark::queries::q6_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q7-flex" => { collect!(::nexmark::queries::q7_flex(&nexmark_input, nexmark_timer, scope, 10_000_000_000)); },
+                "q8-flex" => { collect!(::nexmark::queries::q8_flex(&nexmark_input, nexmark_timer, scope, 10_000_000_000)); },
+                "q9-flex" => { collect!(::nexmark::queries::q9_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q11-flex" => { collect!(::nexmark::queries::q11_flex(&nexmark_input, nexmark_timer, scope, 1_000_000_000)); },
+                "q15-flex" => { collect!(::nexmark::queries::q15_flex(&nexmark_input, nexmark_timer, scope, 2_000_000_000)); },
+                "q16-flex" => { collect!(::nexmark::queries::q16_flex(&nexmark_input, nexmark_timer, scope, 2_000_000_000)); },
+                "q17-flex" => { collect!(::nexmark::queries::q17_flex(&nexmark_input, nexmark_timer, scope, 2_000_000_000)); },
+                "q18-flex" => { collect!(::nexmark::queries::q18_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q19-flex" => { collect!(::nexmark::queries::q19_flex(&nexmark_input, nexmark_timer, scope)); },
+                "q20-flex" => { collect!(::nexmark::queries::q20_flex(&nexmark_input, nexmark_timer, scope)); },
+                other => panic!("unknown query {}", other),
+            }
+        });
+
+        let mut config = nexmark::config::Config::new();
+        config.insert("events-per-second", format!("{}", EVENTS_PER_SECOND));
+        config.insert("base-time", "0".to_string());
+        let config = nexmark::config::NEXMarkConfig::new(&config);
+
+        // Move all bins to worker 1 after a third of the events and back after two thirds
+        let duration_ns = EVENTS * 1_000_000_000 / EVENTS_PER_SECOND;
+        if index == 0 {
+            let bins = 1 << BIN_SHIFT;
+            control_input.advance_to(duration_ns / 3);
+            control_input.send(Control::new(0, 1, ControlInst::Map(vec![1; bins])));
+            control_input.advance_to(2 * duration_ns / 3);
+            control_input.send(Control::new(1, 1, ControlInst::Map((0..peers).cycle().take(bins).collect())));
+        }
+        control_input.close();
+
+        let events = EventSource::Generator(config).worker_events(index, peers).take(worker_share(EVENTS, index, peers));
+        for (count, event) in events.enumerate() {
+            let time = *event.time();
+            if *input.time() < time {
+                input.advance_to(time);
+            }
+            input.send(event);
+            if count % 100 == 0 {
+                while probe.less_than(input.time()) {
+                    worker.step();
+                }
+            }
+        }
+    }).unwrap();
+
+    let mut results = ::std::mem::replace(&mut *results.lock().unwrap(), Vec::new());
+    results.sort();
+    results
+}
+
+#[test]
+fn flex_queries_match_static_partitioning() {
+    // Q12 windows bids by processing time, which differs between runs
+    let queries = [
+        "q1-flex", "q2-flex", "q3-flex", "q4-flex", "q5-flex", "q6-flex", "q7-flex", "q8-flex",
+        "q9-flex", "q11-flex", "q15-flex", "q16-flex", "q17-flex", "q18-flex", "q19-flex", "q20-flex",
+    ];
+    for &query in queries.iter() {
+        let migrating = run(query, false);
+        let baseline = run(query, true);
+        assert_eq!(migrating.len(), baseline.len(), "{} produced {} results with migrations and {} without", query, migrating.len(), baseline.len());
+        assert!(migrating == baseline, "{} produced different results with migrations", query);
+    }
+}
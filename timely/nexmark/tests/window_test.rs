@@ -0,0 +1,180 @@
+//! Checks window assignment, session merging and late-data policies of the window operators, and
+//! that migrating windows does not change their results.
+
+extern crate timely;
+extern crate nexmark;
+extern crate dynamic_scaling_mechanism;
+
+use std::sync::{Arc, Mutex};
+
+use timely::Configuration;
+use timely::dataflow::{InputHandle, ProbeHandle};
+use timely::dataflow::operators::{Broadcast, Input, Inspect, Probe};
+
+use dynamic_scaling_mechanism::{ControlInst, Control, StatefulConfig};
+
+use nexmark::window::{Count, LatePolicy, Window, Windowed, WindowSpec};
+
+/// An input record at a time: key and event time.
+type Record = (usize, (u64, usize));
+
+/// A result at a time: key, window and count.
+type Output = (usize, u64, Window, usize);
+
+/// Count `records` per window on two workers, optionally in migrating bins, and return the sorted
+/// results.
+fn run(spec: WindowSpec, late: LatePolicy, flex: bool, records: Vec<Record>) -> Vec<Output> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let shared = results.clone();
+
+    timely::execute(Configuration::Process(2), move |worker| {
+        let index = worker.index();
+        let mut input = InputHandle::new();
+        let mut control_input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        worker.dataflow(|scope| {
+            let control = scope.input_from(&mut control_input).broadcast();
+            let records = scope.input_from(&mut input);
+            let windows = if flex {
+                records.window_flex::<Count, _>(spec, late, &control, StatefulConfig::new(2), "Windows").results
+            } else {
+                records.window::<Count>(spec, late, "Windows").results
+            };
+            let results = shared.clone();
+            windows
+                .inspect_batch(move |t, xs| {
+                    results.lock().unwrap().extend(xs.iter().map(|&(key, window, count)| (*t, key, window, count)));
+                })
+                .probe_with(&mut probe);
+        });
+
+        if index == 0 {
+            control_input.advance_to(5);
+            control_input.send(Control::new(0, 1, ControlInst::Map(vec![1; 4])));
+            for &(time, (key, event_time)) in records.iter() {
+                input.advance_to(time);
+                input.send((key, event_time, ()));
+            }
+        }
+        control_input.close();
+        input.close();
+        while worker.step() { }
+    }).unwrap();
+
+    let mut results = ::std::mem::replace(&mut *results.lock().unwrap(), Vec::new());
+    results.sort();
+    results
+}
+
+fn window(start: usize, end: usize) -> Window {
+    Window { start, end }
+}
+
+#[test]
+fn tumbling_windows() {
+    let records = vec![(1, (1, 1)), (3, (2, 3)), (5, (1, 5)), (12, (1, 12))];
+    let expected = vec![
+        (10, 1, window(0, 10), 2),
+        (10, 2, window(0, 10), 1),
+        (20, 1, window(10, 20), 1),
+    ];
+    assert_eq!(run(WindowSpec::Tumbling(10), LatePolicy::Drop, false, records.clone()), expected);
+    assert_eq!(run(WindowSpec::Tumbling(10), LatePolicy::Drop, true, records), expected);
+}
+
+#[test]
+fn sliding_windows() {
+    let records = vec![(2, (1, 2)), (7, (1, 7))];
+    let expected = vec![
+        (5, 1, window(0, 5), 1),
+        (10, 1, window(0, 10), 2),
+        (15, 1, window(5, 15), 1),
+    ];
+    let spec = WindowSpec::Sliding { size: 10, slide: 5 };
+    assert_eq!(run(spec, LatePolicy::Drop, false, records.clone()), expected);
+    assert_eq!(run(spec, LatePolicy::Drop, true, records), expected);
+}
+
+#[test]
+fn session_windows() {
+    // The third record bridges the first two sessions
+    let records = vec![(1, (1, 1)), (4, (1, 8)), (5, (1, 5)), (20, (1, 20))];
+    let expected = vec![
+        (13, 1, window(1, 13), 3),
+        (25, 1, window(20, 25), 1),
+    ];
+    assert_eq!(run(WindowSpec::Session(5), LatePolicy::Drop, false, records.clone()), expected);
+    assert_eq!(run(WindowSpec::Session(5), LatePolicy::Drop, true, records), expected);
+}
+
+#[test]
+fn late_records() {
+    // The second record belongs to the first window but arrives after its end, the third arrives
+    // after the allowed lateness
+    let records = vec![(1, (1, 1)), (15, (1, 3)), (30, (1, 4))];
+    assert_eq!(run(WindowSpec::Tumbling(10), LatePolicy::Drop, false, records.clone()), vec![
+        (10, 1, window(0, 10), 1),
+    ]);
+    let expected = vec![
+        (10, 1, window(0, 10), 1),
+        (15, 1, window(0, 10), 2),
+    ];
+    assert_eq!(run(WindowSpec::Tumbling(10), LatePolicy::Update(10), false, records.clone()), expected);
+    assert_eq!(run(WindowSpec::Tumbling(10), LatePolicy::Update(10), true, records), expected);
+}
+
+/// Count `records` per window on one worker, and return the records the operator discards as late.
+fn late(spec: WindowSpec, late: LatePolicy, records: Vec<Record>) -> Vec<(usize, u64, usize)> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let shared = results.clone();
+
+    timely::execute(Configuration::Process(1), move |worker| {
+        let mut input = InputHandle::new();
+        worker.dataflow(|scope| {
+            let results = shared.clone();
+            scope.input_from(&mut input)
+                .window::<Count>(spec, late, "Windows")
+                .late
+                .inspect_batch(move |t, xs| results.lock().unwrap().extend(xs.iter().map(|&(key, event_time, ())| (*t, key, event_time))));
+        });
+        for &(time, (key, event_time)) in records.iter() {
+            input.advance_to(time);
+            input.send((key, event_time, ()));
+        }
+    }).unwrap();
+
+    let late = ::std::mem::replace(&mut *results.lock().unwrap(), Vec::new());
+    late
+}
+
+#[test]
+fn late_records_side_output() {
+    // Same records as above: the second is within the allowed lateness, the third is not
+    let records = vec![(1, (1, 1)), (15, (1, 3)), (30, (1, 4))];
+    assert_eq!(late(WindowSpec::Tumbling(10), LatePolicy::Update(10), records), vec![(30, 1, 4)]);
+}
+
+#[test]
+fn late_records_merged_into_open_sessions() {
+    // The second record's own session has ended, but it extends the session of the first, which
+    // is still open. The third record's session has ended and it merges with none.
+    let records = vec![(1, (1, 8)), (12, (1, 4)), (30, (1, 20))];
+    assert_eq!(late(WindowSpec::Session(5), LatePolicy::Drop, records.clone()), vec![(30, 1, 20)]);
+    assert_eq!(run(WindowSpec::Session(5), LatePolicy::Drop, false, records), vec![
+        (13, 1, window(4, 13), 2),
+    ]);
+}
+
+#[test]
+fn late_records_update_sessions() {
+    // The second record's own session has ended, but it extends the session of the first, which
+    // has produced its result and is retained for updates
+    let records = vec![(1, (1, 1)), (8, (1, 3))];
+    let expected = vec![
+        (6, 1, window(1, 6), 1),
+        (8, 1, window(1, 8), 2),
+    ];
+    assert_eq!(run(WindowSpec::Session(5), LatePolicy::Update(10), false, records.clone()), expected);
+    assert_eq!(run(WindowSpec::Session(5), LatePolicy::Update(10), true, records), expected);
+}